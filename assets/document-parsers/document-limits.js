@@ -15,11 +15,14 @@ const REQUIRED_DOCX_ENTRIES = new Set([
   "_rels/.rels",
   "word/document.xml"
 ]);
+const REQUIRED_EPUB_ENTRIES = new Set(["mimetype", "META-INF/container.xml"]);
+const EPUB_MIMETYPE = "application/epub+zip";
 
 export const DOCUMENT_LIMITS = Object.freeze({
   maxArchiveBytes: 50 * 1024 * 1024,
   maxPdfPages: 2_000,
   maxPdfTextCharacters: 10_000_000,
+  maxEpubTextCharacters: 10_000_000,
   maxEntryCount: 2048,
   maxEntryNameBytes: 1024,
   maxEntryUncompressedBytes: 50 * 1024 * 1024,
@@ -53,6 +56,33 @@ export function addPdfTextCharacters(
   currentCharacters,
   additionalCharacters,
   limits = DOCUMENT_LIMITS
+) {
+  return addTextCharacters(
+    "PDF",
+    currentCharacters,
+    additionalCharacters,
+    limits.maxPdfTextCharacters
+  );
+}
+
+export function addEpubTextCharacters(
+  currentCharacters,
+  additionalCharacters,
+  limits = DOCUMENT_LIMITS
+) {
+  return addTextCharacters(
+    "EPUB",
+    currentCharacters,
+    additionalCharacters,
+    limits.maxEpubTextCharacters
+  );
+}
+
+function addTextCharacters(
+  label,
+  currentCharacters,
+  additionalCharacters,
+  maxCharacters
 ) {
   const totalCharacters = currentCharacters + additionalCharacters;
   if (
@@ -60,16 +90,16 @@ export function addPdfTextCharacters(
     !Number.isSafeInteger(additionalCharacters) ||
     currentCharacters < 0 ||
     additionalCharacters < 0 ||
-    totalCharacters > limits.maxPdfTextCharacters
+    totalCharacters > maxCharacters
   ) {
     throw new DocumentLimitError(
-      "PDF exceeds the extracted text limit"
+      `${label} exceeds the extracted text limit`
     );
   }
   return totalCharacters;
 }
 
-function asBytes(data) {
+function asBytes(data, label) {
   if (data instanceof Uint8Array) {
     return data;
   }
@@ -79,10 +109,10 @@ function asBytes(data) {
   if (ArrayBuffer.isView(data)) {
     return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
   }
-  throw new DocumentLimitError("DOCX archive must be binary data");
+  throw new DocumentLimitError(`${label} archive must be binary data`);
 }
 
-function findEndOfCentralDirectory(bytes, view) {
+function findEndOfCentralDirectory(bytes, view, label) {
   const minimumOffset = Math.max(
     0,
     bytes.byteLength -
@@ -108,10 +138,12 @@ function findEndOfCentralDirectory(bytes, view) {
     }
   }
 
-  throw new DocumentLimitError("DOCX archive has no valid central directory");
+  throw new DocumentLimitError(
+    `${label} archive has no valid central directory`
+  );
 }
 
-function decodeEntryName(bytes, flags) {
+function decodeEntryName(bytes, flags, label) {
   const decoder = new TextDecoder("utf-8", {
     fatal: (flags & UTF8_FLAG) !== 0
   });
@@ -119,11 +151,11 @@ function decodeEntryName(bytes, flags) {
   try {
     return decoder.decode(bytes);
   } catch {
-    throw new DocumentLimitError("DOCX archive has an invalid entry name");
+    throw new DocumentLimitError(`${label} archive has an invalid entry name`);
   }
 }
 
-function validateEntryName(name) {
+function validateEntryName(name, label) {
   const segments = name.split("/");
   if (
     !name ||
@@ -134,7 +166,7 @@ function validateEntryName(name) {
     segments.some((segment) => segment === "." || segment === "..")
   ) {
     throw new DocumentLimitError(
-      `DOCX archive has an unsafe entry name: ${name || "<empty>"}`
+      `${label} archive has an unsafe entry name: ${name || "<empty>"}`
     );
   }
 }
@@ -151,12 +183,12 @@ function assertRange(start, length, upperBound, message) {
   }
 }
 
-function inspectLocalEntry(bytes, view, centralOffset, entry) {
+function inspectLocalEntry(bytes, view, centralOffset, entry, label) {
   assertRange(
     entry.localHeaderOffset,
     LOCAL_FILE_HEADER_BYTES,
     centralOffset,
-    `DOCX entry ${entry.name} has an invalid local header`
+    `${label} entry ${entry.name} has an invalid local header`
   );
 
   if (
@@ -164,7 +196,7 @@ function inspectLocalEntry(bytes, view, centralOffset, entry) {
     LOCAL_FILE_HEADER_SIGNATURE
   ) {
     throw new DocumentLimitError(
-      `DOCX entry ${entry.name} has an invalid local header`
+      `${label} entry ${entry.name} has an invalid local header`
     );
   }
 
@@ -182,12 +214,13 @@ function inspectLocalEntry(bytes, view, centralOffset, entry) {
     localNameOffset,
     localNameLength + localExtraLength + entry.compressedSize,
     centralOffset,
-    `DOCX entry ${entry.name} extends outside the archive payload`
+    `${label} entry ${entry.name} extends outside the archive payload`
   );
 
   const localName = decodeEntryName(
     bytes.subarray(localNameOffset, localNameOffset + localNameLength),
-    localFlags
+    localFlags,
+    label
   );
   if (
     localName !== entry.name ||
@@ -195,7 +228,7 @@ function inspectLocalEntry(bytes, view, centralOffset, entry) {
     localCompressionMethod !== entry.compressionMethod
   ) {
     throw new DocumentLimitError(
-      `DOCX entry ${entry.name} has inconsistent headers`
+      `${label} entry ${entry.name} has inconsistent headers`
     );
   }
 
@@ -206,17 +239,19 @@ function inspectLocalEntry(bytes, view, centralOffset, entry) {
   };
 }
 
-export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
-  const bytes = asBytes(data);
+function inspectZipArchive(data, label, requiredEntries, limits) {
+  const bytes = asBytes(data, label);
   if (bytes.byteLength > limits.maxArchiveBytes) {
-    throw new DocumentLimitError("DOCX archive exceeds the compressed size limit");
+    throw new DocumentLimitError(
+      `${label} archive exceeds the compressed size limit`
+    );
   }
   if (bytes.byteLength < END_OF_CENTRAL_DIRECTORY_BYTES) {
-    throw new DocumentLimitError("DOCX archive is truncated");
+    throw new DocumentLimitError(`${label} archive is truncated`);
   }
 
   const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
-  const endOffset = findEndOfCentralDirectory(bytes, view);
+  const endOffset = findEndOfCentralDirectory(bytes, view, label);
   const diskNumber = view.getUint16(endOffset + 4, true);
   const centralDirectoryDisk = view.getUint16(endOffset + 6, true);
   const entriesOnDisk = view.getUint16(endOffset + 8, true);
@@ -229,23 +264,23 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
     centralDirectoryDisk !== 0 ||
     entriesOnDisk !== entryCount
   ) {
-    throw new DocumentLimitError("Multi-volume DOCX archives are not supported");
+    throw new DocumentLimitError(`Multi-volume ${label} archives are not supported`);
   }
   if (
     entryCount === ZIP64_SENTINEL_16 ||
     centralDirectorySize === ZIP64_SENTINEL_32 ||
     centralDirectoryOffset === ZIP64_SENTINEL_32
   ) {
-    throw new DocumentLimitError("ZIP64 DOCX archives are not supported");
+    throw new DocumentLimitError(`ZIP64 ${label} archives are not supported`);
   }
   if (entryCount === 0 || entryCount > limits.maxEntryCount) {
     throw new DocumentLimitError(
-      `DOCX archive exceeds the ${limits.maxEntryCount}-entry limit`
+      `${label} archive exceeds the ${limits.maxEntryCount}-entry limit`
     );
   }
   if (centralDirectoryOffset + centralDirectorySize !== endOffset) {
     throw new DocumentLimitError(
-      "DOCX archive has an invalid central directory range"
+      `${label} archive has an invalid central directory range`
     );
   }
 
@@ -258,10 +293,10 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
       offset,
       CENTRAL_DIRECTORY_HEADER_BYTES,
       endOffset,
-      "DOCX central directory is truncated"
+      `${label} central directory is truncated`
     );
     if (view.getUint32(offset, true) !== CENTRAL_DIRECTORY_SIGNATURE) {
-      throw new DocumentLimitError("DOCX central directory is malformed");
+      throw new DocumentLimitError(`${label} central directory is malformed`);
     }
 
     const flags = view.getUint16(offset + 8, true);
@@ -283,7 +318,7 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
       offset,
       entryLength,
       endOffset,
-      "DOCX central directory entry is truncated"
+      `${label} central directory entry is truncated`
     );
     if (
       compressedSize === ZIP64_SENTINEL_32 ||
@@ -291,35 +326,42 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
       localHeaderOffset === ZIP64_SENTINEL_32 ||
       diskStart === ZIP64_SENTINEL_16
     ) {
-      throw new DocumentLimitError("ZIP64 DOCX entries are not supported");
+      throw new DocumentLimitError(`ZIP64 ${label} entries are not supported`);
     }
     if (diskStart !== 0) {
-      throw new DocumentLimitError("Multi-volume DOCX entries are not supported");
+      throw new DocumentLimitError(`Multi-volume ${label} entries are not supported`);
     }
     if ((flags & ENCRYPTED_FLAG) !== 0) {
-      throw new DocumentLimitError("Encrypted DOCX entries are not supported");
+      throw new DocumentLimitError(
+        `Encrypted ${label} entries are not supported`
+      );
     }
     if (!SUPPORTED_COMPRESSION_METHODS.has(compressionMethod)) {
       throw new DocumentLimitError(
-        `DOCX entry uses unsupported compression method ${compressionMethod}`
+        `${label} entry uses unsupported compression method ${compressionMethod}`
       );
     }
     if (nameLength === 0 || nameLength > limits.maxEntryNameBytes) {
-      throw new DocumentLimitError("DOCX entry name exceeds the allowed size");
+      throw new DocumentLimitError(
+        `${label} entry name exceeds the allowed size`
+      );
     }
 
     const nameOffset = offset + CENTRAL_DIRECTORY_HEADER_BYTES;
     const name = decodeEntryName(
       bytes.subarray(nameOffset, nameOffset + nameLength),
-      flags
+      flags,
+      label
     );
-    validateEntryName(name);
+    validateEntryName(name, label);
     if (entries.has(name)) {
-      throw new DocumentLimitError(`DOCX archive has duplicate entry ${name}`);
+      throw new DocumentLimitError(
+        `${label} archive has duplicate entry ${name}`
+      );
     }
     if (uncompressedSize > limits.maxEntryUncompressedBytes) {
       throw new DocumentLimitError(
-        `DOCX entry ${name} exceeds the uncompressed size limit`
+        `${label} entry ${name} exceeds the uncompressed size limit`
       );
     }
     if (
@@ -328,14 +370,14 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
         uncompressedSize / compressedSize > limits.maxCompressionRatio)
     ) {
       throw new DocumentLimitError(
-        `DOCX entry ${name} exceeds the compression ratio limit`
+        `${label} entry ${name} exceeds the compression ratio limit`
       );
     }
 
     totalUncompressedBytes += uncompressedSize;
     if (totalUncompressedBytes > limits.maxTotalUncompressedBytes) {
       throw new DocumentLimitError(
-        "DOCX archive exceeds the total uncompressed size limit"
+        `${label} archive exceeds the total uncompressed size limit`
       );
     }
 
@@ -351,32 +393,27 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
   }
 
   if (offset !== endOffset) {
-    throw new DocumentLimitError("DOCX central directory size is inconsistent");
+    throw new DocumentLimitError(
+      `${label} central directory size is inconsistent`
+    );
   }
-  for (const requiredEntry of REQUIRED_DOCX_ENTRIES) {
+  for (const requiredEntry of requiredEntries) {
     if (!entries.has(requiredEntry)) {
       throw new DocumentLimitError(
-        `DOCX archive is missing required entry ${requiredEntry}`
+        `${label} archive is missing required entry ${requiredEntry}`
       );
     }
   }
 
-  const documentXml = entries.get("word/document.xml");
-  if (documentXml.uncompressedSize > limits.maxDocumentXmlBytes) {
-    throw new DocumentLimitError(
-      "DOCX document XML exceeds the uncompressed size limit"
-    );
-  }
-
   const payloadRanges = [...entries.values()]
     .map((entry) =>
-      inspectLocalEntry(bytes, view, centralDirectoryOffset, entry)
+      inspectLocalEntry(bytes, view, centralDirectoryOffset, entry, label)
     )
     .sort((left, right) => left.start - right.start);
   for (let index = 1; index < payloadRanges.length; index += 1) {
     if (payloadRanges[index].start < payloadRanges[index - 1].end) {
       throw new DocumentLimitError(
-        `DOCX entries ${payloadRanges[index - 1].name} and ${payloadRanges[index].name} overlap`
+        `${label} entries ${payloadRanges[index - 1].name} and ${payloadRanges[index].name} overlap`
       );
     }
   }
@@ -384,14 +421,60 @@ export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
   return {
     entryCount,
     totalUncompressedBytes,
-    documentXml
+    entries
+  };
+}
+
+export function inspectDocxArchive(data, limits = DOCUMENT_LIMITS) {
+  const inspection = inspectZipArchive(
+    data,
+    "DOCX",
+    REQUIRED_DOCX_ENTRIES,
+    limits
+  );
+  const documentXml = inspection.entries.get("word/document.xml");
+  if (documentXml.uncompressedSize > limits.maxDocumentXmlBytes) {
+    throw new DocumentLimitError(
+      "DOCX document XML exceeds the uncompressed size limit"
+    );
+  }
+
+  return {
+    entryCount: inspection.entryCount,
+    totalUncompressedBytes: inspection.totalUncompressedBytes,
+    documentXml,
+    coreXml: inspection.entries.get("docProps/core.xml") ?? null
   };
 }
 
+export function inspectEpubArchive(data, limits = DOCUMENT_LIMITS) {
+  const inspection = inspectZipArchive(
+    data,
+    "EPUB",
+    REQUIRED_EPUB_ENTRIES,
+    limits
+  );
+  const mimetype = inspection.entries.get("mimetype");
+  if (
+    mimetype.compressionMethod !== 0 ||
+    mimetype.uncompressedSize !== EPUB_MIMETYPE.length
+  ) {
+    throw new DocumentLimitError("EPUB archive has an invalid mimetype entry");
+  }
+  return inspection;
+}
+
+export function assertEpubMimetype(text) {
+  if (text !== EPUB_MIMETYPE) {
+    throw new DocumentLimitError("EPUB archive has an invalid mimetype entry");
+  }
+}
+
 export function readZipEntryBounded(
   entry,
   maxBytes,
-  expectedBytes = undefined
+  expectedBytes = undefined,
+  label = "DOCX document XML"
 ) {
   return new Promise((resolve, reject) => {
     const chunks = [];
@@ -417,7 +500,7 @@ export function readZipEntryBounded(
         if (totalBytes > maxBytes) {
           fail(
             new DocumentLimitError(
-              "DOCX document XML exceeded its runtime inflation limit"
+              `${label} exceeded its runtime inflation limit`
             )
           );
           return;
@@ -434,7 +517,7 @@ export function readZipEntryBounded(
         if (expectedBytes !== undefined && totalBytes !== expectedBytes) {
           fail(
             new DocumentLimitError(
-              "DOCX document XML size does not match its archive header"
+              `${label} size does not match its archive header`
             )
           );
           return;
@@ -471,65 +554,88 @@ function findMarkupEnd(xml, startOffset) {
   return -1;
 }
 
-function findRequiredTerminator(xml, startOffset, terminator) {
+function findRequiredTerminator(xml, startOffset, terminator, label) {
   const endOffset = xml.indexOf(terminator, startOffset);
   if (endOffset === -1) {
-    throw new DocumentLimitError("DOCX document XML is malformed");
+    throw new DocumentLimitError(`${label} is malformed`);
   }
   return endOffset + terminator.length;
 }
 
-export function validateDocumentXml(xml, limits = DOCUMENT_LIMITS) {
+export function validateDocumentXml(
+  xml,
+  limits = DOCUMENT_LIMITS,
+  { label = "DOCX document XML", allowDoctype = false } = {}
+) {
   let elementCount = 0;
   let depth = 0;
   let offset = 0;
 
   while ((offset = xml.indexOf("<", offset)) !== -1) {
     if (xml.startsWith("<!--", offset)) {
-      offset = findRequiredTerminator(xml, offset + 4, "-->");
+      offset = findRequiredTerminator(xml, offset + 4, "-->", label);
       continue;
     }
     if (xml.startsWith("<![CDATA[", offset)) {
-      offset = findRequiredTerminator(xml, offset + 9, "]]>");
+      offset = findRequiredTerminator(xml, offset + 9, "]]>", label);
       continue;
     }
     if (xml.startsWith("<?", offset)) {
-      offset = findRequiredTerminator(xml, offset + 2, "?>");
+      offset = findRequiredTerminator(xml, offset + 2, "?>", label);
+      continue;
+    }
+    if (
+      allowDoctype &&
+      depth === 0 &&
+      elementCount === 0 &&
+      xml.startsWith("<!DOCTYPE", offset)
+    ) {
+      const endOffset = findMarkupEnd(xml, offset + 9);
+      if (endOffset === -1) {
+        throw new DocumentLimitError(`${label} is malformed`);
+      }
+      if (xml.slice(offset, endOffset).includes("[")) {
+        throw new DocumentLimitError(
+          `${label} declarations and entities are not allowed`
+        );
+      }
+      allowDoctype = false;
+      offset = endOffset + 1;
       continue;
     }
     if (xml.startsWith("<!", offset)) {
       throw new DocumentLimitError(
-        "DOCX document XML declarations and entities are not allowed"
+        `${label} declarations and entities are not allowed`
       );
     }
 
     const endOffset = findMarkupEnd(xml, offset + 1);
     if (endOffset === -1) {
-      throw new DocumentLimitError("DOCX document XML is malformed");
+      throw new DocumentLimitError(`${label} is malformed`);
     }
 
     const markup = xml.slice(offset + 1, endOffset).trim();
     if (markup.startsWith("/")) {
       depth -= 1;
       if (depth < 0) {
-        throw new DocumentLimitError("DOCX document XML is malformed");
+        throw new DocumentLimitError(`${label} is malformed`);
       }
     } else {
       const name = markup.match(/^([A-Za-z_][\w:.-]*)/u);
       if (!name) {
-        throw new DocumentLimitError("DOCX document XML is malformed");
+        throw new DocumentLimitError(`${label} is malformed`);
       }
       elementCount += 1;
       if (elementCount > limits.maxXmlElements) {
         throw new DocumentLimitError(
-          "DOCX document XML exceeds the element limit"
+          `${label} exceeds the element limit`
         );
       }
       if (!markup.endsWith("/")) {
         depth += 1;
         if (depth > limits.maxXmlDepth) {
           throw new DocumentLimitError(
-            "DOCX document XML exceeds the nesting depth limit"
+            `${label} exceeds the nesting depth limit`
           );
         }
       }
@@ -538,6 +644,6 @@ export function validateDocumentXml(xml, limits = DOCUMENT_LIMITS) {
   }
 
   if (depth !== 0) {
-    throw new DocumentLimitError("DOCX document XML is malformed");
+    throw new DocumentLimitError(`${label} is malformed`);
   }
 }
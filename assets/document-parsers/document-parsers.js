@@ -1,9 +1,12 @@
 import * as pdfjsLib from "./pdf.min.mjs";
 import {
+  addEpubTextCharacters,
   addPdfTextCharacters,
+  assertEpubMimetype,
   assertPdfPageCount,
   DOCUMENT_LIMITS,
   inspectDocxArchive,
+  inspectEpubArchive,
   readZipEntryBounded,
   validateDocumentXml
 } from "./document-limits.js";
@@ -15,6 +18,76 @@ pdfjsLib.GlobalWorkerOptions.workerSrc = new URL(
   import.meta.url
 ).toString();
 
+const MAX_PDF_OUTLINE_ENTRIES = 2_000;
+
+// Flattens the PDF outline into `{ title, page }` entries in document order,
+// where `page` is zero-based. Entries whose destination cannot be resolved are
+// skipped.
+async function extractPdfOutline(pdf) {
+  const outline = (await pdf.getOutline()) ?? [];
+  const entries = [];
+  const pending = [...outline];
+
+  while (pending.length > 0 && entries.length < MAX_PDF_OUTLINE_ENTRIES) {
+    const item = pending.shift();
+    pending.unshift(...(item.items ?? []));
+
+    const title = collapseWhitespace(item.title);
+    if (!title) {
+      continue;
+    }
+    try {
+      const dest =
+        typeof item.dest === "string"
+          ? await pdf.getDestination(item.dest)
+          : item.dest;
+      const target = Array.isArray(dest) ? dest[0] : null;
+      const page =
+        typeof target === "number" ? target : target ? await pdf.getPageIndex(target) : null;
+      if (Number.isInteger(page)) {
+        entries.push({ title, page });
+      }
+    } catch {
+      // Broken outline destinations do not prevent reading the text.
+    }
+  }
+
+  return entries;
+}
+
+function textOrNull(value) {
+  return typeof value === "string" ? collapseWhitespace(value) || null : null;
+}
+
+// Title, author, language, and date from the PDF Info dictionary and its XMP
+// packet. The Rust side chooses between them. XMP values arrive as lists.
+async function extractPdfMetadata(pdf) {
+  try {
+    const { info, metadata } = await pdf.getMetadata();
+    const xmp = (name) => {
+      const value = metadata?.get(name);
+      return Array.isArray(value) ? value.map(textOrNull).filter(Boolean) : [];
+    };
+    return {
+      info: {
+        title: textOrNull(info?.Title),
+        author: textOrNull(info?.Author),
+        language: textOrNull(info?.Language),
+        published: textOrNull(info?.CreationDate)
+      },
+      xmp: {
+        title: xmp("dc:title")[0] ?? null,
+        author: xmp("dc:creator").join(", ") || null,
+        language: xmp("dc:language")[0] ?? null,
+        published: xmp("dc:date")[0] ?? xmp("xmp:createdate")[0] ?? null
+      }
+    };
+  } catch {
+    // Unreadable metadata falls back to the file name.
+    return {};
+  }
+}
+
 globalThis.extractPdfText = async function extractPdfText(arrayBuffer) {
   const loadingTask = pdfjsLib.getDocument({
     data: arrayBuffer,
@@ -35,29 +108,36 @@ globalThis.extractPdfText = async function extractPdfText(arrayBuffer) {
       const page = await pdf.getPage(pageNumber);
       try {
         const textContent = await page.getTextContent();
-        const pageParts = [];
+        // Lines are kept so the Rust side can find running headers, page
+        // numbers, and paragraphs broken across lines.
+        let pageText = "";
         for (const item of textContent.items) {
           if (typeof item.str !== "string") {
             continue;
           }
-          const separatorCharacters = pageParts.length === 0 ? 0 : 1;
+          const separator = pageText === "" || pageText.endsWith("\n") ? "" : " ";
+          const text = separator + item.str + (item.hasEOL ? "\n" : "");
           extractedCharacters = addPdfTextCharacters(
             extractedCharacters,
-            item.str.length + separatorCharacters
+            text.length
           );
-          pageParts.push(item.str);
+          pageText += text;
         }
 
         if (pages.length > 0) {
           extractedCharacters = addPdfTextCharacters(extractedCharacters, 1);
         }
-        pages.push(pageParts.join(" "));
+        pages.push(pageText);
       } finally {
         page.cleanup();
       }
     }
 
-    return pages.join("\n").trim();
+    return JSON.stringify({
+      pages,
+      outline: await extractPdfOutline(pdf),
+      ...(await extractPdfMetadata(pdf))
+    });
   } catch (error) {
     const message = error instanceof Error ? error.message : String(error);
     throw new Error(`PDF parsing failed: ${message}`);
@@ -70,6 +150,45 @@ globalThis.extractPdfText = async function extractPdfText(arrayBuffer) {
   }
 };
 
+const DOCX_HEADING_STYLE = /^(?:heading\s*\d|title|subtitle)$/iu;
+
+function isDocxHeading(paragraph) {
+  const style = paragraph.getElementsByTagName("w:pStyle")[0];
+  return DOCX_HEADING_STYLE.test(style?.getAttribute("w:val") ?? "");
+}
+
+// Title, author, language, and creation date from docProps/core.xml, which is
+// optional. A missing or unreadable part leaves them empty.
+async function extractDocxMetadata(zip, inspection) {
+  const entry = zip.file("docProps/core.xml");
+  if (!inspection.coreXml || !entry || entry.dir) {
+    return {};
+  }
+  try {
+    const label = "DOCX core properties";
+    const bytes = await readZipEntryBounded(
+      entry,
+      DOCUMENT_LIMITS.maxDocumentXmlBytes,
+      inspection.coreXml.uncompressedSize,
+      label
+    );
+    const xml = new TextDecoder("utf-8", { fatal: true }).decode(bytes);
+    validateDocumentXml(xml, DOCUMENT_LIMITS, { label });
+    const core = new DOMParser().parseFromString(xml, "application/xml");
+    if (core.querySelector("parsererror")) {
+      return {};
+    }
+    return {
+      title: firstElementText(core, "title"),
+      author: firstElementText(core, "creator"),
+      language: firstElementText(core, "language"),
+      published: firstElementText(core, "created")
+    };
+  } catch {
+    return {};
+  }
+}
+
 globalThis.extractDocxText = async function extractDocxText(arrayBuffer) {
   if (!jsZip) {
     throw new Error("DOCX parsing failed: JSZip is not loaded");
@@ -101,26 +220,208 @@ globalThis.extractDocxText = async function extractDocxText(arrayBuffer) {
     }
 
     const paragraphs = Array.from(parsedDocument.getElementsByTagName("w:p"));
-    const paragraphText = paragraphs
-      .map((paragraph) =>
-        Array.from(paragraph.getElementsByTagName("w:t"))
+    const blocks = paragraphs
+      .map((paragraph) => ({
+        text: Array.from(paragraph.getElementsByTagName("w:t"))
           .map((node) => node.textContent ?? "")
           .join("")
-          .trim()
-      )
-      .filter(Boolean)
-      .join("\n");
+          .trim(),
+        heading: isDocxHeading(paragraph)
+      }))
+      .filter((block) => block.text);
 
-    if (paragraphText) {
-      return paragraphText;
+    if (blocks.length === 0) {
+      const text = Array.from(parsedDocument.getElementsByTagName("w:t"))
+        .map((node) => node.textContent ?? "")
+        .join("")
+        .trim();
+      if (text) {
+        blocks.push({ text, heading: false });
+      }
     }
 
-    return Array.from(parsedDocument.getElementsByTagName("w:t"))
-      .map((node) => node.textContent ?? "")
-      .join("")
-      .trim();
+    return JSON.stringify({
+      blocks,
+      metadata: await extractDocxMetadata(zip, inspection)
+    });
   } catch (error) {
     const message = error instanceof Error ? error.message : String(error);
     throw new Error(`DOCX parsing failed: ${message}`);
   }
 };
+
+const EPUB_BLOCK_SELECTOR =
+  "h1, h2, h3, h4, h5, h6, p, li, blockquote, pre, dt, dd, figcaption, td, th";
+const EPUB_HEADING_SELECTOR = "h1, h2, h3";
+const EPUB_HEADING_ELEMENTS = new Set(["h1", "h2", "h3", "h4", "h5", "h6"]);
+const XHTML_MEDIA_TYPES = new Set(["application/xhtml+xml", "text/html"]);
+
+async function readEpubEntry(zip, inspection, name, label) {
+  const metadata = inspection.entries.get(name);
+  const entry = zip.file(name);
+  if (!metadata || !entry || entry.dir) {
+    throw new Error(`EPUB archive has no readable ${name} entry`);
+  }
+
+  const bytes = await readZipEntryBounded(
+    entry,
+    DOCUMENT_LIMITS.maxDocumentXmlBytes,
+    metadata.uncompressedSize,
+    label
+  );
+  return new TextDecoder("utf-8", { fatal: true }).decode(bytes);
+}
+
+async function readEpubXml(zip, inspection, name, mimeType = "application/xml") {
+  const label = `EPUB ${name}`;
+  const xml = await readEpubEntry(zip, inspection, name, label);
+  validateDocumentXml(xml, DOCUMENT_LIMITS, { label, allowDoctype: true });
+  const parsed = new DOMParser().parseFromString(xml, mimeType);
+  if (parsed.querySelector("parsererror")) {
+    throw new Error(`${label} is malformed`);
+  }
+  return parsed;
+}
+
+function resolveEpubPath(basePath, href) {
+  const segments = basePath.split("/").slice(0, -1);
+  const target = decodeURIComponent(href.split("#")[0]);
+  for (const segment of target.split("/")) {
+    if (segment === "..") {
+      if (segments.length === 0) {
+        throw new Error(`EPUB reference escapes the archive: ${href}`);
+      }
+      segments.pop();
+    } else if (segment && segment !== ".") {
+      segments.push(segment);
+    }
+  }
+  return segments.join("/");
+}
+
+function collapseWhitespace(text) {
+  return (text ?? "").replace(/\s+/gu, " ").trim();
+}
+
+function firstElementByLocalName(parent, localName) {
+  return Array.from(parent.getElementsByTagName("*")).find(
+    (node) => node.localName === localName
+  );
+}
+
+function firstElementText(parent, localName) {
+  const element = firstElementByLocalName(parent, localName);
+  return collapseWhitespace(element?.textContent) || null;
+}
+
+function extractEpubChapter(xhtml) {
+  const body = xhtml.querySelector("body") ?? xhtml.documentElement;
+  const blocks = Array.from(body.querySelectorAll(EPUB_BLOCK_SELECTOR))
+    .filter((block) => !block.parentElement?.closest(EPUB_BLOCK_SELECTOR))
+    .map((block) => ({
+      text: collapseWhitespace(block.textContent),
+      heading: EPUB_HEADING_ELEMENTS.has(block.localName)
+    }))
+    .filter((block) => block.text);
+  if (blocks.length === 0) {
+    const text = collapseWhitespace(body.textContent);
+    if (text) {
+      blocks.push({ text, heading: false });
+    }
+  }
+  const heading = body.querySelector(EPUB_HEADING_SELECTOR);
+
+  return {
+    title: collapseWhitespace(heading?.textContent) || null,
+    blocks
+  };
+}
+
+globalThis.extractEpubText = async function extractEpubText(arrayBuffer) {
+  if (!jsZip) {
+    throw new Error("EPUB parsing failed: JSZip is not loaded");
+  }
+
+  try {
+    const inspection = inspectEpubArchive(arrayBuffer);
+    const zip = await jsZip.loadAsync(arrayBuffer, { createFolders: false });
+    assertEpubMimetype(
+      await readEpubEntry(zip, inspection, "mimetype", "EPUB mimetype")
+    );
+
+    const container = await readEpubXml(
+      zip,
+      inspection,
+      "META-INF/container.xml"
+    );
+    const rootfile = firstElementByLocalName(container, "rootfile");
+    const packagePath = rootfile?.getAttribute("full-path");
+    if (!packagePath) {
+      throw new Error("EPUB container has no package document");
+    }
+
+    const opf = await readEpubXml(zip, inspection, packagePath);
+    const manifest = new Map();
+    for (const item of opf.getElementsByTagName("*")) {
+      if (item.localName !== "item") {
+        continue;
+      }
+      const id = item.getAttribute("id");
+      const href = item.getAttribute("href");
+      if (id && href) {
+        manifest.set(id, {
+          path: resolveEpubPath(packagePath, href),
+          mediaType: item.getAttribute("media-type") ?? ""
+        });
+      }
+    }
+
+    const chapters = [];
+    let extractedCharacters = 0;
+    for (const itemref of opf.getElementsByTagName("*")) {
+      if (
+        itemref.localName !== "itemref" ||
+        itemref.getAttribute("linear") === "no"
+      ) {
+        continue;
+      }
+      const item = manifest.get(itemref.getAttribute("idref"));
+      if (!item || !XHTML_MEDIA_TYPES.has(item.mediaType)) {
+        continue;
+      }
+
+      const xhtml = await readEpubXml(
+        zip,
+        inspection,
+        item.path,
+        "application/xhtml+xml"
+      );
+      const chapter = extractEpubChapter(xhtml);
+      if (chapter.blocks.length === 0) {
+        continue;
+      }
+      for (const block of chapter.blocks) {
+        extractedCharacters = addEpubTextCharacters(
+          extractedCharacters,
+          block.text.length + 1
+        );
+      }
+      chapters.push(chapter);
+    }
+
+    if (chapters.length === 0) {
+      throw new Error("EPUB spine has no readable text");
+    }
+
+    return JSON.stringify({
+      title: firstElementText(opf, "title"),
+      author: firstElementText(opf, "creator"),
+      language: firstElementText(opf, "language"),
+      published: firstElementText(opf, "date"),
+      chapters
+    });
+  } catch (error) {
+    const message = error instanceof Error ? error.message : String(error);
+    throw new Error(`EPUB parsing failed: ${message}`);
+  }
+};
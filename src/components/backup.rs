@@ -0,0 +1,156 @@
+use super::library::load_library;
+use super::StoreContext;
+use crate::export::download_text;
+use crate::state::AppState;
+use crate::storage::{self, DocumentStore, StorageError};
+use leptos::html::Input;
+use leptos::prelude::*;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+async fn read_backup_file(input: web_sys::HtmlInputElement) -> Result<String, String> {
+    let file = input
+        .files()
+        .and_then(|files| files.get(0))
+        .ok_or_else(|| "Choose a backup file first.".to_string())?;
+    JsFuture::from(file.text())
+        .await
+        .ok()
+        .and_then(|text| text.as_string())
+        .ok_or_else(|| "The file could not be read.".to_string())
+}
+
+/// Exports the library to a passphrase-protected file and imports one back.
+#[component]
+pub fn BackupSettings() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
+    let (passphrase, set_passphrase) = signal(String::new());
+    let (confirm, set_confirm) = signal(String::new());
+    let (message, set_message) = signal(None::<Result<String, String>>);
+    let (is_busy, set_busy) = signal(false);
+    let file_input = NodeRef::<Input>::new();
+
+    let on_export = move |_| {
+        let passphrase = passphrase.get_untracked();
+        if passphrase.chars().count() < storage::MIN_PASSPHRASE_CHARS {
+            set_message.set(Some(Err(format!(
+                "Use a passphrase of at least {} characters.",
+                storage::MIN_PASSPHRASE_CHARS
+            ))));
+            return;
+        }
+        if passphrase != confirm.get_untracked() {
+            set_message.set(Some(Err("The passphrases do not match.".to_string())));
+            return;
+        }
+        let settings = untrack(|| state.settings());
+        set_busy.set(true);
+        set_message.set(None);
+        let store = store.get();
+        spawn_local(async move {
+            let result = match storage::export_backup(store.as_ref(), &passphrase, settings).await {
+                Ok(export) => {
+                    let file_name =
+                        format!("nabu-backup-{}.json", chrono::Utc::now().format("%Y-%m-%d"));
+                    download_text(&file_name, "application/json", &export.text)
+                        .map(|_| export.summary())
+                        .map_err(|e| format!("Could not save the backup: {:?}", e))
+                }
+                Err(e) => Err(format!("Could not export: {}", e)),
+            };
+            let _ = set_message.try_set(Some(result));
+            let _ = set_busy.try_set(false);
+        });
+    };
+
+    let on_import = move |_| {
+        let Some(input) = file_input.get_untracked() else {
+            return;
+        };
+        let passphrase = passphrase.get_untracked();
+        set_busy.set(true);
+        set_message.set(None);
+        let store = store.get();
+        spawn_local(async move {
+            let result = match read_backup_file(input).await {
+                Ok(text) => import(state, store.as_ref(), &text, &passphrase).await,
+                Err(e) => Err(e),
+            };
+            let _ = set_message.try_set(Some(result));
+            let _ = set_busy.try_set(false);
+        });
+    };
+
+    view! {
+        <section class="settings-section">
+            <span class="settings-label">"Backup"</span>
+            <p class="settings-note">
+                "Save every document, reading position, note, and setting to one file encrypted \
+                 with a passphrase. Importing adds documents that are not in the library yet."
+            </p>
+            <div class="settings-row">
+                <label class="settings-sublabel" for="backup-passphrase">"Passphrase"</label>
+                <input
+                    id="backup-passphrase"
+                    class="search-input"
+                    type="password"
+                    autocomplete="new-password"
+                    prop:value=move || passphrase.get()
+                    on:input=move |ev| set_passphrase.set(event_target_value(&ev))
+                />
+            </div>
+            <div class="settings-row">
+                <label class="settings-sublabel" for="backup-confirm">"Confirm"</label>
+                <input
+                    id="backup-confirm"
+                    class="search-input"
+                    type="password"
+                    autocomplete="new-password"
+                    placeholder="Only needed to export"
+                    prop:value=move || confirm.get()
+                    on:input=move |ev| set_confirm.set(event_target_value(&ev))
+                />
+            </div>
+            <div class="settings-confirm">
+                <button class="btn" prop:disabled=move || is_busy.get() on:click=on_export>
+                    "Export backup"
+                </button>
+            </div>
+            <div class="settings-row">
+                <input node_ref=file_input type="file" accept=".json,application/json" aria-label="Backup file" />
+                <button class="btn" prop:disabled=move || is_busy.get() on:click=on_import>
+                    "Import backup"
+                </button>
+            </div>
+            {move || is_busy.get().then(|| view! { <p class="settings-note">"Working..."</p> })}
+            {move || message.get().map(|result| match result {
+                Ok(text) => view! { <p class="settings-note">{text}</p> }.into_any(),
+                Err(e) => view! { <p class="settings-error">{e}</p> }.into_any(),
+            })}
+        </section>
+    }
+}
+
+/// Imports `text` and reloads the library so the new documents show up.
+async fn import(
+    state: AppState,
+    store: &dyn DocumentStore,
+    text: &str,
+    passphrase: &str,
+) -> Result<String, String> {
+    let report = storage::import_backup(store, text, passphrase)
+        .await
+        .map_err(|e| match e {
+            StorageError::WrongPassphrase => "Wrong passphrase for this backup.".to_string(),
+            e => format!("Could not import: {}", e),
+        })?;
+
+    if let Some(settings) = report.settings.clone() {
+        state.apply_settings(settings);
+    }
+    if let Err(e) = load_library(state, store).await {
+        web_sys::console::warn_1(&format!("Failed to reload the library: {}", e).into());
+    }
+
+    Ok(report.summary())
+}
@@ -0,0 +1,149 @@
+use super::library::load_library;
+use super::StoreContext;
+use crate::export::download_text;
+use crate::state::AppState;
+use crate::storage::{self, DamagedRecord};
+use leptos::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+/// Document records that could not be read, with ways to recover or remove them.
+#[component]
+pub fn DamagedRecords(damaged: RwSignal<Vec<DamagedRecord>>) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
+    let (key_text, set_key_text) = signal(String::new());
+    let (message, set_message) = signal(None::<Result<String, String>>);
+    let (is_busy, set_busy) = signal(false);
+
+    let on_retry = move |_| {
+        let key = match storage::parse_key(&key_text.get_untracked()) {
+            Ok(key) => key,
+            Err(e) => {
+                set_message.set(Some(Err(e.to_string())));
+                return;
+            }
+        };
+        set_busy.set(true);
+        set_message.set(None);
+        let store = store.get();
+        spawn_local(async move {
+            let result = match store.recover_with_key(&key).await {
+                Ok(0) => Err("That key does not open any of these records.".to_string()),
+                Ok(count) => {
+                    if let Ok(records) = load_library(state, store.as_ref()).await {
+                        let _ = damaged.try_set(records);
+                    }
+                    Ok(format!(
+                        "Recovered {} record{}.",
+                        count,
+                        if count == 1 { "" } else { "s" }
+                    ))
+                }
+                Err(e) => Err(format!("Could not recover: {}", e)),
+            };
+            let _ = set_key_text.try_set(String::new());
+            let _ = set_message.try_set(Some(result));
+            let _ = set_busy.try_set(false);
+        });
+    };
+
+    view! {
+        <Show when=move || damaged.with(|records| !records.is_empty())>
+            <section class="damaged-records glass-panel">
+                <h2 class="damaged-title">
+                    {move || format!("Damaged items ({})", damaged.with(Vec::len))}
+                </h2>
+                <p class="settings-note">
+                    "These records could not be read. If they were saved with an older key, \
+                     paste that key to re-encrypt them with the current one."
+                </p>
+                <ul class="damaged-list">
+                    <For
+                        each=move || damaged.get()
+                        key=|record| (record.store.clone(), record.key.clone())
+                        children=move |record| view! { <DamagedItem record=record damaged=damaged /> }
+                    />
+                </ul>
+                <div class="settings-row">
+                    <input
+                        class="search-input"
+                        type="password"
+                        autocomplete="off"
+                        placeholder="Base64 key"
+                        aria-label="Previous key"
+                        prop:value=move || key_text.get()
+                        on:input=move |ev| set_key_text.set(event_target_value(&ev))
+                    />
+                    <button class="btn" prop:disabled=move || is_busy.get() on:click=on_retry>
+                        "Retry with key"
+                    </button>
+                </div>
+                {move || message.get().map(|result| match result {
+                    Ok(text) => view! { <p class="settings-note">{text}</p> }.into_any(),
+                    Err(e) => view! { <p class="settings-error">{e}</p> }.into_any(),
+                })}
+            </section>
+        </Show>
+    }
+}
+
+#[component]
+fn DamagedItem(record: DamagedRecord, damaged: RwSignal<Vec<DamagedRecord>>) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
+    let (error, set_error) = signal(None::<String>);
+    let label = format!("{} / {}", record.store, record.key);
+    let reason = record.reason.clone();
+    let export_record = record.clone();
+
+    let on_export = move |_| {
+        let file_name = format!("nabu-damaged-{}.json", export_record.key);
+        if let Err(e) = download_text(&file_name, "application/json", &export_record.export_json())
+        {
+            set_error.set(Some(format!("Could not export: {:?}", e)));
+        }
+    };
+
+    let on_delete = move |_| {
+        let record = record.clone();
+        let store = store.get();
+        spawn_local(async move {
+            match store.delete_damaged(&record).await {
+                Ok(()) => {
+                    // A document with unreadable text is listed; it is gone now.
+                    if record.is_document() {
+                        let _ = state
+                            .documents
+                            .try_update(|docs| docs.retain(|doc| doc.id != record.key));
+                    }
+                    // Deleting a document deletes its other damaged records too.
+                    let annotations = format!("{}/", record.key);
+                    let _ = damaged.try_update(|records| {
+                        records.retain(|r| {
+                            let same_document =
+                                r.key == record.key || r.key.starts_with(&annotations);
+                            r != &record && !(record.is_document() && same_document)
+                        })
+                    });
+                }
+                Err(e) => {
+                    let _ = set_error.try_set(Some(format!("Could not delete: {}", e)));
+                }
+            }
+        });
+    };
+
+    view! {
+        <li class="damaged-item">
+            <div class="damaged-info">
+                <span class="damaged-key">{label}</span>
+                <span class="damaged-reason">{reason}</span>
+                {move || error.get().map(|e| view! { <span class="settings-error">{e}</span> })}
+            </div>
+            <div class="damaged-actions">
+                <button class="btn" on:click=on_export>"Export"</button>
+                <button class="btn btn-danger" on:click=on_delete>"Delete"</button>
+            </div>
+        </li>
+    }
+}
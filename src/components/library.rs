@@ -1,24 +1,110 @@
-use crate::state::{AppState, Document};
-use crate::storage::Storage;
+use super::StoreContext;
+use crate::search::{document_matches, library_documents};
+use crate::state::{AppState, DocumentMeta, SortKey};
+use crate::storage::{DamagedRecord, DocumentStore, StorageError};
 use leptos::prelude::*;
 use leptos_router::hooks::use_navigate;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 
+/// Pause after typing before document text is searched, since that decrypts
+/// every document.
+const CONTENT_SEARCH_DELAY: Duration = Duration::from_millis(300);
+
+/// Whether `target` takes typed or pasted text itself.
+fn is_text_field(target: Option<web_sys::EventTarget>) -> bool {
+    let Some(element) = target.and_then(|target| target.dyn_into::<web_sys::HtmlElement>().ok())
+    else {
+        return false;
+    };
+    element.is_content_editable() || matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA")
+}
+
+/// Loads the library and reading positions into `state`, returning the records
+/// that could not be read.
+pub(super) async fn load_library(
+    state: AppState,
+    store: &dyn DocumentStore,
+) -> Result<Vec<DamagedRecord>, StorageError> {
+    let scan = store.scan_library().await?;
+    let progress = store.get_all_progress().await?;
+    let _ = state.documents.try_set(scan.documents);
+    let _ = state.progress.try_set(
+        progress
+            .into_iter()
+            .map(|p| (p.document_id.clone(), p))
+            .collect(),
+    );
+    Ok(scan.damaged)
+}
+
+/// Deletes a document with its position and annotations, then drops it from
+/// the library.
+async fn delete_document(
+    state: AppState,
+    store: &dyn DocumentStore,
+    id: &str,
+) -> Result<(), StorageError> {
+    store.delete_document(id).await?;
+    let _ = state.progress.try_update(|all| {
+        all.remove(id);
+    });
+    let _ = state
+        .documents
+        .try_update(|docs| docs.retain(|doc| doc.id != id));
+    Ok(())
+}
+
 #[component]
 pub fn Library() -> impl IntoView {
     let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
     let documents = state.documents;
     let (show_upload, set_show_upload) = signal(false);
+    // Text pasted onto the library, waiting in the upload dialog.
+    let (pasted, set_pasted) = signal(None::<String>);
     let (is_loading, set_loading) = signal(true);
+    let (query, set_query) = signal(String::new());
+    // Documents whose text matches, with the query they were found for.
+    let (content_hits, set_content_hits) = signal((String::new(), HashSet::<String>::new()));
+    let search_generation = StoredValue::new(0u64);
+    let library_view = RwSignal::new(store.get().load_library_view());
+    let damaged = RwSignal::new(Vec::<DamagedRecord>::new());
+
+    // Persist the sort and filter choice whenever it changes.
+    Effect::new(move |_| {
+        library_view.with(|view| {
+            if let Err(e) = store.get().save_library_view(view) {
+                web_sys::console::warn_1(&format!("Failed to save library view: {}", e).into());
+            }
+        });
+    });
+
+    // Pasting outside a text field opens the upload dialog with the text.
+    let paste_listener = window_event_listener(leptos::ev::paste, move |ev| {
+        if show_upload.get_untracked() || is_text_field(ev.target()) {
+            return;
+        }
+        let text = ev
+            .clipboard_data()
+            .and_then(|data| data.get_data("text/plain").ok())
+            .filter(|text| !text.trim().is_empty());
+        if let Some(text) = text {
+            ev.prevent_default();
+            set_pasted.set(Some(text));
+            set_show_upload.set(true);
+        }
+    });
+    on_cleanup(move || paste_listener.remove());
 
     // Load documents from IndexedDB on mount
     Effect::new(move |_| {
+        let store = store.get();
         spawn_local(async move {
-            let storage = Storage::new();
-            match storage.get_all_documents().await {
-                Ok(docs) => {
-                    state.documents.set(docs);
-                }
+            match load_library(state, store.as_ref()).await {
+                Ok(records) => damaged.set(records),
                 Err(e) => {
                     web_sys::console::error_1(&format!("Failed to load documents: {}", e).into());
                 }
@@ -27,13 +113,98 @@ pub fn Library() -> impl IntoView {
         });
     });
 
-    let has_documents = move || !documents.get().is_empty();
+    // Titles and authors are matched as you type; document text is searched in
+    // the background once typing pauses. Older searches are dropped.
+    Effect::new(move |_| {
+        let q = query.get();
+        let generation = search_generation.get_value() + 1;
+        search_generation.set_value(generation);
+        if q.trim().is_empty() {
+            return;
+        }
+        set_timeout(
+            move || {
+                if search_generation.try_get_value() != Some(generation) {
+                    return;
+                }
+                let metadata: HashMap<String, DocumentMeta> = documents.with_untracked(|docs| {
+                    docs.iter()
+                        .map(|doc| (doc.id.clone(), doc.clone()))
+                        .collect()
+                });
+                let store = store.get();
+                spawn_local(async move {
+                    let found = store
+                        .find_documents(&|id, body| {
+                            metadata
+                                .get(id)
+                                .is_some_and(|meta| document_matches(meta, &body.content, &q))
+                        })
+                        .await;
+                    match found {
+                        Ok(ids) if search_generation.try_get_value() == Some(generation) => {
+                            let _ = set_content_hits.try_set((q, ids.into_iter().collect()));
+                        }
+                        Ok(_) => {}
+                        Err(e) => web_sys::console::warn_1(
+                            &format!("Failed to search document text: {}", e).into(),
+                        ),
+                    }
+                });
+            },
+            CONTENT_SEARCH_DELAY,
+        );
+    });
+
+    let has_documents = move || documents.with(|docs| !docs.is_empty());
+
+    let shown_documents = Memo::new(move |_| {
+        documents.with(|docs| {
+            state.progress.with(|progress| {
+                library_view.with(|view| {
+                    query.with(|q| {
+                        content_hits.with(|(hits_query, hits)| {
+                            let no_hits = HashSet::new();
+                            let hits = if hits_query == q { hits } else { &no_hits };
+                            library_documents(docs, progress, view, q, hits)
+                        })
+                    })
+                })
+            })
+        })
+    });
+
+    // Includes the saved filter so it stays selectable after its last document is deleted.
+    let file_types = Memo::new(move |_| {
+        let mut types: Vec<String> =
+            documents.with(|docs| docs.iter().map(|doc| doc.file_type.clone()).collect());
+        types.extend(library_view.with(|view| view.file_type.clone()));
+        types.sort();
+        types.dedup();
+        types
+    });
+
+    let on_sort_change = move |ev: leptos::ev::Event| {
+        let value = event_target_value(&ev);
+        if let Some(sort) = SortKey::ALL
+            .into_iter()
+            .find(|key| format!("{:?}", key) == value)
+        {
+            library_view.update(|view| view.sort = sort);
+        }
+    };
+
+    let on_type_change = move |ev: leptos::ev::Event| {
+        let value = event_target_value(&ev);
+        library_view.update(|view| view.file_type = Some(value).filter(|v| !v.is_empty()));
+    };
 
     view! {
         <div class="library-container">
             <header class="library-header glass-panel">
                 <h1 class="library-title">"NABU"</h1>
                 <div class="header-actions">
+                    <a href="/settings" class="btn">"Settings"</a>
                     <button class="btn btn-primary" on:click=move |_| set_show_upload.set(true)>"+ Add"</button>
                 </div>
             </header>
@@ -46,22 +217,75 @@ pub fn Library() -> impl IntoView {
             </Show>
 
             <Show when=move || !is_loading.get()>
+                <super::damaged::DamagedRecords damaged=damaged />
                 <Show
                     when=has_documents
                     fallback=move || view! { <EmptyState on_upload=move || set_show_upload.set(true) /> }
                 >
-                    <div class="document-grid">
-                        <For
-                            each=move || documents.get()
-                            key=|doc| doc.id.clone()
-                            children=move |doc| view! { <DocumentCard doc=doc /> }
+                    <div class="library-toolbar">
+                        <input
+                            type="search"
+                            class="search-input"
+                            placeholder="Search titles, authors, and text"
+                            prop:value=move || query.get()
+                            on:input=move |ev| set_query.set(event_target_value(&ev))
                         />
+                        <select
+                            class="library-select"
+                            aria-label="Sort by"
+                            prop:value=move || library_view.with(|view| format!("{:?}", view.sort))
+                            on:change=on_sort_change
+                        >
+                            {SortKey::ALL
+                                .into_iter()
+                                .map(|key| view! { <option value=format!("{:?}", key)>{key.label()}</option> })
+                                .collect_view()}
+                        </select>
+                        <button
+                            class="btn"
+                            aria-label="Reverse sort order"
+                            on:click=move |_| library_view.update(|view| view.descending = !view.descending)
+                        >
+                            {move || if library_view.with(|view| view.descending) { "Descending" } else { "Ascending" }}
+                        </button>
+                        <select
+                            class="library-select"
+                            aria-label="File type"
+                            prop:value=move || library_view.with(|view| view.file_type.clone().unwrap_or_default())
+                            on:change=on_type_change
+                        >
+                            <option value="">"All formats"</option>
+                            <For
+                                each=move || file_types.get()
+                                key=|file_type| file_type.clone()
+                                children=move |file_type| view! { <option value=file_type.clone()>{file_type.clone()}</option> }
+                            />
+                        </select>
                     </div>
+
+                    <Show
+                        when=move || shown_documents.with(|docs| !docs.is_empty())
+                        fallback=|| view! { <p class="library-no-results">"No documents match."</p> }
+                    >
+                        <div class="document-grid">
+                            <For
+                                each=move || shown_documents.get()
+                                key=|doc| doc.id.clone()
+                                children=move |doc| view! { <DocumentCard doc=doc /> }
+                            />
+                        </div>
+                    </Show>
                 </Show>
             </Show>
 
             <Show when=move || show_upload.get()>
-                <super::upload::UploadModal on_close=move || set_show_upload.set(false) />
+                <super::upload::UploadModal
+                    pasted=pasted.get_untracked()
+                    on_close=move || {
+                        set_show_upload.set(false);
+                        set_pasted.set(None);
+                    }
+                />
             </Show>
         </div>
     }
@@ -80,7 +304,7 @@ fn EmptyState(on_upload: impl Fn() + Send + Sync + 'static) -> impl IntoView {
             <button class="btn btn-primary" on:click=move |_| on_upload()>
                 "+ Upload your first document"
             </button>
-            <p class="empty-formats">"PDF . TXT . DOCX . MD"</p>
+            <p class="empty-formats">"PDF . TXT . DOCX . MD . EPUB"</p>
             <div class="drop-zone">
                 <p>"or drag and drop anywhere"</p>
             </div>
@@ -89,25 +313,20 @@ fn EmptyState(on_upload: impl Fn() + Send + Sync + 'static) -> impl IntoView {
 }
 
 #[component]
-fn DocumentCard(doc: Document) -> impl IntoView {
+fn DocumentCard(doc: DocumentMeta) -> impl IntoView {
     let state = expect_context::<AppState>();
     let navigate = use_navigate();
     let doc_id = doc.id.clone();
     let doc_id_nav = doc.id.clone();
     let doc_id_delete = doc.id.clone();
-    let doc_clone = doc.clone();
+    let doc_id_progress = doc.id.clone();
+    let doc_id_edit = doc.id.clone();
     let (show_confirm, set_show_confirm) = signal(false);
+    let (show_edit, set_show_edit) = signal(false);
 
     let on_click = move |ev: web_sys::MouseEvent| {
         ev.prevent_default();
-        // Set current document words for the reader
-        let words: Vec<String> = doc_clone
-            .content
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect();
-        state.current_words.set(words);
-        // Navigate client-side after state is set
+        // The reader loads the document text itself.
         let path = format!("/read/{}", doc_id_nav);
         navigate(&path, Default::default());
     };
@@ -118,20 +337,46 @@ fn DocumentCard(doc: Document) -> impl IntoView {
         set_show_confirm.set(true);
     };
 
+    let on_edit_click = move |ev: web_sys::MouseEvent| {
+        ev.prevent_default();
+        ev.stop_propagation();
+        set_show_edit.set(true);
+    };
+
     let word_count_display = format!("{} words", doc.word_count);
+    let byline = [doc.author.clone(), doc.published.clone()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" · ");
+    let resume_display = move || {
+        state.progress.with(|all| {
+            all.get(&doc_id_progress)
+                .filter(|p| p.word_index > 0 && !p.is_finished())
+                .map(|p| format!("Resume from {}%", p.percent()))
+        })
+    };
     let delete_label = format!("Delete {}", doc.title);
+    let edit_label = format!("Edit {}", doc.title);
 
     view! {
         <div class="document-card-wrapper">
             <a href=format!("/read/{}", doc_id) class="document-card" on:click=on_click>
                 <div class="card-content">
-                    <h3 class="card-title">{doc.title.clone()}</h3>
+                    <h3 class="card-title" lang=doc.language.clone()>{doc.title.clone()}</h3>
+                    {(!byline.is_empty()).then(|| view! { <p class="card-meta">{byline}</p> })}
                     <p class="card-meta">{word_count_display}</p>
+                    {move || resume_display().map(|text| view! { <p class="card-meta card-progress">{text}</p> })}
                 </div>
                 <div class="card-footer">
                     <span class="card-type">{doc.file_type.clone()}</span>
                 </div>
             </a>
+            <button class="btn-edit" on:click=on_edit_click aria-label=edit_label>
+                <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
+                    <path d="M12 20h9M16.5 3.5a2.1 2.1 0 013 3L7 19l-4 1 1-4z"/>
+                </svg>
+            </button>
             <button class="btn-delete" on:click=on_delete_click aria-label=delete_label>
                 <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
                     <path d="M3 6h18M8 6V4a2 2 0 012-2h4a2 2 0 012 2v2m3 0v14a2 2 0 01-2 2H7a2 2 0 01-2-2V6h14"/>
@@ -144,6 +389,12 @@ fn DocumentCard(doc: Document) -> impl IntoView {
                     None
                 }
             }}
+            <Show when=move || show_edit.get()>
+                <super::review::EditDocumentModal
+                    doc_id=doc_id_edit.clone()
+                    on_close=move || set_show_edit.set(false)
+                />
+            </Show>
         </div>
     }
 }
@@ -151,24 +402,17 @@ fn DocumentCard(doc: Document) -> impl IntoView {
 #[component]
 fn DeleteConfirm(doc_id: String, set_show_confirm: WriteSignal<bool>) -> impl IntoView {
     let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
     let id = doc_id.clone();
 
     let on_confirm = move |ev: web_sys::MouseEvent| {
         ev.prevent_default();
         ev.stop_propagation();
         let id = id.clone();
+        let store = store.get();
         spawn_local(async move {
-            let storage = Storage::new();
-            match storage.delete_document(&id).await {
-                Ok(_) => match storage.get_all_documents().await {
-                    Ok(docs) => state.documents.set(docs),
-                    Err(e) => {
-                        web_sys::console::error_1(&format!("Failed to refresh: {}", e).into())
-                    }
-                },
-                Err(e) => {
-                    web_sys::console::error_1(&format!("Failed to delete: {}", e).into());
-                }
+            if let Err(e) = delete_document(state, store.as_ref(), &id).await {
+                web_sys::console::error_1(&format!("Failed to delete: {}", e).into());
             }
         });
     };
@@ -189,3 +433,53 @@ fn DeleteConfirm(doc_id: String, set_show_confirm: WriteSignal<bool>) -> impl In
         </div>
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_document, load_library};
+    use crate::state::{AppState, Document, ReadingProgress, Settings};
+    use crate::storage::{DocumentStore, MemoryStore};
+    use crate::tokens::Block;
+    use futures::executor::block_on;
+    use leptos::prelude::*;
+
+    fn document(title: &str) -> Document {
+        Document::new(title.into(), vec![Block::paragraph("Text.")], "TXT".into())
+    }
+
+    #[test]
+    fn loading_fills_the_library_and_lists_damaged_records() {
+        let store = MemoryStore::new();
+        let (read, damaged) = (document("Read"), document("Damaged"));
+        block_on(store.save_document(&read)).unwrap();
+        block_on(store.save_document(&damaged)).unwrap();
+        block_on(store.save_progress(&ReadingProgress::new(read.id.clone(), 1, 2))).unwrap();
+        store.set_record("metadata", &damaged.id, b"not a record".to_vec());
+        let state = AppState::new(Settings::default());
+
+        let records = block_on(load_library(state, &store)).unwrap();
+
+        assert_eq!(state.documents.get_untracked(), vec![read.meta()]);
+        assert!(state
+            .progress
+            .with_untracked(|all| all.contains_key(&read.id)));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key, damaged.id);
+    }
+
+    #[test]
+    fn deleting_drops_the_document_from_storage_and_the_library() {
+        let store = MemoryStore::new();
+        let doc = document("Deleted");
+        block_on(store.save_document(&doc)).unwrap();
+        block_on(store.save_progress(&ReadingProgress::new(doc.id.clone(), 1, 2))).unwrap();
+        let state = AppState::new(Settings::default());
+        block_on(load_library(state, &store)).unwrap();
+
+        block_on(delete_document(state, &store, &doc.id)).unwrap();
+
+        assert!(state.documents.get_untracked().is_empty());
+        assert!(state.progress.get_untracked().is_empty());
+        assert_eq!(block_on(store.get_document(&doc.id)).unwrap(), None);
+    }
+}
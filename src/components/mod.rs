@@ -1,28 +1,72 @@
+mod backup;
+mod damaged;
 mod library;
 mod reader;
+mod review;
+mod settings;
+mod sync;
 mod upload;
+mod usage;
+mod vault;
 
 pub use library::Library;
 pub use reader::Reader;
+pub use settings::Settings;
+
+use std::rc::Rc;
 
 use leptos::prelude::*;
 use leptos_router::components::*;
 use leptos_router::path;
 
 use crate::state::AppState;
+use crate::storage::{self, DocumentStore, Storage};
+
+/// The document store components read and write. Get the store with
+/// [`StoreContext::get`] before moving it into a task.
+#[derive(Clone, Copy)]
+pub struct StoreContext(StoredValue<Rc<dyn DocumentStore>, LocalStorage>);
+
+impl StoreContext {
+    pub fn new(store: Rc<dyn DocumentStore>) -> Self {
+        Self(StoredValue::new_local(store))
+    }
+
+    pub fn get(&self) -> Rc<dyn DocumentStore> {
+        self.0.get_value()
+    }
+}
 
 #[component]
 pub fn App() -> impl IntoView {
-    let state = AppState::new();
+    let store: Rc<dyn DocumentStore> = Rc::new(Storage::new());
+    let state = AppState::new(store.load_settings());
+    state.locked.set(storage::is_locked());
     provide_context(state);
+    let store = StoreContext::new(store);
+    provide_context(store);
+    sync::follow_other_tabs(state, store);
+    vault::watch_inactivity(state);
+    vault::follow_vault_changes(state);
+
+    // Persist settings whenever any of them changes.
+    Effect::new(move |_| {
+        if let Err(e) = store.get().save_settings(&state.settings()) {
+            web_sys::console::warn_1(&format!("Failed to save settings: {}", e).into());
+        }
+    });
 
+    // Nothing that reads storage is mounted while the vault is locked.
     view! {
         <Router>
             <main id="app">
-                <Routes fallback=|| view! { <p>"Page not found"</p> }>
-                    <Route path=path!("/") view=Library />
-                    <Route path=path!("/read/:id") view=Reader />
-                </Routes>
+                <Show when=move || !state.locked.get() fallback=vault::Unlock>
+                    <Routes fallback=|| view! { <p>"Page not found"</p> }>
+                        <Route path=path!("/") view=Library />
+                        <Route path=path!("/read/:id") view=Reader />
+                        <Route path=path!("/settings") view=Settings />
+                    </Routes>
+                </Show>
             </main>
         </Router>
     }
@@ -1,90 +1,357 @@
-use crate::state::AppState;
-use crate::storage::Storage;
+use super::StoreContext;
+use crate::export::{annotations_markdown, download_text, export_file_name};
+use crate::scheduler::FrameClock;
+use crate::search::{find_in_tokens, next_hit, previous_hit, MAX_LISTED_HITS};
+use crate::state::{Annotation, AnnotationKind, AppState, ReadingProgress};
+use crate::timing::TimingModel;
+use crate::tokens::{chunk_end, last_sentence, sections, sentence_at, text_of};
 use leptos::ev::KeyboardEvent;
 use leptos::prelude::*;
-use leptos_router::hooks::use_params_map;
+use leptos_router::hooks::{use_navigate, use_params_map};
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::window;
 
+/// How often the reading position is persisted while playback is running.
+const PROGRESS_SAVE_INTERVAL_MS: f64 = 5000.0;
+
+/// Words shown on each side of a search match.
+const SEARCH_CONTEXT_WORDS: usize = 6;
+
+/// How long a confirmation such as "Bookmarked" stays on screen.
+const TOAST_DURATION: Duration = Duration::from_millis(1500);
+
 // The browser timer outlives individual reactive callbacks and is cleared on unmount.
+// Each frame schedules the next one with a fresh timeout so its duration can
+// follow WPM and chunk-size changes.
 thread_local! {
-    static INTERVAL_ID: std::cell::Cell<Option<i32>> = const { std::cell::Cell::new(None) };
+    static TIMEOUT_ID: Cell<Option<i32>> = const { Cell::new(None) };
+    static FRAME_CLOCK: Cell<Option<FrameClock>> = const { Cell::new(None) };
+    static TICK: RefCell<Option<Closure<dyn Fn()>>> = const { RefCell::new(None) };
+}
+
+fn performance_now() -> f64 {
+    window()
+        .and_then(|win| win.performance())
+        .map(|perf| perf.now())
+        .unwrap_or_else(js_sys::Date::now)
 }
 
-fn clear_global_interval() {
-    INTERVAL_ID.with(|id| {
-        if let Some(interval_id) = id.get() {
+fn clear_pending_timeout() {
+    TIMEOUT_ID.with(|id| {
+        if let Some(timeout_id) = id.take() {
             if let Some(win) = window() {
-                win.clear_interval_with_handle(interval_id);
+                win.clear_timeout_with_handle(timeout_id);
+            }
+        }
+    });
+}
+
+fn schedule_tick(delay_ms: f64) {
+    clear_pending_timeout();
+    TICK.with(|tick| {
+        let Some(tick) = tick.borrow().as_ref().map(|t| t.as_ref().clone()) else {
+            return;
+        };
+        if let Some(win) = window() {
+            if let Ok(id) = win.set_timeout_with_callback_and_timeout_and_arguments_0(
+                tick.unchecked_ref(),
+                delay_ms.ceil() as i32,
+            ) {
+                TIMEOUT_ID.with(|current| current.set(Some(id)));
             }
-            id.set(None);
         }
     });
 }
 
-fn set_global_interval(new_id: i32) {
-    INTERVAL_ID.with(|id| id.set(Some(new_id)));
+/// Stops playback timing. The tick closure is kept because this may run from
+/// inside it; `release_playback_timer` drops it.
+fn stop_playback_timer() {
+    clear_pending_timeout();
+    FRAME_CLOCK.with(|clock| clock.set(None));
 }
 
-fn has_global_interval() -> bool {
-    INTERVAL_ID.with(|id| id.get().is_some())
+fn release_playback_timer() {
+    stop_playback_timer();
+    TICK.with(|tick| tick.borrow_mut().take());
+}
+
+fn has_playback_timer() -> bool {
+    FRAME_CLOCK.with(|clock| clock.get().is_some())
+}
+
+/// Gives the frame on screen a new duration, keeping the time it has already
+/// been shown.
+fn retime_current_frame(duration_ms: f64) {
+    let Some(mut clock) = FRAME_CLOCK.with(|clock| clock.get()) else {
+        return;
+    };
+    clock.retime(duration_ms);
+    FRAME_CLOCK.with(|current| current.set(Some(clock)));
+    schedule_tick(clock.delay_ms(performance_now()));
 }
 
 #[component]
 pub fn Reader() -> impl IntoView {
     let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
     let params = use_params_map();
+    let navigate = use_navigate();
+    let (doc_id, set_doc_id) = signal(String::new());
+    let (doc_title, set_doc_title) = signal(String::new());
+    // Lets screen readers and fonts treat the words as the document's language.
+    let (doc_language, set_doc_language) = signal(None::<String>);
     let (is_loading, set_loading) = signal(true);
+    let (load_error, set_load_error) = signal(None::<String>);
     let (is_playing, set_playing) = signal(false);
     let (is_controls_visible, set_controls_visible) = signal(true);
     let (current_word_index, set_current_word_index) = signal(0usize);
     let (is_fullscreen, set_fullscreen) = signal(false);
-
-    let words = state.current_words;
+    let (is_toc_visible, set_toc_visible) = signal(false);
+    let (is_notes_visible, set_notes_visible) = signal(false);
+    let (toast, set_toast) = signal(None::<&'static str>);
+    let annotations = RwSignal::new(Vec::<Annotation>::new());
+    let (search_query, set_search_query) = signal(String::new());
+    let search_input = NodeRef::<leptos::html::Input>::new();
+
+    let tokens = state.current_tokens;
     let wpm = state.wpm;
     let font_size = state.font_size;
     let chunk_size = state.chunk_size;
+    let timing = state.timing;
 
     // Load document from IndexedDB
     Effect::new(move |_| {
-        let doc_id = params.get().get("id").unwrap_or_default();
-        if doc_id.is_empty() {
+        let id = params.get().get("id").unwrap_or_default();
+        set_doc_id.set(id.clone());
+        if id.is_empty() {
             set_loading.set(false);
             return;
         }
+        // Never show the previously opened document while this one decrypts.
+        tokens.set(Vec::new());
+        set_load_error.set(None);
+        let storage = store.get();
         spawn_local(async move {
-            let storage = Storage::new();
-            if let Ok(Some(doc)) = storage.get_document(&doc_id).await {
-                let doc_words: Vec<String> = doc
-                    .content
-                    .split_whitespace()
-                    .map(|s| s.to_string())
-                    .collect();
-                state.current_words.set(doc_words);
+            match storage.get_document(&id).await {
+                Ok(Some(doc)) => {
+                    let doc_tokens = doc.tokens();
+                    let total = doc_tokens.len();
+                    tokens.set(doc_tokens);
+                    set_doc_title.set(doc.title.clone());
+                    set_doc_language.set(doc.language.clone());
+
+                    match storage.get_annotations(&id).await {
+                        Ok(saved) => annotations.set(saved),
+                        Err(e) => web_sys::console::warn_1(
+                            &format!("Failed to load bookmarks and highlights: {}", e).into(),
+                        ),
+                    }
+
+                    match storage.get_progress(&id).await {
+                        Ok(Some(progress)) => {
+                            let index = progress.resume_index().min(total.saturating_sub(1));
+                            set_current_word_index.set(index);
+                        }
+                        Ok(None) => set_current_word_index.set(0),
+                        Err(e) => {
+                            set_current_word_index.set(0);
+                            web_sys::console::warn_1(
+                                &format!("Failed to load reading position: {}", e).into(),
+                            );
+                        }
+                    }
+                }
+                // Deleted in another tab, or a stale link.
+                Ok(None) => {
+                    set_load_error.set(Some("This document is no longer in the library.".into()))
+                }
+                Err(e) => {
+                    set_load_error.set(Some(format!("This document could not be read. {}", e)))
+                }
             }
             set_loading.set(false);
         });
     });
 
+    // Signals may already be disposed when this runs during unmount.
+    let save_position_at = move |index: usize| {
+        if is_loading.try_get_untracked().unwrap_or(true) {
+            return;
+        }
+        let (Some(id), Some(total)) = (
+            doc_id.try_get_untracked(),
+            tokens.try_with_untracked(|t| t.len()),
+        ) else {
+            return;
+        };
+        if id.is_empty() || total == 0 {
+            return;
+        }
+
+        let progress = ReadingProgress::new(id, index, total);
+        state.set_progress(progress.clone());
+        let storage = store.get();
+        spawn_local(async move {
+            if let Err(e) = storage.save_progress(&progress).await {
+                web_sys::console::warn_1(&format!("Failed to save reading position: {}", e).into());
+            }
+        });
+    };
+
+    let save_position = move || {
+        if let Some(index) = current_word_index.try_get_untracked() {
+            save_position_at(index);
+        }
+    };
+
     // Cleanup on unmount
     on_cleanup(move || {
-        clear_global_interval();
+        release_playback_timer();
+        save_position();
     });
 
     let current_chunk = Signal::derive(move || {
         let idx = current_word_index.get();
         let chunk = chunk_size.get() as usize;
-        let word_list = words.get();
-        let end_idx = (idx + chunk).min(word_list.len());
-        word_list
-            .get(idx..end_idx)
-            .map(|slice| slice.join(" "))
-            .unwrap_or_default()
+        tokens.with(|t| {
+            t.get(idx..chunk_end(t, idx, chunk))
+                .map(|slice| {
+                    slice
+                        .iter()
+                        .map(|token| token.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .unwrap_or_default()
+        })
+    });
+
+    // Headings are shown differently so new sections stand out.
+    let is_heading = Signal::derive(move || {
+        let idx = current_word_index.get();
+        tokens.with(|t| t.get(idx).is_some_and(|token| token.heading))
+    });
+
+    let toc = Memo::new(move |_| tokens.with(|t| sections(t)));
+
+    let jump_to = move |index: usize| {
+        set_current_word_index.set(index);
+        set_toc_visible.set(false);
+        set_notes_visible.set(false);
+        save_position();
+    };
+
+    let search_hits = Memo::new(move |_| {
+        let query = search_query.get();
+        tokens.with(|t| find_in_tokens(t, &query))
     });
 
+    let go_to_hit = move |forward: bool| {
+        let index = current_word_index.get_untracked();
+        let hit = search_hits.with_untracked(|hits| {
+            if forward {
+                next_hit(hits, index)
+            } else {
+                previous_hit(hits, index)
+            }
+        });
+        if let Some(hit) = hit {
+            set_current_word_index.set(hit);
+            save_position();
+        }
+    };
+
+    let search_status = move || {
+        let query_is_empty = search_query.with(|q| q.trim().is_empty());
+        let index = current_word_index.get();
+        search_hits.with(|hits| {
+            if query_is_empty {
+                String::new()
+            } else if let Some(position) = hits.iter().position(|hit| hit.start == index) {
+                format!("{} of {}", position + 1, hits.len())
+            } else {
+                match hits.len() {
+                    0 => "No matches".to_string(),
+                    1 => "1 match".to_string(),
+                    n => format!("{} matches", n),
+                }
+            }
+        })
+    };
+
+    let show_toast = move |message: &'static str| {
+        set_toast.set(Some(message));
+        set_timeout(
+            move || {
+                let _ = set_toast.try_set(None);
+            },
+            TOAST_DURATION,
+        );
+    };
+
+    // Bookmarks mark the word on screen; highlights take the last sentence that
+    // has been fully shown.
+    let add_annotation = move |kind: AnnotationKind| {
+        let id = doc_id.get_untracked();
+        if id.is_empty() {
+            return;
+        }
+        let index = current_word_index.get_untracked();
+        let chunk = chunk_size.get_untracked() as usize;
+        let annotation = tokens.with_untracked(|t| {
+            let (range, context) = match kind {
+                AnnotationKind::Bookmark => {
+                    let range = (index < t.len()).then_some(index..index + 1)?;
+                    (range, sentence_at(t, index))
+                }
+                AnnotationKind::Highlight => {
+                    let range = last_sentence(t, chunk_end(t, index, chunk).saturating_sub(1))?;
+                    (range.clone(), range)
+                }
+            };
+            Some(Annotation::new(
+                id,
+                kind,
+                range.start,
+                range.end,
+                text_of(t, context),
+            ))
+        });
+
+        let Some(annotation) = annotation else {
+            show_toast("No complete sentence yet");
+            return;
+        };
+        let exists = annotations.with_untracked(|all| {
+            all.iter()
+                .any(|a| a.kind == kind && a.start == annotation.start && a.end == annotation.end)
+        });
+        if exists {
+            show_toast("Already saved");
+            return;
+        }
+
+        annotations.update(|all| {
+            all.push(annotation.clone());
+            all.sort_by_key(|a| (a.start, a.end));
+        });
+        show_toast(match kind {
+            AnnotationKind::Bookmark => "Bookmarked",
+            AnnotationKind::Highlight => "Highlighted",
+        });
+        let storage = store.get();
+        spawn_local(async move {
+            if let Err(e) = storage.save_annotation(&annotation).await {
+                web_sys::console::warn_1(&format!("Failed to save annotation: {}", e).into());
+            }
+        });
+    };
+
     let progress_percent = move || {
-        let total = words.get().len();
+        let total = tokens.with(|t| t.len());
         if total == 0 {
             return 0.0;
         }
@@ -92,7 +359,9 @@ pub fn Reader() -> impl IntoView {
     };
 
     let time_remaining = move || {
-        let remaining_words = words.get().len().saturating_sub(current_word_index.get());
+        let remaining_words = tokens
+            .with(|t| t.len())
+            .saturating_sub(current_word_index.get());
         let wpm_val = wpm.get() as f64;
         if wpm_val == 0.0 {
             return "0:00".to_string();
@@ -102,53 +371,89 @@ pub fn Reader() -> impl IntoView {
         format!("{}:{:02}", total_seconds / 60, total_seconds % 60)
     };
 
+    let timing_model = Memo::new(move |_| {
+        let config = timing.get();
+        tokens.with(|t| TimingModel::new(t, config))
+    });
+
+    // Each chunk's time follows its words, averaging to the selected WPM.
+    let current_frame_duration = move || {
+        let index = current_word_index.get_untracked();
+        let chunk = chunk_size.get_untracked() as usize;
+        let wpm = wpm.get_untracked();
+        tokens.with_untracked(|t| {
+            let chunk_tokens = t.get(index..chunk_end(t, index, chunk)).unwrap_or_default();
+            timing_model.with_untracked(|model| model.chunk_duration_ms(chunk_tokens, wpm))
+        })
+    };
+
     let start_playing = move || {
-        if has_global_interval() {
+        if has_playback_timer() {
             return;
         }
 
-        let total = words.get_untracked().len();
+        let total = tokens.with_untracked(|t| t.len());
         if total == 0 {
             return;
         }
 
-        // Speed is proportional to chunk size: more words = proportionally more time
-        let chunk = chunk_size.get_untracked() as u32;
-        let base_delay = 60000 / wpm.get_untracked().max(1);
-        let delay = base_delay * chunk;
-
-        if let Some(win) = window() {
-            let closure = Closure::<dyn Fn()>::new(move || {
-                let total = words.get_untracked().len();
-                let current = current_word_index.get_untracked();
-                let chunk = chunk_size.get_untracked() as usize;
-
-                let next_idx = current + chunk;
-                if next_idx < total {
-                    set_current_word_index.set(next_idx);
-                } else {
-                    clear_global_interval();
-                    set_playing.set(false);
-                    set_controls_visible.set(true);
+        let last_saved = Cell::new(performance_now());
+        let tick = Closure::<dyn Fn()>::new(move || {
+            let now = performance_now();
+            let current = current_word_index.get_untracked();
+            let chunk = chunk_size.get_untracked() as usize;
+            let (next_idx, total) =
+                tokens.with_untracked(|t| (chunk_end(t, current, chunk), t.len()));
+
+            if next_idx < total {
+                super::vault::note_activity();
+                set_current_word_index.set(next_idx);
+                if now - last_saved.get() >= PROGRESS_SAVE_INTERVAL_MS {
+                    last_saved.set(now);
+                    save_position();
                 }
-            });
 
-            if let Ok(id) = win.set_interval_with_callback_and_timeout_and_arguments_0(
-                closure.as_ref().unchecked_ref(),
-                delay as i32,
-            ) {
-                set_global_interval(id);
-                set_playing.set(true);
-                set_controls_visible.set(false);
-                closure.forget();
+                let Some(mut clock) = FRAME_CLOCK.with(|clock| clock.get()) else {
+                    return;
+                };
+                clock.advance(now, current_frame_duration());
+                FRAME_CLOCK.with(|current| current.set(Some(clock)));
+                schedule_tick(clock.delay_ms(now));
+            } else {
+                stop_playback_timer();
+                set_playing.set(false);
+                set_controls_visible.set(true);
+                save_position_at(total - 1);
             }
-        }
+        });
+
+        release_playback_timer();
+        TICK.with(|current| *current.borrow_mut() = Some(tick));
+
+        let now = performance_now();
+        let clock = FrameClock::start(now, current_frame_duration());
+        FRAME_CLOCK.with(|current| current.set(Some(clock)));
+        schedule_tick(clock.delay_ms(now));
+
+        set_playing.set(true);
+        set_controls_visible.set(false);
     };
 
+    // WPM, chunk-size, and timing changes apply to the frame on screen immediately.
+    Effect::new(move |_| {
+        wpm.track();
+        chunk_size.track();
+        timing_model.track();
+        if is_playing.get_untracked() {
+            retime_current_frame(current_frame_duration());
+        }
+    });
+
     let stop_playing = move || {
-        clear_global_interval();
+        stop_playback_timer();
         set_playing.set(false);
         set_controls_visible.set(true);
+        save_position();
     };
 
     let toggle_play = move |ev: web_sys::MouseEvent| {
@@ -169,6 +474,7 @@ pub fn Reader() -> impl IntoView {
     let restart = move || {
         stop_playing();
         set_current_word_index.set(0);
+        save_position();
     };
 
     let toggle_fullscreen = move || {
@@ -217,14 +523,35 @@ pub fn Reader() -> impl IntoView {
         "r" | "R" => {
             restart();
         }
+        "b" | "B" => {
+            add_annotation(AnnotationKind::Bookmark);
+        }
+        "h" | "H" => {
+            add_annotation(AnnotationKind::Highlight);
+        }
+        "n" | "N" => {
+            go_to_hit(true);
+        }
+        "p" | "P" => {
+            go_to_hit(false);
+        }
+        "/" => {
+            ev.prevent_default();
+            stop_playing();
+            request_animation_frame(move || {
+                if let Some(input) = search_input.get_untracked() {
+                    let _ = input.focus();
+                }
+            });
+        }
         "f" | "F" => {
             toggle_fullscreen();
         }
         "Escape" => {
             if is_fullscreen.get_untracked() {
                 toggle_fullscreen();
-            } else if let Some(win) = window() {
-                let _ = win.location().set_href("/");
+            } else {
+                navigate("/", Default::default());
             }
         }
         _ => {}
@@ -253,10 +580,14 @@ pub fn Reader() -> impl IntoView {
             </Show>
 
             <Show when=move || !is_loading.get()>
-                <div class="reader-word-display">
-                    <WordDisplay word=current_chunk font_class=font_class />
+                {move || load_error.get().map(|e| view! { <p class="reader-load-error settings-error">{e}</p> })}
+
+                <div class="reader-word-display" lang=move || doc_language.get()>
+                    <WordDisplay word=current_chunk font_class=font_class heading=is_heading />
                 </div>
 
+                {move || toast.get().map(|message| view! { <div class="reader-toast glass-panel">{message}</div> })}
+
                 <Show when=move || !is_controls_visible.get()>
                     <div class="reader-minimal-progress">
                         <div class="minimal-progress-fill" style=move || format!("width: {}%", progress_percent())></div>
@@ -267,12 +598,142 @@ pub fn Reader() -> impl IntoView {
                     <div class="reader-controls glass-panel animate-fade-in" on:click=move |ev: web_sys::MouseEvent| ev.stop_propagation()>
                         <div class="controls-header">
                             <a href="/" class="btn">"Library"</a>
+                            <button
+                                class=move || if is_toc_visible.get() { "btn btn-active" } else { "btn" }
+                                on:click=move |_| {
+                                    set_notes_visible.set(false);
+                                    set_toc_visible.update(|visible| *visible = !*visible);
+                                }
+                            >"Contents"</button>
+                            <button
+                                class=move || if is_notes_visible.get() { "btn btn-active" } else { "btn" }
+                                on:click=move |_| {
+                                    set_toc_visible.set(false);
+                                    set_notes_visible.update(|visible| *visible = !*visible);
+                                }
+                            >"Notes"</button>
                             <div class="spacer"></div>
                             <button class="btn" on:click=move |_| toggle_fullscreen()>
                                 {move || if is_fullscreen.get() { "Exit Fullscreen" } else { "Fullscreen" }}
                             </button>
                         </div>
 
+                        <Show when=move || is_toc_visible.get()>
+                            <nav class="toc-panel">
+                                <Show
+                                    when=move || toc.with(|t| !t.is_empty())
+                                    fallback=|| view! { <p class="toc-empty">"This document has no headings."</p> }
+                                >
+                                    <ol class="toc-list">
+                                        <For
+                                            each=move || toc.get().into_iter().enumerate()
+                                            key=|(_, section)| section.start
+                                            let:entry
+                                        >
+                                            {
+                                                let (number, section) = entry;
+                                                let start = section.start;
+                                                let section_progress = section.clone();
+                                                let is_current = move || {
+                                                    let index = current_word_index.get();
+                                                    index >= section.start && index < section.end
+                                                };
+                                                view! {
+                                                    <li>
+                                                        <button
+                                                            class=move || if is_current() { "toc-entry toc-current" } else { "toc-entry" }
+                                                            on:click=move |_| jump_to(start)
+                                                        >
+                                                            <span class="toc-number">{number + 1}</span>
+                                                            <span class="toc-title">{section_progress.title.clone()}</span>
+                                                            <span class="toc-meta">
+                                                                {move || format!(
+                                                                    "Word {} \u{b7} {}%",
+                                                                    start + 1,
+                                                                    section_progress.percent_at(current_word_index.get()),
+                                                                )}
+                                                            </span>
+                                                        </button>
+                                                    </li>
+                                                }
+                                            }
+                                        </For>
+                                    </ol>
+                                </Show>
+                            </nav>
+                        </Show>
+
+                        <Show when=move || is_notes_visible.get()>
+                            <AnnotationsPanel
+                                annotations=annotations
+                                title=doc_title
+                                on_jump=Callback::new(jump_to)
+                            />
+                        </Show>
+
+                        <div class="controls-search">
+                            <input
+                                type="search"
+                                class="search-input"
+                                placeholder="Search this document"
+                                node_ref=search_input
+                                prop:value=move || search_query.get()
+                                on:change=move |ev| set_search_query.set(event_target_value(&ev))
+                                on:keydown=move |ev: KeyboardEvent| {
+                                    ev.stop_propagation();
+                                    match ev.key().as_str() {
+                                        "Enter" => {
+                                            set_search_query.set(event_target_value(&ev));
+                                            go_to_hit(!ev.shift_key());
+                                        }
+                                        "Escape" => {
+                                            if let Some(input) = search_input.get_untracked() {
+                                                let _ = input.blur();
+                                            }
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            />
+                            <span class="search-status">{search_status}</span>
+                            <button class="btn" on:click=move |_| go_to_hit(false)>"Prev"</button>
+                            <button class="btn" on:click=move |_| go_to_hit(true)>"Next"</button>
+                        </div>
+
+                        <Show when=move || search_hits.with(|hits| !hits.is_empty())>
+                            <ol class="toc-panel toc-list search-results">
+                                <For
+                                    each=move || search_hits.get().into_iter().take(MAX_LISTED_HITS)
+                                    key=|hit| hit.start
+                                    let:hit
+                                >
+                                    {
+                                        let start = hit.start;
+                                        let (before, matched, after) = tokens.with_untracked(|t| {
+                                            (
+                                                text_of(t, hit.start.saturating_sub(SEARCH_CONTEXT_WORDS)..hit.start),
+                                                text_of(t, hit.clone()),
+                                                text_of(t, hit.end..hit.end + SEARCH_CONTEXT_WORDS),
+                                            )
+                                        });
+                                        view! {
+                                            <li>
+                                                <button
+                                                    class=move || if current_word_index.get() == start { "toc-entry toc-current" } else { "toc-entry" }
+                                                    on:click=move |_| jump_to(start)
+                                                >
+                                                    <span class="toc-title">
+                                                        {before} " " <mark class="search-match">{matched}</mark> " " {after}
+                                                    </span>
+                                                    <span class="toc-meta">{format!("Word {}", start + 1)}</span>
+                                                </button>
+                                            </li>
+                                        }
+                                    }
+                                </For>
+                            </ol>
+                        </Show>
+
                         <div class="controls-main">
                             <button class="btn" on:click=move |_| {
                                 let current = current_word_index.get_untracked();
@@ -283,7 +744,7 @@ pub fn Reader() -> impl IntoView {
                             </button>
                             <button class="btn" on:click=move |_| {
                                 let current = current_word_index.get_untracked();
-                                let total = words.get_untracked().len();
+                                let total = tokens.with_untracked(|t| t.len());
                                 set_current_word_index.set((current + 50).min(total.saturating_sub(1)));
                             }>"+50 >|"</button>
                         </div>
@@ -302,7 +763,7 @@ pub fn Reader() -> impl IntoView {
                                 let width = elem.offset_width() as f64;
                                 if width > 0.0 {
                                     let percent = (x / width).clamp(0.0, 1.0);
-                                    let total = words.get_untracked().len();
+                                    let total = tokens.with_untracked(|t| t.len());
                                     let new_index = ((total as f64) * percent) as usize;
                                     set_current_word_index.set(new_index.min(total.saturating_sub(1)));
                                 }
@@ -310,7 +771,7 @@ pub fn Reader() -> impl IntoView {
                                 <div class="progress-bar-fill" style=move || format!("width: {}%", progress_percent())></div>
                             </div>
                             <div class="progress-info">
-                                <span>"Word " {move || current_word_index.get() + 1} " of " {move || words.get().len()}</span>
+                                <span>"Word " {move || current_word_index.get() + 1} " of " {move || tokens.with(|t| t.len())}</span>
                                 <span>{time_remaining} " remaining"</span>
                             </div>
                         </div>
@@ -336,6 +797,10 @@ pub fn Reader() -> impl IntoView {
                             <span>"SPACE pause"</span>
                             <span>"Arrows +/-WPM"</span>
                             <span>"R restart"</span>
+                            <span>"B bookmark"</span>
+                            <span>"H highlight"</span>
+                            <span>"/ search"</span>
+                            <span>"N/P next/prev match"</span>
                             <span>"F fullscreen"</span>
                             <span>"ESC exit"</span>
                         </div>
@@ -346,6 +811,121 @@ pub fn Reader() -> impl IntoView {
     }
 }
 
+#[component]
+fn AnnotationsPanel(
+    annotations: RwSignal<Vec<Annotation>>,
+    title: ReadSignal<String>,
+    on_jump: Callback<usize>,
+) -> impl IntoView {
+    let store = expect_context::<StoreContext>();
+    let export = move |_| {
+        let title = title.get_untracked();
+        let markdown = annotations.with_untracked(|all| annotations_markdown(&title, all));
+        if let Err(e) = download_text(
+            &export_file_name(&title, "notes.md"),
+            "text/markdown",
+            &markdown,
+        ) {
+            web_sys::console::warn_1(&format!("Failed to export notes: {:?}", e).into());
+        }
+    };
+
+    let save = move |annotation: Annotation| {
+        let storage = store.get();
+        spawn_local(async move {
+            if let Err(e) = storage.save_annotation(&annotation).await {
+                web_sys::console::warn_1(&format!("Failed to save note: {}", e).into());
+            }
+        });
+    };
+
+    let delete = move |id: String| {
+        let mut removed = None;
+        annotations.update(|all| {
+            if let Some(position) = all.iter().position(|a| a.id == id) {
+                removed = Some(all.remove(position));
+            }
+        });
+        if let Some(annotation) = removed {
+            let storage = store.get();
+            spawn_local(async move {
+                if let Err(e) = storage.delete_annotation(&annotation).await {
+                    web_sys::console::warn_1(&format!("Failed to delete annotation: {}", e).into());
+                }
+            });
+        }
+    };
+
+    view! {
+        <div class="toc-panel notes-panel">
+            <div class="notes-header">
+                <span class="settings-label">"Bookmarks and highlights"</span>
+                <button
+                    class="btn"
+                    prop:disabled=move || annotations.with(|all| all.is_empty())
+                    on:click=export
+                >"Export Markdown"</button>
+            </div>
+            <Show
+                when=move || annotations.with(|all| !all.is_empty())
+                fallback=|| view! { <p class="toc-empty">"Press B to bookmark a word or H to highlight the last sentence."</p> }
+            >
+                <ul class="toc-list">
+                    <For
+                        each=move || annotations.get()
+                        key=|annotation| annotation.id.clone()
+                        let:annotation
+                    >
+                        {
+                            let start = annotation.start;
+                            let id = annotation.id.clone();
+                            let note_id = annotation.id.clone();
+                            let label = match annotation.kind {
+                                AnnotationKind::Bookmark => "Bookmark",
+                                AnnotationKind::Highlight => "Highlight",
+                            };
+                            view! {
+                                <li class="note-item">
+                                    <button class="toc-entry" on:click=move |_| on_jump.run(start)>
+                                        <span class="toc-number">{label}</span>
+                                        <span class="toc-title">{annotation.text.clone()}</span>
+                                        <span class="toc-meta">{format!("Word {}", start + 1)}</span>
+                                    </button>
+                                    <div class="note-row">
+                                        <textarea
+                                            class="note-input"
+                                            rows="2"
+                                            placeholder="Add a note"
+                                            prop:value=annotation.note.clone()
+                                            on:keydown=|ev: KeyboardEvent| ev.stop_propagation()
+                                            on:change=move |ev| {
+                                                let note = event_target_value(&ev);
+                                                let mut updated = None;
+                                                annotations.update(|all| {
+                                                    if let Some(a) = all.iter_mut().find(|a| a.id == note_id) {
+                                                        a.note = note;
+                                                        updated = Some(a.clone());
+                                                    }
+                                                });
+                                                if let Some(annotation) = updated {
+                                                    save(annotation);
+                                                }
+                                            }
+                                        ></textarea>
+                                        <button class="btn btn-danger" on:click=move |_| delete(id.clone())>
+                                            "Delete"
+                                        </button>
+                                    </div>
+                                </li>
+                            }
+                        }
+                    </For>
+                </ul>
+            </Show>
+        </div>
+    }
+}
+
 fn get_orp_index(len: usize) -> usize {
     match len {
         0 => 0,
@@ -379,14 +959,21 @@ fn format_word_with_orp(word: &str) -> (String, String, String) {
 }
 
 #[component]
-fn WordDisplay(word: Signal<String>, font_class: Signal<&'static str>) -> impl IntoView {
+fn WordDisplay(
+    word: Signal<String>,
+    font_class: Signal<&'static str>,
+    heading: Signal<bool>,
+) -> impl IntoView {
     let state = expect_context::<AppState>();
     let chunk_size = state.chunk_size;
 
     let orp_index = Signal::derive(move || get_orp_index(word.get().chars().count()));
 
     view! {
-        <div class=move || format!("word-container {}", font_class.get())>
+        <div class=move || {
+            let heading_class = if heading.get() { " word-heading" } else { "" };
+            format!("word-container {}{}", font_class.get(), heading_class)
+        }>
             <Show when=move || chunk_size.get() == 1>
                 <div class="orp-guides"><div class="orp-line-top"></div></div>
                 <div class="word-text">
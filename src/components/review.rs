@@ -0,0 +1,339 @@
+use super::StoreContext;
+use crate::import::{preview, DocumentEdit, EditedDocument};
+use crate::state::{Annotation, AppState, Document, ReadingProgress};
+use crate::storage::{DocumentStore, RecordBatch, StorageError};
+use crate::tokens::paragraphs;
+use leptos::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+/// Saves an edit to a document already in the library. If text was removed,
+/// its reading position and annotations move to the words they were on, and
+/// annotations on removed text are deleted. Everything is saved in one batch,
+/// so the stored positions always match the stored text.
+async fn save_edit(
+    state: AppState,
+    store: &dyn DocumentStore,
+    edited: &EditedDocument,
+) -> Result<(), StorageError> {
+    let doc = &edited.document;
+    let mut batch = RecordBatch {
+        documents: vec![doc.clone()],
+        ..RecordBatch::default()
+    };
+
+    if edited.text_changed() {
+        if let Some(progress) = store.get_progress(&doc.id).await? {
+            batch.progress.push(ReadingProgress::new(
+                doc.id.clone(),
+                edited.word_index(progress.word_index),
+                doc.word_count,
+            ));
+        }
+        for annotation in store.get_annotations(&doc.id).await? {
+            let start = edited.word_index(annotation.start);
+            let end = edited.word_index(annotation.end);
+            if start == end {
+                batch.deleted_annotations.push(annotation);
+            } else {
+                batch.annotations.push(Annotation {
+                    start,
+                    end,
+                    ..annotation
+                });
+            }
+        }
+    }
+    store.save_batch(&batch).await?;
+
+    for progress in batch.progress {
+        state.merge_progress(progress);
+    }
+
+    let meta = doc.meta();
+    let _ = state.documents.try_update(|docs| {
+        match docs.iter_mut().find(|listed| listed.id == meta.id) {
+            Some(listed) => *listed = meta,
+            None => docs.push(meta),
+        }
+    });
+    Ok(())
+}
+
+/// Title, author, and text trimming for a document, with a preview of the
+/// result. Nothing is saved here; `on_save` gets the edited document.
+#[component]
+pub fn DocumentReview(
+    document: Document,
+    on_save: impl Fn(EditedDocument) + Send + Sync + 'static,
+    on_cancel: impl Fn() + Send + Sync + 'static,
+) -> impl IntoView {
+    let paragraph_count = paragraphs(&document.content).len();
+    let edit = RwSignal::new(DocumentEdit::new(&document));
+    let document = StoredValue::new(document);
+    let edited = Memo::new(move |_| edit.with(|edit| document.with_value(|doc| edit.apply(doc))));
+    let shown = Memo::new(move |_| edited.with(|edited| preview(&edited.document)));
+
+    let set_count = move |ev: leptos::ev::Event, field: fn(&mut DocumentEdit) -> &mut usize| {
+        let count = event_target_value(&ev).parse().unwrap_or(0);
+        edit.update(|edit| *field(edit) = usize::min(count, paragraph_count));
+    };
+
+    let word_summary = move || {
+        edited.with(
+            |edited| match (edited.document.word_count, edited.removed_words()) {
+                (0, _) => "Nothing left to save".to_string(),
+                (words, 0) => format!("{} words", words),
+                (words, removed) => format!("{} words, {} removed", words, removed),
+            },
+        )
+    };
+
+    view! {
+        <div class="review-form">
+            <div class="settings-row">
+                <label class="settings-sublabel" for="review-title">"Title"</label>
+                <input
+                    id="review-title"
+                    class="search-input"
+                    type="text"
+                    prop:value=move || edit.with(|edit| edit.title.clone())
+                    on:input=move |ev| edit.update(|edit| edit.title = event_target_value(&ev))
+                />
+            </div>
+            <div class="settings-row">
+                <label class="settings-sublabel" for="review-author">"Author"</label>
+                <input
+                    id="review-author"
+                    class="search-input"
+                    type="text"
+                    prop:value=move || edit.with(|edit| edit.author.clone())
+                    on:input=move |ev| edit.update(|edit| edit.author = event_target_value(&ev))
+                />
+            </div>
+            <div class="settings-row">
+                <label class="settings-sublabel" for="review-skip-start">"Skip at start"</label>
+                <input
+                    id="review-skip-start"
+                    class="search-input review-count"
+                    type="number"
+                    min="0"
+                    max=paragraph_count
+                    prop:value=move || edit.with(|edit| edit.skip_start.to_string())
+                    on:input=move |ev| set_count(ev, |edit| &mut edit.skip_start)
+                />
+                <span class="card-meta">"paragraphs"</span>
+            </div>
+            <div class="settings-row">
+                <label class="settings-sublabel" for="review-skip-end">"Skip at end"</label>
+                <input
+                    id="review-skip-end"
+                    class="search-input review-count"
+                    type="number"
+                    min="0"
+                    max=paragraph_count
+                    prop:value=move || edit.with(|edit| edit.skip_end.to_string())
+                    on:input=move |ev| set_count(ev, |edit| &mut edit.skip_end)
+                />
+                <span class="card-meta">"paragraphs"</span>
+            </div>
+            <label class="settings-sublabel" for="review-remove">"Remove lines"</label>
+            <textarea
+                id="review-remove"
+                class="note-input"
+                rows="3"
+                placeholder="One per line, such as a running header. # matches any number, as in Page #."
+                prop:value=move || edit.with(|edit| edit.remove.clone())
+                on:input=move |ev| edit.update(|edit| edit.remove = event_target_value(&ev))
+            ></textarea>
+
+            <p class="card-meta" aria-live="polite">{word_summary}</p>
+            <div class="review-preview">
+                {move || shown.with(|shown| {
+                    let start = shown
+                        .start
+                        .iter()
+                        .map(|paragraph| view! { <p>{paragraph.clone()}</p> })
+                        .collect_view();
+                    let end = (!shown.end.is_empty()).then(|| {
+                        let end = shown
+                            .end
+                            .iter()
+                            .map(|paragraph| view! { <p>{paragraph.clone()}</p> })
+                            .collect_view();
+                        view! {
+                            <p class="review-gap" aria-hidden="true">"…"</p>
+                            {end}
+                        }
+                    });
+                    view! { {start} {end} }
+                })}
+            </div>
+
+            <div class="settings-confirm">
+                <button
+                    class="btn btn-primary"
+                    prop:disabled=move || edited.with(|edited| edited.document.word_count == 0)
+                    on:click=move |_| on_save(edited.get_untracked())
+                >
+                    "Save"
+                </button>
+                <button class="btn" on:click=move |_| on_cancel()>"Cancel"</button>
+            </div>
+        </div>
+    }
+}
+
+/// Edits a saved document from its library card.
+#[component]
+pub fn EditDocumentModal(
+    doc_id: String,
+    on_close: impl Fn() + Clone + Send + Sync + 'static,
+) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
+    let document = RwSignal::new(None::<Document>);
+    let (error, set_error) = signal::<Option<String>>(None);
+
+    let storage = store.get();
+    spawn_local(async move {
+        match storage.get_document(&doc_id).await {
+            Ok(Some(doc)) => {
+                let _ = document.try_set(Some(doc));
+            }
+            Ok(None) => {
+                let _ = set_error.try_set(Some("This document no longer exists.".into()));
+            }
+            Err(e) => {
+                let _ = set_error.try_set(Some(format!("Could not open the document: {}", e)));
+            }
+        }
+    });
+
+    let on_backdrop_click = on_close.clone();
+    let on_escape = on_close.clone();
+    let on_close_button = on_close.clone();
+
+    let review = move || {
+        document.get().map(|doc| {
+            let on_saved = on_close.clone();
+            let on_cancel = on_close.clone();
+            view! {
+                <DocumentReview
+                    document=doc
+                    on_save=move |edited: EditedDocument| {
+                        let on_saved = on_saved.clone();
+                        let storage = store.get();
+                        spawn_local(async move {
+                            match save_edit(state, storage.as_ref(), &edited).await {
+                                Ok(()) => on_saved(),
+                                Err(e) => {
+                                    let _ = set_error.try_set(Some(format!("Could not save: {}", e)));
+                                }
+                            }
+                        });
+                    }
+                    on_cancel=move || on_cancel()
+                />
+            }
+        })
+    };
+
+    view! {
+        <div
+            class="modal-backdrop"
+            on:click=move |_| on_backdrop_click()
+            on:keydown=move |event: web_sys::KeyboardEvent| {
+                if event.key() == "Escape" {
+                    on_escape();
+                }
+            }
+        >
+            <div
+                class="modal glass-panel animate-scale-in"
+                role="dialog"
+                aria-modal="true"
+                aria-labelledby="edit-dialog-title"
+                on:click=|e| e.stop_propagation()
+            >
+                <div class="modal-header">
+                    <h2 id="edit-dialog-title">"Edit Document"</h2>
+                    <button
+                        class="btn-close"
+                        aria-label="Close edit dialog"
+                        on:click=move |_| on_close_button()
+                    >"X"</button>
+                </div>
+                {review}
+                <Show when=move || error.get().is_some()>
+                    <p class="upload-error" role="alert">{move || error.get().unwrap_or_default()}</p>
+                </Show>
+            </div>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::save_edit;
+    use crate::import::DocumentEdit;
+    use crate::state::{Annotation, AnnotationKind, AppState, Document, ReadingProgress, Settings};
+    use crate::storage::{DocumentStore, MemoryStore};
+    use crate::tokens::Block;
+    use futures::executor::block_on;
+    use leptos::prelude::*;
+
+    #[test]
+    fn edits_move_positions_and_annotations_with_the_text() {
+        let store = MemoryStore::new();
+        let doc = Document::new(
+            "Scan".into(),
+            vec![
+                Block::paragraph("Front matter"),
+                Block::paragraph("One two three four."),
+            ],
+            "PDF".into(),
+        );
+        block_on(store.save_document(&doc)).unwrap();
+        let mut progress = ReadingProgress::new(doc.id.clone(), 4, doc.word_count);
+        progress.updated_at = "2024-01-01T00:00:00+00:00".into();
+        block_on(store.save_progress(&progress)).unwrap();
+        let on_removed = Annotation::new(
+            doc.id.clone(),
+            AnnotationKind::Bookmark,
+            0,
+            1,
+            "Front matter".into(),
+        );
+        let kept = Annotation::new(
+            doc.id.clone(),
+            AnnotationKind::Highlight,
+            2,
+            6,
+            "One two three four.".into(),
+        );
+        block_on(store.save_annotation(&on_removed)).unwrap();
+        block_on(store.save_annotation(&kept)).unwrap();
+        let state = AppState::new(Settings::default());
+        state.add_document(doc.meta());
+
+        let edit = DocumentEdit {
+            title: "Four Words".into(),
+            skip_start: 1,
+            ..DocumentEdit::new(&doc)
+        };
+        block_on(save_edit(state, &store, &edit.apply(&doc))).unwrap();
+
+        let saved = block_on(store.get_document(&doc.id)).unwrap().unwrap();
+        assert_eq!(saved.title, "Four Words");
+        assert_eq!(saved.content, "One two three four.");
+        assert_eq!(state.documents.get_untracked(), vec![saved.meta()]);
+
+        let moved = block_on(store.get_progress(&doc.id)).unwrap().unwrap();
+        assert_eq!((moved.word_index, moved.word_count), (2, 4));
+        assert_eq!(state.progress.get_untracked().get(&doc.id), Some(&moved));
+
+        let annotations = block_on(store.get_annotations(&doc.id)).unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!((annotations[0].start, annotations[0].end), (0, 4));
+    }
+}
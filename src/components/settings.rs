@@ -0,0 +1,164 @@
+use crate::state::{AppState, CHUNK_SIZES, FONT_SIZES, MAX_WPM, MIN_WPM};
+use crate::timing::TimingConfig;
+use leptos::prelude::*;
+
+/// A 0–3 range input for one extra-dwell value, shown as a multiple of a word.
+fn dwell_slider(
+    state: AppState,
+    id: &'static str,
+    label: &'static str,
+    get: fn(&TimingConfig) -> f64,
+    set: fn(&mut TimingConfig, f64),
+) -> impl IntoView {
+    let timing = state.timing;
+
+    view! {
+        <div class="settings-row">
+            <label class="settings-sublabel" for=id>{label}</label>
+            <input
+                id=id
+                type="range"
+                min="0"
+                max="3"
+                step="0.1"
+                prop:disabled=move || !timing.with(|t| t.enabled)
+                prop:value=move || timing.with(|t| get(t).to_string())
+                on:input=move |ev| {
+                    if let Ok(value) = event_target_value(&ev).parse::<f64>() {
+                        state.update_timing(|t| set(t, value));
+                    }
+                }
+            />
+            <span class="wpm-display">{move || format!("+{:.1}", timing.with(get))}</span>
+        </div>
+    }
+}
+
+#[component]
+pub fn Settings() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let wpm = state.wpm;
+    let font_size = state.font_size;
+    let chunk_size = state.chunk_size;
+    let timing = state.timing;
+    let (show_reset_confirm, set_show_reset_confirm) = signal(false);
+
+    let on_wpm_input = move |ev: leptos::ev::Event| {
+        if let Ok(value) = event_target_value(&ev).parse::<u32>() {
+            state.set_wpm(value);
+        }
+    };
+
+    view! {
+        <div class="settings-container">
+            <header class="library-header glass-panel">
+                <h1 class="library-title">"SETTINGS"</h1>
+                <div class="header-actions">
+                    <a href="/" class="btn">"Library"</a>
+                </div>
+            </header>
+
+            <div class="settings-panel glass-panel animate-fade-in">
+                <section class="settings-section">
+                    <label class="settings-label" for="settings-wpm">"Reading speed"</label>
+                    <div class="settings-row">
+                        <input
+                            id="settings-wpm"
+                            type="range"
+                            min=MIN_WPM
+                            max=MAX_WPM
+                            step="10"
+                            prop:value=move || wpm.get().to_string()
+                            on:input=on_wpm_input
+                        />
+                        <span class="wpm-display">{move || format!("{} WPM", wpm.get())}</span>
+                    </div>
+                </section>
+
+                <section class="settings-section">
+                    <span class="settings-label">"Font size"</span>
+                    <div class="font-size-controls">
+                        {FONT_SIZES
+                            .into_iter()
+                            .map(|size| {
+                                view! {
+                                    <button
+                                        class=move || if font_size.get() == size { "btn btn-active" } else { "btn" }
+                                        on:click=move |_| state.set_font_size(size.to_string())
+                                    >
+                                        {size}
+                                    </button>
+                                }
+                            })
+                            .collect_view()}
+                    </div>
+                </section>
+
+                <section class="settings-section">
+                    <span class="settings-label">"Words per flash"</span>
+                    <div class="word-group-controls">
+                        {CHUNK_SIZES
+                            .into_iter()
+                            .map(|size| {
+                                view! {
+                                    <button
+                                        class=move || if chunk_size.get() == size { "btn btn-active" } else { "btn" }
+                                        on:click=move |_| state.set_chunk_size(size)
+                                    >
+                                        {size}
+                                    </button>
+                                }
+                            })
+                            .collect_view()}
+                    </div>
+                </section>
+
+                <section class="settings-section">
+                    <span class="settings-label">"Natural timing"</span>
+                    <label class="settings-row settings-toggle">
+                        <input
+                            type="checkbox"
+                            prop:checked=move || timing.with(|t| t.enabled)
+                            on:change=move |ev| {
+                                let enabled = event_target_checked(&ev);
+                                state.update_timing(|t| t.enabled = enabled);
+                            }
+                        />
+                        "Pause longer at punctuation and long words"
+                    </label>
+                    {dwell_slider(state, "timing-sentence", "Sentence end", |t| t.sentence_end, |t, v| t.sentence_end = v)}
+                    {dwell_slider(state, "timing-clause", "Comma or clause", |t| t.clause_pause, |t, v| t.clause_pause = v)}
+                    {dwell_slider(state, "timing-paragraph", "Paragraph break", |t| t.paragraph_break, |t, v| t.paragraph_break = v)}
+                    {dwell_slider(state, "timing-number", "Numbers", |t| t.number, |t, v| t.number = v)}
+                    <p class="settings-note">"Extra time per word. The average speed stays at your selected WPM."</p>
+                </section>
+
+                <super::vault::VaultSettings />
+
+                <super::backup::BackupSettings />
+
+                <super::usage::UsageSettings />
+
+                <section class="settings-section settings-actions">
+                    <p class="settings-note">"Settings are saved in this browser."</p>
+                    <Show
+                        when=move || show_reset_confirm.get()
+                        fallback=move || view! {
+                            <button class="btn" on:click=move |_| set_show_reset_confirm.set(true)>
+                                "Reset to defaults"
+                            </button>
+                        }
+                    >
+                        <div class="settings-confirm">
+                            <button class="btn btn-danger" on:click=move |_| {
+                                state.reset_settings();
+                                set_show_reset_confirm.set(false);
+                            }>"Reset"</button>
+                            <button class="btn" on:click=move |_| set_show_reset_confirm.set(false)>"Cancel"</button>
+                        </div>
+                    </Show>
+                </section>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,121 @@
+use super::StoreContext;
+use crate::state::AppState;
+use crate::storage::{self, DocumentStore, LibraryChange, StorageError};
+use leptos::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+/// Brings `state` up to date with a change another tab made.
+async fn apply_change(
+    state: AppState,
+    store: &dyn DocumentStore,
+    change: LibraryChange,
+) -> Result<(), StorageError> {
+    match change {
+        LibraryChange::DocumentsSaved => {
+            let documents = store.get_all_metadata().await?;
+            let progress = store.get_all_progress().await?;
+            let _ = state.documents.try_set(documents);
+            for progress in progress {
+                state.merge_progress(progress);
+            }
+        }
+        LibraryChange::DocumentDeleted { id } => {
+            let _ = state
+                .documents
+                .try_update(|docs| docs.retain(|doc| doc.id != id));
+            let _ = state.progress.try_update(|all| {
+                all.remove(&id);
+            });
+        }
+        LibraryChange::ProgressSaved { document_id } => {
+            if let Some(progress) = store.get_progress(&document_id).await? {
+                state.merge_progress(progress);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies library and reading position changes made in other tabs. A locked
+/// tab ignores them and loads everything when it is unlocked.
+pub fn follow_other_tabs(state: AppState, store: StoreContext) {
+    storage::subscribe(move |change| {
+        if state.locked.get_untracked() {
+            return;
+        }
+        let store = store.get();
+        spawn_local(async move {
+            if let Err(e) = apply_change(state, store.as_ref(), change).await {
+                web_sys::console::warn_1(
+                    &format!("Failed to apply a change from another tab: {}", e).into(),
+                );
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_change;
+    use crate::state::{AppState, Document, ReadingProgress, Settings};
+    use crate::storage::{DocumentStore, LibraryChange, MemoryStore};
+    use crate::tokens::Block;
+    use futures::executor::block_on;
+    use leptos::prelude::*;
+
+    fn document(title: &str) -> Document {
+        Document::new(title.into(), vec![Block::paragraph("Text.")], "TXT".into())
+    }
+
+    fn progress(document_id: &str, word_index: usize, updated_at: &str) -> ReadingProgress {
+        let mut progress = ReadingProgress::new(document_id.into(), word_index, 1000);
+        progress.updated_at = updated_at.into();
+        progress
+    }
+
+    #[test]
+    fn documents_saved_elsewhere_are_listed() {
+        let store = MemoryStore::new();
+        let doc = document("Uploaded elsewhere");
+        block_on(store.save_document(&doc)).unwrap();
+        let state = AppState::new(Settings::default());
+
+        block_on(apply_change(state, &store, LibraryChange::DocumentsSaved)).unwrap();
+
+        assert_eq!(state.documents.get_untracked(), vec![doc.meta()]);
+    }
+
+    #[test]
+    fn documents_deleted_elsewhere_are_dropped() {
+        let store = MemoryStore::new();
+        let doc = document("Deleted elsewhere");
+        let state = AppState::new(Settings::default());
+        state.add_document(doc.meta());
+        state.set_progress(progress(&doc.id, 10, "2024-01-01T00:00:00+00:00"));
+
+        let change = LibraryChange::DocumentDeleted { id: doc.id };
+        block_on(apply_change(state, &store, change)).unwrap();
+
+        assert!(state.documents.get_untracked().is_empty());
+        assert!(state.progress.get_untracked().is_empty());
+    }
+
+    #[test]
+    fn positions_from_elsewhere_apply_only_when_later() {
+        let store = MemoryStore::new();
+        let state = AppState::new(Settings::default());
+        let change = || LibraryChange::ProgressSaved {
+            document_id: "doc".into(),
+        };
+
+        let saved = progress("doc", 100, "2024-01-01T00:00:00+00:00");
+        block_on(store.save_progress(&saved)).unwrap();
+        block_on(apply_change(state, &store, change())).unwrap();
+        assert_eq!(state.progress.get_untracked().get("doc"), Some(&saved));
+
+        let local = progress("doc", 300, "2024-03-01T00:00:00+00:00");
+        state.set_progress(local.clone());
+        block_on(apply_change(state, &store, change())).unwrap();
+        assert_eq!(state.progress.get_untracked().get("doc"), Some(&local));
+    }
+}
@@ -1,23 +1,116 @@
 use leptos::prelude::*;
+use std::collections::HashMap;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::HtmlInputElement;
+use web_sys::{File, HtmlInputElement};
 
-use crate::parser::parse_file;
-use crate::state::AppState;
-use crate::storage::Storage;
+use super::review::DocumentReview;
+use super::StoreContext;
+use crate::import::{EditedDocument, ImportQueue, ImportStatus};
+use crate::parser::{parse_file, parse_pasted_text, pasted_title, PdfCleanup};
+use crate::state::{AppState, Document};
+use crate::storage::DocumentStore;
+
+/// Where the upload dialog takes documents from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ImportMode {
+    Files,
+    Paste,
+}
+
+/// Files waiting in the import queue, with the PDF cleanup chosen when they
+/// were added, by queue id.
+type PendingFiles = StoredValue<HashMap<usize, (File, PdfCleanup)>, LocalStorage>;
+
+/// The setting a PDF cleanup rule toggles.
+type CleanupRule = fn(&mut PdfCleanup) -> &mut bool;
+
+/// PDF cleanup rules offered at import.
+const PDF_CLEANUP_RULES: [(&str, CleanupRule); 4] = [
+    ("Remove repeated headers and footers", |c| {
+        &mut c.repeated_lines
+    }),
+    ("Remove page numbers", |c| &mut c.page_numbers),
+    ("Rejoin hyphenated words", |c| &mut c.hyphenation),
+    ("Rebuild paragraphs across lines and pages", |c| {
+        &mut c.paragraphs
+    }),
+];
+
+/// Parsed files waiting for review, by queue id.
+type ParsedDocuments = StoredValue<HashMap<usize, Document>>;
+
+/// Saves a parsed document and lists it, returning its title. The reader opens
+/// documents from storage, so an unsaved document is not listed.
+async fn add_document(
+    state: AppState,
+    store: &dyn DocumentStore,
+    doc: Document,
+) -> Result<String, String> {
+    store
+        .save_document(&doc)
+        .await
+        .map_err(|e| format!("Could not save {}: {}", doc.title, e))?;
+    state.add_document(doc.meta());
+    Ok(doc.title)
+}
+
+/// Parses one queued file and holds it for review.
+async fn parse_queued_file(
+    queue: RwSignal<ImportQueue>,
+    parsed: ParsedDocuments,
+    id: usize,
+    file: File,
+    cleanup: PdfCleanup,
+) {
+    match parse_file(file, cleanup).await {
+        // A file cancelled while it was parsed is dropped here.
+        Ok(doc) => {
+            if queue.try_update(|queue| queue.update(id, ImportStatus::Ready)) == Some(true) {
+                let _ = parsed.try_update_value(|parsed| parsed.insert(id, doc));
+            }
+        }
+        Err(e) => {
+            let _ = queue.try_update(|queue| queue.update(id, ImportStatus::Failed(e.to_string())));
+        }
+    }
+}
+
+/// Starts queued files while there is room, and again as each one is parsed.
+/// Files still queued when the dialog closes are not imported.
+fn run_queue(queue: RwSignal<ImportQueue>, pending: PendingFiles, parsed: ParsedDocuments) {
+    let Some(started) = queue.try_update(|queue| queue.start_next()) else {
+        return;
+    };
+    for id in started {
+        let Some((file, cleanup)) = pending
+            .try_update_value(|files| files.remove(&id))
+            .flatten()
+        else {
+            continue;
+        };
+        spawn_local(async move {
+            parse_queued_file(queue, parsed, id, file, cleanup).await;
+            run_queue(queue, pending, parsed);
+        });
+    }
+}
 
 #[component]
 pub fn Upload() -> impl IntoView {
     view! {
         <div class="upload-container">
-            <UploadZone />
+            <UploadZone pasted=None />
         </div>
     }
 }
 
+/// `pasted` opens the dialog on the paste form with that text.
 #[component]
-pub fn UploadModal(on_close: impl Fn() + Clone + Send + Sync + 'static) -> impl IntoView {
+pub fn UploadModal(
+    on_close: impl Fn() + Clone + Send + Sync + 'static,
+    pasted: Option<String>,
+) -> impl IntoView {
     let on_backdrop_click = on_close.clone();
     let on_escape = on_close.clone();
     let close_button = NodeRef::<leptos::html::Button>::new();
@@ -44,7 +137,7 @@ pub fn UploadModal(on_close: impl Fn() + Clone + Send + Sync + 'static) -> impl
                 on:click=|e| e.stop_propagation()
             >
                 <div class="modal-header">
-                    <h2 id="upload-dialog-title">"Upload Document"</h2>
+                    <h2 id="upload-dialog-title">"Add Document"</h2>
                     <button
                         class="btn-close"
                         aria-label="Close upload dialog"
@@ -52,58 +145,121 @@ pub fn UploadModal(on_close: impl Fn() + Clone + Send + Sync + 'static) -> impl
                         on:click=move |_| on_close()
                     >"X"</button>
                 </div>
-                <UploadZone />
+                <UploadZone pasted=pasted />
             </div>
         </div>
     }
 }
 
 #[component]
-fn UploadZone() -> impl IntoView {
+fn UploadZone(pasted: Option<String>) -> impl IntoView {
     let state = expect_context::<AppState>();
+    let store = expect_context::<StoreContext>();
+    let mode = RwSignal::new(if pasted.is_some() {
+        ImportMode::Paste
+    } else {
+        ImportMode::Files
+    });
     let (is_dragging, set_dragging) = signal(false);
     let (is_loading, set_loading) = signal(false);
     let (error, set_error) = signal::<Option<String>>(None);
     let (success, set_success) = signal::<Option<String>>(None);
+    let (paste_text, set_paste_text) = signal(pasted.unwrap_or_default());
+    let (paste_title, set_paste_title) = signal(String::new());
+    let paste_input = NodeRef::<leptos::html::Textarea>::new();
 
-    let handle_files = move |files: web_sys::FileList| {
-        set_loading.set(true);
-        set_error.set(None);
-        set_success.set(None);
+    paste_input.on_load(|textarea| {
+        let _ = textarea.focus();
+    });
+
+    let cleanup = RwSignal::new(PdfCleanup::default());
+    let queue = RwSignal::new(ImportQueue::default());
+    let pending: PendingFiles = StoredValue::new_local(HashMap::new());
+    let parsed: ParsedDocuments = StoredValue::new(HashMap::new());
+    // The parsed file being reviewed, by queue id.
+    let reviewing = RwSignal::new(None::<usize>);
 
+    let handle_files = move |files: web_sys::FileList| {
         for i in 0..files.length() {
             if let Some(file) = files.get(i) {
-                spawn_local(async move {
-                    match parse_file(file).await {
-                        Ok(doc) => {
-                            let doc_title = doc.title.clone();
-                            let storage = Storage::new();
-
-                            // Save to IndexedDB
-                            match storage.save_document(&doc).await {
-                                Ok(_) => {
-                                    state.add_document(doc);
-                                    set_success.set(Some(format!("Added: {}", doc_title)));
-                                }
-                                Err(e) => {
-                                    // Still add to state even if storage fails
-                                    state.add_document(doc);
-                                    web_sys::console::warn_1(
-                                        &format!("Storage error: {}", e).into(),
-                                    );
-                                    set_success
-                                        .set(Some(format!("Added: {} (not persisted)", doc_title)));
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            set_error.set(Some(e.to_string()));
-                        }
-                    }
-                    set_loading.set(false);
-                });
+                if let Some(id) = queue.try_update(|queue| queue.add(file.name())) {
+                    pending.update_value(|files| {
+                        files.insert(id, (file, cleanup.get_untracked()));
+                    });
+                }
             }
         }
+        run_queue(queue, pending, parsed);
+    };
+
+    let on_cancel = move |id: usize| {
+        queue.update(|queue| queue.cancel(id));
+        pending.update_value(|files| {
+            files.remove(&id);
+        });
+        parsed.update_value(|parsed| {
+            parsed.remove(&id);
+        });
+    };
+
+    let on_save_reviewed = move |id: usize, edited: EditedDocument| {
+        reviewing.set(None);
+        parsed.update_value(|parsed| {
+            parsed.remove(&id);
+        });
+        if queue.try_update(|queue| queue.start_saving(id)) != Some(true) {
+            return;
+        }
+        let storage = store.get();
+        spawn_local(async move {
+            let status = match add_document(state, storage.as_ref(), edited.document).await {
+                Ok(title) => ImportStatus::Done(title),
+                Err(e) => ImportStatus::Failed(e),
+            };
+            let _ = queue.try_update(|queue| queue.update(id, status));
+            run_queue(queue, pending, parsed);
+        });
+    };
+
+    let review = move || {
+        let id = reviewing.get()?;
+        let doc = parsed.with_value(|parsed| parsed.get(&id).cloned())?;
+        Some(view! {
+            <DocumentReview
+                document=doc
+                on_save=move |edited| on_save_reviewed(id, edited)
+                on_cancel=move || reviewing.set(None)
+            />
+        })
+    };
+
+    let on_add_pasted = move |_| {
+        set_error.set(None);
+        set_success.set(None);
+        let doc = match paste_text
+            .with(|text| paste_title.with(|title| parse_pasted_text(text, title)))
+        {
+            Ok(doc) => doc,
+            Err(e) => {
+                set_error.set(Some(e.to_string()));
+                return;
+            }
+        };
+        set_loading.set(true);
+        let storage = store.get();
+        spawn_local(async move {
+            match add_document(state, storage.as_ref(), doc).await {
+                Ok(title) => {
+                    let _ = set_paste_text.try_set(String::new());
+                    let _ = set_paste_title.try_set(String::new());
+                    let _ = set_success.try_set(Some(format!("Added: {}", title)));
+                }
+                Err(e) => {
+                    let _ = set_error.try_set(Some(e));
+                }
+            }
+            let _ = set_loading.try_set(false);
+        });
     };
 
     let on_change = move |ev: leptos::ev::Event| {
@@ -141,21 +297,48 @@ fn UploadZone() -> impl IntoView {
         }
     };
 
+    let mode_class = move |button_mode: ImportMode| {
+        if mode.get() == button_mode {
+            "btn btn-active"
+        } else {
+            "btn"
+        }
+    };
+
+    let title_placeholder = move || {
+        let title = paste_text.with(|text| pasted_title(text));
+        if title.is_empty() {
+            "Taken from the first line".to_string()
+        } else {
+            title
+        }
+    };
+
     view! {
-        <div
-            class=zone_class
-            on:drop=on_drop
-            on:dragover=on_drag_over
-            on:dragleave=on_drag_leave
-        >
-            <Show when=move || is_loading.get()>
-                <div class="upload-loading">
-                    <div class="spinner"></div>
-                    <p>"Processing..."</p>
-                </div>
-            </Show>
+        <div class="import-modes" role="group" aria-label="Import from">
+            <button
+                class=move || mode_class(ImportMode::Files)
+                aria-pressed=move || (mode.get() == ImportMode::Files).to_string()
+                on:click=move |_| mode.set(ImportMode::Files)
+            >"Files"</button>
+            <button
+                class=move || mode_class(ImportMode::Paste)
+                aria-pressed=move || (mode.get() == ImportMode::Paste).to_string()
+                on:click=move |_| mode.set(ImportMode::Paste)
+            >"Paste text"</button>
+        </div>
+
+        <Show when=move || mode.get() == ImportMode::Files && reviewing.get().is_some()>
+            {review}
+        </Show>
 
-            <Show when=move || !is_loading.get()>
+        <Show when=move || mode.get() == ImportMode::Files && reviewing.get().is_none()>
+            <div
+                class=zone_class
+                on:drop=on_drop
+                on:dragover=on_drag_over
+                on:dragleave=on_drag_leave
+            >
                 <div class="upload-icon">
                     <svg width="48" height="48" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="1.5">
                         <path d="M21 15v4a2 2 0 01-2 2H5a2 2 0 01-2-2v-4M17 8l-5-5-5 5M12 3v12"/>
@@ -167,22 +350,114 @@ fn UploadZone() -> impl IntoView {
                     "Browse Files"
                     <input
                         type="file"
-                        accept=".pdf,.txt,.docx,.md"
+                        accept=".pdf,.txt,.docx,.md,.epub"
                         multiple
                         on:change=on_change
                         style="display: none"
                     />
                 </label>
-                <p class="upload-formats">"TXT, MD, PDF, DOCX supported."</p>
-            </Show>
+                <p class="upload-formats">"TXT, MD, PDF, DOCX, EPUB supported."</p>
+            </div>
 
-            <Show when=move || error.get().is_some()>
-                <p class="upload-error" role="alert">{move || error.get().unwrap_or_default()}</p>
-            </Show>
+            <fieldset class="pdf-cleanup">
+                <legend class="settings-sublabel">"PDF cleanup"</legend>
+                {PDF_CLEANUP_RULES
+                    .into_iter()
+                    .map(|(label, rule)| view! {
+                        <label class="settings-row settings-toggle">
+                            <input
+                                type="checkbox"
+                                prop:checked=move || *rule(&mut cleanup.get())
+                                on:change=move |ev| {
+                                    let on = event_target_checked(&ev);
+                                    cleanup.update(|cleanup| *rule(cleanup) = on);
+                                }
+                            />
+                            {label}
+                        </label>
+                    })
+                    .collect_view()}
+            </fieldset>
 
-            <Show when=move || success.get().is_some()>
-                <p class="upload-success" aria-live="polite">{move || success.get().unwrap_or_default()}</p>
-            </Show>
-        </div>
+            <ul class="import-queue" aria-live="polite">
+                {move || queue.with(|queue| {
+                    queue
+                        .items()
+                        .iter()
+                        .map(|item| {
+                            let id = item.id;
+                            let status_class = match item.status {
+                                ImportStatus::Done(_) => "import-status upload-success",
+                                ImportStatus::Failed(_) => "import-status upload-error",
+                                _ => "import-status",
+                            };
+                            view! {
+                                <li class="import-item">
+                                    <span class="import-name">{item.name.clone()}</span>
+                                    <span class=status_class>{item.status.label()}</span>
+                                    {(item.status == ImportStatus::Ready).then(|| view! {
+                                        <button class="btn btn-primary" on:click=move |_| reviewing.set(Some(id))>
+                                            "Review"
+                                        </button>
+                                    })}
+                                    {item.can_cancel().then(|| view! {
+                                        <button class="btn" on:click=move |_| on_cancel(id)>
+                                            "Cancel"
+                                        </button>
+                                    })}
+                                </li>
+                            }
+                        })
+                        .collect_view()
+                })}
+            </ul>
+            {move || queue.with(ImportQueue::summary).map(|summary| view! {
+                <p class="upload-success" aria-live="polite">{summary}</p>
+            })}
+        </Show>
+
+        <Show when=move || mode.get() == ImportMode::Paste>
+            <div class="paste-form">
+                <div class="settings-row">
+                    <label class="settings-sublabel" for="paste-title">"Title"</label>
+                    <input
+                        id="paste-title"
+                        class="search-input"
+                        type="text"
+                        placeholder=title_placeholder
+                        prop:value=move || paste_title.get()
+                        on:input=move |ev| set_paste_title.set(event_target_value(&ev))
+                    />
+                </div>
+                <textarea
+                    node_ref=paste_input
+                    class="note-input paste-input"
+                    rows="12"
+                    aria-label="Text to import"
+                    placeholder="Paste an email, a chat thread, or an article."
+                    prop:value=move || paste_text.get()
+                    on:input=move |ev| set_paste_text.set(event_target_value(&ev))
+                ></textarea>
+                <div class="settings-confirm">
+                    <button
+                        class="btn btn-primary"
+                        prop:disabled=move || {
+                            is_loading.get() || paste_text.with(|text| text.trim().is_empty())
+                        }
+                        on:click=on_add_pasted
+                    >
+                        "Add to library"
+                    </button>
+                </div>
+            </div>
+        </Show>
+
+        <Show when=move || error.get().is_some()>
+            <p class="upload-error" role="alert">{move || error.get().unwrap_or_default()}</p>
+        </Show>
+
+        <Show when=move || success.get().is_some()>
+            <p class="upload-success" aria-live="polite">{move || success.get().unwrap_or_default()}</p>
+        </Show>
     }
 }
@@ -0,0 +1,81 @@
+use super::StoreContext;
+use crate::storage::{format_bytes, StorageUsage};
+use leptos::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+fn store_label(store: &str) -> &'static str {
+    match store {
+        "metadata" => "Library entries",
+        "content" => "Document text",
+        "progress" => "Reading positions",
+        "annotations" => "Bookmarks and highlights",
+        _ => "Old-format documents",
+    }
+}
+
+/// How much space the library takes and what compression saves.
+#[component]
+pub fn UsageSettings() -> impl IntoView {
+    let store = expect_context::<StoreContext>();
+    let (usage, set_usage) = signal(None::<Result<StorageUsage, String>>);
+    let (is_busy, set_busy) = signal(false);
+
+    let on_measure = move |_| {
+        set_busy.set(true);
+        let store = store.get();
+        spawn_local(async move {
+            let result = store
+                .storage_usage()
+                .await
+                .map_err(|e| format!("Could not measure storage: {}", e));
+            let _ = set_usage.try_set(Some(result));
+            let _ = set_busy.try_set(false);
+        });
+    };
+
+    view! {
+        <section class="settings-section">
+            <span class="settings-label">"Storage"</span>
+            <p class="settings-note">
+                "Document text is compressed before it is encrypted. Measuring decrypts each \
+                 document once."
+            </p>
+            <div class="settings-confirm">
+                <button class="btn" prop:disabled=move || is_busy.get() on:click=on_measure>
+                    "Measure storage"
+                </button>
+            </div>
+            {move || usage.get().map(|result| match result {
+                Ok(usage) => view! {
+                    <dl class="usage-list">
+                        {usage
+                            .stores
+                            .iter()
+                            .map(|store| view! {
+                                <dt>{store_label(store.store)}</dt>
+                                <dd>{format!(
+                                    "{}, {} record{}",
+                                    format_bytes(store.bytes),
+                                    store.records,
+                                    if store.records == 1 { "" } else { "s" }
+                                )}</dd>
+                            })
+                            .collect_view()}
+                        <dt>"Total"</dt>
+                        <dd>{format_bytes(usage.total_bytes())}</dd>
+                    </dl>
+                    <p class="settings-note">
+                        {format!(
+                            "Compression saves {} ({}%) of {} of document text.",
+                            format_bytes(usage.saved_bytes()),
+                            usage.saved_percent(),
+                            format_bytes(usage.content_bytes),
+                        )}
+                    </p>
+                }
+                .into_any(),
+                Err(e) => view! { <p class="settings-error">{e}</p> }.into_any(),
+            })}
+        </section>
+    }
+}
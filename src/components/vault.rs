@@ -0,0 +1,306 @@
+use crate::state::{AppState, AUTO_LOCK_MINUTES};
+use crate::storage;
+use leptos::prelude::*;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How often inactivity is checked against the auto-lock setting.
+const AUTO_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+thread_local! {
+    static LAST_ACTIVITY_MS: Cell<f64> = Cell::new(js_sys::Date::now());
+}
+
+/// Resets the auto-lock timer. Input events call this, and so does playback,
+/// so reading without touching anything does not lock the vault.
+pub fn note_activity() {
+    LAST_ACTIVITY_MS.with(|last| last.set(js_sys::Date::now()));
+}
+
+/// Shows the unlock screen and forgets the data key and everything decrypted
+/// with it.
+pub fn lock_vault(state: AppState) {
+    state.locked.set(true);
+    // Clear after the routes unmount, so the reader can still save its position.
+    set_timeout(
+        move || {
+            storage::lock();
+            state.documents.set(Vec::new());
+            state.progress.set(HashMap::new());
+            state.current_tokens.set(Vec::new());
+        },
+        Duration::ZERO,
+    );
+}
+
+/// Follows the vault being turned on or off in another tab. A tab left without
+/// the key shows the unlock screen, and a locked tab whose vault was turned
+/// off opens again.
+pub fn follow_vault_changes(state: AppState) {
+    storage::subscribe_vault_changes(move || {
+        if !storage::vault_enabled() {
+            // Forget the old session key, so turning the vault on again locks this tab.
+            storage::lock();
+        }
+        let locked = storage::is_locked();
+        if locked == state.locked.get_untracked() {
+            return;
+        }
+        if locked {
+            lock_vault(state);
+        } else {
+            note_activity();
+            state.locked.set(false);
+        }
+    });
+}
+
+/// Locks the vault after the configured minutes without input.
+pub fn watch_inactivity(state: AppState) {
+    for event in ["pointerdown", "keydown", "wheel", "touchstart"] {
+        // Listeners live as long as the page.
+        let _ = window_event_listener_untyped(event, |_| note_activity());
+    }
+
+    set_interval(
+        move || {
+            let minutes = state.auto_lock_minutes.get_untracked();
+            if minutes == 0 || state.locked.get_untracked() || !storage::vault_enabled() {
+                return;
+            }
+            let idle_ms = js_sys::Date::now() - LAST_ACTIVITY_MS.with(Cell::get);
+            if idle_ms >= f64::from(minutes) * 60_000.0 {
+                lock_vault(state);
+            }
+        },
+        AUTO_LOCK_CHECK_INTERVAL,
+    );
+}
+
+#[component]
+pub fn Unlock() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let (passphrase, set_passphrase) = signal(String::new());
+    let (error, set_error) = signal(None::<String>);
+    let (is_unlocking, set_unlocking) = signal(false);
+
+    let on_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        if is_unlocking.get_untracked() {
+            return;
+        }
+        set_unlocking.set(true);
+        set_error.set(None);
+        // Key derivation blocks the page for a moment; let "Unlocking" show first.
+        set_timeout(
+            move || {
+                let result = passphrase.with_untracked(|p| storage::unlock(p));
+                set_unlocking.set(false);
+                match result {
+                    Ok(()) => {
+                        set_passphrase.set(String::new());
+                        note_activity();
+                        state.locked.set(false);
+                    }
+                    Err(e) => set_error.set(Some(e.to_string())),
+                }
+            },
+            Duration::from_millis(50),
+        );
+    };
+
+    view! {
+        <div class="unlock-container">
+            <form class="unlock-panel glass-panel animate-fade-in" on:submit=on_submit>
+                <h1 class="library-title">"NABU"</h1>
+                <label class="settings-label" for="unlock-passphrase">"Passphrase"</label>
+                <input
+                    id="unlock-passphrase"
+                    class="search-input"
+                    type="password"
+                    autocomplete="current-password"
+                    autofocus=true
+                    prop:value=move || passphrase.get()
+                    on:input=move |ev| set_passphrase.set(event_target_value(&ev))
+                />
+                <button type="submit" class="btn btn-primary" prop:disabled=move || is_unlocking.get()>
+                    {move || if is_unlocking.get() { "Unlocking..." } else { "Unlock" }}
+                </button>
+                {move || error.get().map(|e| view! { <p class="settings-error">{e}</p> })}
+                <p class="settings-note">
+                    "Your library is encrypted with your passphrase. It cannot be recovered without it."
+                </p>
+            </form>
+        </div>
+    }
+}
+
+fn minutes_label(minutes: u32) -> String {
+    match minutes {
+        0 => "Never".to_string(),
+        m => format!("After {} minutes", m),
+    }
+}
+
+/// Turns the vault on or off, changes its passphrase, and sets auto-lock.
+#[component]
+pub fn VaultSettings() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let (enabled, set_enabled) = signal(storage::vault_enabled());
+    let (current, set_current) = signal(String::new());
+    let (new_passphrase, set_new_passphrase) = signal(String::new());
+    let (confirm, set_confirm) = signal(String::new());
+    let (message, set_message) = signal(None::<Result<&'static str, String>>);
+    let (is_busy, set_busy) = signal(false);
+
+    let clear_fields = move || {
+        set_current.set(String::new());
+        set_new_passphrase.set(String::new());
+        set_confirm.set(String::new());
+    };
+
+    let check_new_passphrase = move || -> Result<String, String> {
+        let new_passphrase = new_passphrase.get_untracked();
+        if new_passphrase.chars().count() < storage::MIN_PASSPHRASE_CHARS {
+            return Err(format!(
+                "Use at least {} characters.",
+                storage::MIN_PASSPHRASE_CHARS
+            ));
+        }
+        if new_passphrase != confirm.get_untracked() {
+            return Err("The passphrases do not match.".to_string());
+        }
+        Ok(new_passphrase)
+    };
+
+    // Key derivation blocks the page for a moment; let "Working" show first.
+    let run = move |action: Box<dyn FnOnce() -> Result<&'static str, String>>| {
+        set_busy.set(true);
+        set_message.set(None);
+        set_timeout(
+            move || {
+                let result = action();
+                if result.is_ok() {
+                    clear_fields();
+                    set_enabled.set(storage::vault_enabled());
+                }
+                set_message.set(Some(result));
+                set_busy.set(false);
+            },
+            Duration::from_millis(50),
+        );
+    };
+
+    let on_enable = move |_| match check_new_passphrase() {
+        Ok(passphrase) => run(Box::new(move || {
+            storage::enable_vault(&passphrase)
+                .map(|_| "Vault turned on.")
+                .map_err(|e| e.to_string())
+        })),
+        Err(e) => set_message.set(Some(Err(e))),
+    };
+
+    let on_change = move |_| match check_new_passphrase() {
+        Ok(passphrase) => {
+            let current = current.get_untracked();
+            run(Box::new(move || {
+                storage::change_passphrase(&current, &passphrase)
+                    .map(|_| "Passphrase changed.")
+                    .map_err(|e| e.to_string())
+            }))
+        }
+        Err(e) => set_message.set(Some(Err(e))),
+    };
+
+    let on_disable = move |_| {
+        let current = current.get_untracked();
+        run(Box::new(move || {
+            storage::disable_vault(&current)
+                .map(|_| "Vault turned off.")
+                .map_err(|e| e.to_string())
+        }))
+    };
+
+    let passphrase_input = move |id: &'static str,
+                                 label: &'static str,
+                                 autocomplete: &'static str,
+                                 value: ReadSignal<String>,
+                                 set_value: WriteSignal<String>| {
+        view! {
+            <div class="settings-row">
+                <label class="settings-sublabel" for=id>{label}</label>
+                <input
+                    id=id
+                    class="search-input"
+                    type="password"
+                    autocomplete=autocomplete
+                    prop:value=move || value.get()
+                    on:input=move |ev| set_value.set(event_target_value(&ev))
+                />
+            </div>
+        }
+    };
+
+    view! {
+        <section class="settings-section">
+            <span class="settings-label">"Passphrase vault"</span>
+            <Show
+                when=move || enabled.get()
+                fallback=move || view! {
+                    <p class="settings-note">
+                        "Protect your library with a passphrase. The encryption key is then kept only \
+                         in memory while the vault is unlocked. A forgotten passphrase cannot be recovered."
+                    </p>
+                    {passphrase_input("vault-new", "Passphrase", "new-password", new_passphrase, set_new_passphrase)}
+                    {passphrase_input("vault-confirm", "Confirm", "new-password", confirm, set_confirm)}
+                    <div class="settings-confirm">
+                        <button class="btn btn-primary" prop:disabled=move || is_busy.get() on:click=on_enable>
+                            "Turn on vault"
+                        </button>
+                    </div>
+                }
+            >
+                <div class="settings-row">
+                    <label class="settings-sublabel" for="vault-auto-lock">"Auto-lock"</label>
+                    <select
+                        id="vault-auto-lock"
+                        class="library-select"
+                        prop:value=move || state.auto_lock_minutes.get().to_string()
+                        on:change=move |ev| {
+                            if let Ok(minutes) = event_target_value(&ev).parse::<u32>() {
+                                state.auto_lock_minutes.set(minutes);
+                            }
+                        }
+                    >
+                        {AUTO_LOCK_MINUTES
+                            .into_iter()
+                            .map(|minutes| view! { <option value=minutes.to_string()>{minutes_label(minutes)}</option> })
+                            .collect_view()}
+                    </select>
+                    <button class="btn" on:click=move |_| lock_vault(state)>"Lock now"</button>
+                </div>
+                {passphrase_input("vault-current", "Current", "current-password", current, set_current)}
+                {passphrase_input("vault-new", "New", "new-password", new_passphrase, set_new_passphrase)}
+                {passphrase_input("vault-confirm", "Confirm new", "new-password", confirm, set_confirm)}
+                <div class="settings-confirm">
+                    <button class="btn" prop:disabled=move || is_busy.get() on:click=on_change>
+                        "Change passphrase"
+                    </button>
+                    <button class="btn btn-danger" prop:disabled=move || is_busy.get() on:click=on_disable>
+                        "Turn off vault"
+                    </button>
+                </div>
+                <p class="settings-note">
+                    "Changing the passphrase does not re-encrypt your documents. Turning the vault off \
+                     stores the key in this browser again; enter the current passphrase."
+                </p>
+            </Show>
+            {move || is_busy.get().then(|| view! { <p class="settings-note">"Working..."</p> })}
+            {move || message.get().map(|result| match result {
+                Ok(text) => view! { <p class="settings-note">{text}</p> }.into_any(),
+                Err(e) => view! { <p class="settings-error">{e}</p> }.into_any(),
+            })}
+        </section>
+    }
+}
@@ -0,0 +1,112 @@
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::state::{Annotation, AnnotationKind};
+
+/// Highlights and notes for one document as Markdown, in reading order.
+pub fn annotations_markdown(title: &str, annotations: &[Annotation]) -> String {
+    let mut markdown = format!("# {}\n", title.trim());
+
+    for annotation in annotations {
+        markdown.push('\n');
+        match annotation.kind {
+            AnnotationKind::Highlight => {
+                markdown.push_str(&format!("> {}\n", annotation.text));
+            }
+            AnnotationKind::Bookmark => {
+                markdown.push_str(&format!(
+                    "**Bookmark at word {}:** {}\n",
+                    annotation.start + 1,
+                    annotation.text
+                ));
+            }
+        }
+
+        let note = annotation.note.trim();
+        if !note.is_empty() {
+            markdown.push('\n');
+            markdown.push_str(note);
+            markdown.push('\n');
+        }
+    }
+
+    markdown
+}
+
+/// File name for an export of `title`, keeping only characters that are safe
+/// on common file systems.
+pub fn export_file_name(title: &str, suffix: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stem = stem.trim();
+    let stem = if stem.is_empty() { "document" } else { stem };
+    format!("{} {}", stem, suffix)
+}
+
+/// Saves `text` through the browser's download prompt.
+pub fn download_text(file_name: &str, mime_type: &str, text: &str) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let document = window.document().ok_or("no document")?;
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(text));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let link = document
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()?;
+    link.set_href(&url);
+    link.set_download(file_name);
+    link.click();
+
+    web_sys::Url::revoke_object_url(&url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotations_markdown, export_file_name};
+    use crate::state::{Annotation, AnnotationKind};
+
+    #[test]
+    fn markdown_lists_highlights_bookmarks_and_notes_in_order() {
+        let mut highlight = Annotation::new(
+            "doc".into(),
+            AnnotationKind::Highlight,
+            10,
+            14,
+            "Call me Ishmael.".into(),
+        );
+        highlight.note = "  Famous opening.\n".into();
+        let bookmark = Annotation::new(
+            "doc".into(),
+            AnnotationKind::Bookmark,
+            41,
+            42,
+            "Some years ago.".into(),
+        );
+
+        assert_eq!(
+            annotations_markdown("Moby Dick", &[highlight, bookmark]),
+            "# Moby Dick\n\n> Call me Ishmael.\n\nFamous opening.\n\n\
+             **Bookmark at word 42:** Some years ago.\n"
+        );
+    }
+
+    #[test]
+    fn file_names_drop_path_separators() {
+        assert_eq!(
+            export_file_name("../A/B: notes", "notes.md"),
+            "___A_B_ notes notes.md"
+        );
+        assert_eq!(export_file_name("  ", "notes.md"), "document notes.md");
+    }
+}
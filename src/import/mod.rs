@@ -0,0 +1,275 @@
+mod review;
+
+pub use review::{preview, DocumentEdit, EditedDocument};
+
+/// Files parsed at the same time. PDF and EPUB extraction hold the whole file
+/// in memory, so a large drop is worked through a few files at a time.
+pub const MAX_CONCURRENT_IMPORTS: usize = 2;
+
+/// Where one file is in the import.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportStatus {
+    Queued,
+    Parsing,
+    /// Parsed and waiting to be reviewed before it is saved.
+    Ready,
+    Saving,
+    /// Saved under this title.
+    Done(String),
+    Failed(String),
+    /// Cancelled while parsing; the parse still holds its slot until it ends.
+    Cancelling,
+    Cancelled,
+}
+
+impl ImportStatus {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Queued => "Queued".into(),
+            Self::Parsing => "Parsing...".into(),
+            Self::Ready => "Ready to review".into(),
+            Self::Saving => "Saving...".into(),
+            Self::Done(_) => "Added".into(),
+            Self::Failed(reason) => format!("Failed: {}", reason),
+            Self::Cancelling => "Cancelling...".into(),
+            Self::Cancelled => "Cancelled".into(),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(self, Self::Parsing | Self::Saving | Self::Cancelling)
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, Self::Done(_) | Self::Failed(_) | Self::Cancelled)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportItem {
+    pub id: usize,
+    pub name: String,
+    pub status: ImportStatus,
+}
+
+impl ImportItem {
+    /// A file can be cancelled until it is being saved; a save is not undone.
+    pub fn can_cancel(&self) -> bool {
+        matches!(
+            self.status,
+            ImportStatus::Queued | ImportStatus::Parsing | ImportStatus::Ready
+        )
+    }
+}
+
+/// Files waiting for, going through, or finished with import, in the order
+/// they were added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportQueue {
+    items: Vec<ImportItem>,
+    next_id: usize,
+}
+
+impl ImportQueue {
+    /// Queues a file and returns its id. A new batch replaces finished ones.
+    pub fn add(&mut self, name: String) -> usize {
+        if self.is_finished() {
+            self.items.clear();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.items.push(ImportItem {
+            id,
+            name,
+            status: ImportStatus::Queued,
+        });
+        id
+    }
+
+    pub fn items(&self) -> &[ImportItem] {
+        &self.items
+    }
+
+    /// Marks queued files as parsing, oldest first, while fewer than
+    /// [`MAX_CONCURRENT_IMPORTS`] are running. Files waiting for review do not
+    /// count. Returns the ids to start.
+    pub fn start_next(&mut self) -> Vec<usize> {
+        let running = self
+            .items
+            .iter()
+            .filter(|item| item.status.is_running())
+            .count();
+        let mut started = Vec::new();
+        for item in &mut self.items {
+            if running + started.len() >= MAX_CONCURRENT_IMPORTS {
+                break;
+            }
+            if item.status == ImportStatus::Queued {
+                item.status = ImportStatus::Parsing;
+                started.push(item.id);
+            }
+        }
+        started
+    }
+
+    /// Moves a running file on. Returns `false` if the file was cancelled or is
+    /// unknown, so its task can stop; a file cancelled while parsing is then
+    /// marked cancelled, freeing its slot.
+    pub fn update(&mut self, id: usize, status: ImportStatus) -> bool {
+        match self.items.iter_mut().find(|item| item.id == id) {
+            Some(item) if item.status == ImportStatus::Cancelling => {
+                item.status = ImportStatus::Cancelled;
+                false
+            }
+            Some(item) if item.status.is_running() => {
+                item.status = status;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves a reviewed file on to saving. Returns `false` if it was not
+    /// waiting for review.
+    pub fn start_saving(&mut self, id: usize) -> bool {
+        match self.items.iter_mut().find(|item| item.id == id) {
+            Some(item) if item.status == ImportStatus::Ready => {
+                item.status = ImportStatus::Saving;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancels a file. One being parsed keeps counting as running until its
+    /// parse ends, so cancelling does not start another file early.
+    pub fn cancel(&mut self, id: usize) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            if item.status == ImportStatus::Parsing {
+                item.status = ImportStatus::Cancelling;
+            } else if item.can_cancel() {
+                item.status = ImportStatus::Cancelled;
+            }
+        }
+    }
+
+    /// Whether every file has finished, one way or another.
+    pub fn is_finished(&self) -> bool {
+        self.items.iter().all(|item| item.status.is_finished())
+    }
+
+    /// What a finished batch added, failed, and skipped.
+    pub fn summary(&self) -> Option<String> {
+        if self.items.is_empty() || !self.is_finished() {
+            return None;
+        }
+        let count = |finished: fn(&ImportStatus) -> bool| {
+            self.items
+                .iter()
+                .filter(|item| finished(&item.status))
+                .count()
+        };
+        let added = count(|status| matches!(status, ImportStatus::Done(_)));
+        let failed = count(|status| matches!(status, ImportStatus::Failed(_)));
+        let cancelled = count(|status| *status == ImportStatus::Cancelled);
+
+        let mut summary = format!(
+            "Added {} document{}",
+            added,
+            if added == 1 { "" } else { "s" }
+        );
+        if failed > 0 {
+            summary.push_str(&format!("; {} failed", failed));
+        }
+        if cancelled > 0 {
+            summary.push_str(&format!("; {} cancelled", cancelled));
+        }
+        summary.push('.');
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportQueue, ImportStatus, MAX_CONCURRENT_IMPORTS};
+
+    fn queue(files: usize) -> ImportQueue {
+        let mut queue = ImportQueue::default();
+        for i in 0..files {
+            queue.add(format!("file-{}.pdf", i));
+        }
+        queue
+    }
+
+    #[test]
+    fn files_start_a_few_at_a_time_in_order() {
+        let mut queue = queue(4);
+
+        assert_eq!(queue.start_next(), vec![0, 1]);
+        assert!(queue.start_next().is_empty());
+
+        assert!(queue.update(0, ImportStatus::Ready));
+        assert_eq!(queue.start_next(), vec![2]);
+        assert!(!queue.update(0, ImportStatus::Done("File 0".into())));
+        assert!(queue.start_saving(0));
+        assert!(queue.update(0, ImportStatus::Done("File 0".into())));
+        assert!(queue.start_next().is_empty());
+
+        let running = queue
+            .items()
+            .iter()
+            .filter(|item| item.status == ImportStatus::Parsing)
+            .count();
+        assert_eq!(running, MAX_CONCURRENT_IMPORTS);
+    }
+
+    #[test]
+    fn cancelled_files_are_skipped_and_their_results_dropped() {
+        let mut queue = queue(3);
+        queue.start_next();
+
+        queue.cancel(0);
+        queue.cancel(2);
+        assert!(!queue.update(0, ImportStatus::Saving));
+        assert_eq!(queue.items()[0].status, ImportStatus::Cancelled);
+        assert!(queue.start_next().is_empty());
+
+        assert!(queue.update(1, ImportStatus::Saving));
+        queue.cancel(1);
+        assert_eq!(queue.items()[1].status, ImportStatus::Saving);
+    }
+
+    #[test]
+    fn cancelled_parses_hold_their_slot_until_they_end() {
+        let mut queue = queue(3);
+        queue.start_next();
+
+        queue.cancel(0);
+        assert_eq!(queue.items()[0].status, ImportStatus::Cancelling);
+        assert!(!queue.items()[0].can_cancel());
+        assert!(queue.start_next().is_empty());
+        assert!(!queue.is_finished());
+
+        assert!(!queue.update(0, ImportStatus::Ready));
+        assert_eq!(queue.items()[0].status, ImportStatus::Cancelled);
+        assert_eq!(queue.start_next(), vec![2]);
+    }
+
+    #[test]
+    fn finished_batches_are_summarized_and_replaced() {
+        let mut queue = queue(3);
+        queue.start_next();
+        queue.update(0, ImportStatus::Done("A".into()));
+        queue.update(1, ImportStatus::Failed("Invalid file format".into()));
+        assert_eq!(queue.summary(), None);
+
+        queue.cancel(2);
+        assert_eq!(
+            queue.summary().as_deref(),
+            Some("Added 1 document; 1 failed; 1 cancelled.")
+        );
+
+        assert_eq!(queue.add("next.txt".into()), 3);
+        assert_eq!(queue.items().len(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::state::Document;
+use crate::tokens::paragraphs;
+
+/// Paragraphs shown from each end of the text in review.
+const PREVIEW_PARAGRAPHS: usize = 3;
+/// Characters shown of each previewed paragraph.
+const PREVIEW_CHARS: usize = 280;
+
+/// Changes made to a document in review, before it is saved or after.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocumentEdit {
+    pub title: String,
+    pub author: String,
+    /// Paragraphs dropped from the start, such as a cover page or contents.
+    pub skip_start: usize,
+    /// Paragraphs dropped from the end, such as references or an index.
+    pub skip_end: usize,
+    /// Text to remove, one pattern per line, such as a running header. A
+    /// pattern matches whole words, ignoring case, and `#` in it matches any
+    /// number, so `Page #` removes every page number.
+    pub remove: String,
+}
+
+/// A document with an edit applied, remembering which of the original words
+/// were kept so positions in the old text can be moved to the new one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditedDocument {
+    pub document: Document,
+    kept: Vec<bool>,
+}
+
+/// The start and end of a document's text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preview {
+    pub start: Vec<String>,
+    /// Empty when `start` already reaches the end.
+    pub end: Vec<String>,
+}
+
+impl DocumentEdit {
+    /// An edit that changes nothing yet.
+    pub fn new(doc: &Document) -> Self {
+        Self {
+            title: doc.title.clone(),
+            author: doc.author.clone().unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    /// Applies the edit to `doc`. An emptied title keeps the old one.
+    pub fn apply(&self, doc: &Document) -> EditedDocument {
+        let patterns = self.patterns();
+        let paragraphs = paragraphs(&doc.content);
+        let end = paragraphs.len().saturating_sub(self.skip_end);
+
+        let mut kept = Vec::new();
+        let mut content = Vec::new();
+        let mut headings = Vec::new();
+        for (index, paragraph) in paragraphs.iter().enumerate() {
+            let words: Vec<&str> = paragraph.split_whitespace().collect();
+            let mut keep = vec![index >= self.skip_start && index < end; words.len()];
+            if keep.first() == Some(&true) {
+                remove_matches(&words, &patterns, &mut keep);
+            }
+
+            let text = words
+                .iter()
+                .zip(&keep)
+                .filter(|(_, keep)| **keep)
+                .map(|(word, _)| *word)
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !text.is_empty() {
+                if doc.headings.contains(&index) {
+                    headings.push(content.len());
+                }
+                content.push(text);
+            }
+            kept.extend(keep);
+        }
+
+        let title = self.title.trim();
+        let author = self.author.trim();
+        let content = content.join("\n\n");
+        let document = Document {
+            title: if title.is_empty() {
+                doc.title.clone()
+            } else {
+                title.to_string()
+            },
+            author: (!author.is_empty()).then(|| author.to_string()),
+            word_count: content.split_whitespace().count(),
+            content,
+            headings,
+            ..doc.clone()
+        };
+        EditedDocument { document, kept }
+    }
+
+    /// Each pattern as its words, lowercased.
+    fn patterns(&self) -> Vec<Vec<Vec<char>>> {
+        self.remove
+            .lines()
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|word| word.chars().flat_map(char::to_lowercase).collect())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    }
+}
+
+impl EditedDocument {
+    /// Whether any of the text was removed.
+    pub fn text_changed(&self) -> bool {
+        self.kept.iter().any(|keep| !keep)
+    }
+
+    pub fn removed_words(&self) -> usize {
+        self.kept.iter().filter(|keep| !**keep).count()
+    }
+
+    /// Where the word at `index` in the original text is now. A removed word
+    /// moves to the word that followed it.
+    pub fn word_index(&self, index: usize) -> usize {
+        self.kept[..index.min(self.kept.len())]
+            .iter()
+            .filter(|keep| **keep)
+            .count()
+    }
+}
+
+/// Marks every run of words matching a pattern as removed.
+fn remove_matches(words: &[&str], patterns: &[Vec<Vec<char>>], keep: &mut [bool]) {
+    let words: Vec<Vec<char>> = words
+        .iter()
+        .map(|word| word.chars().flat_map(char::to_lowercase).collect())
+        .collect();
+    for start in 0..words.len() {
+        for pattern in patterns {
+            let window = words.get(start..start + pattern.len());
+            let matches = window.is_some_and(|window| {
+                window
+                    .iter()
+                    .zip(pattern)
+                    .all(|(word, pattern)| word_matches(pattern, word))
+            });
+            if matches {
+                keep[start..start + pattern.len()].fill(false);
+            }
+        }
+    }
+}
+
+/// Whether `word` matches `pattern`, where `#` stands for one or more digits.
+fn word_matches(pattern: &[char], word: &[char]) -> bool {
+    match pattern.split_first() {
+        None => word.is_empty(),
+        Some(('#', rest)) => {
+            let digits = word.iter().take_while(|c| c.is_ascii_digit()).count();
+            (1..=digits).any(|n| word_matches(rest, &word[n..]))
+        }
+        Some((c, rest)) => word.first() == Some(c) && word_matches(rest, &word[1..]),
+    }
+}
+
+/// The first and last few paragraphs of `doc`, each cut short if long.
+pub fn preview(doc: &Document) -> Preview {
+    let paragraphs: Vec<String> = paragraphs(&doc.content)
+        .into_iter()
+        .map(|paragraph| shorten(&paragraph))
+        .collect();
+    if paragraphs.len() <= PREVIEW_PARAGRAPHS * 2 {
+        return Preview {
+            start: paragraphs,
+            end: Vec::new(),
+        };
+    }
+    Preview {
+        start: paragraphs[..PREVIEW_PARAGRAPHS].to_vec(),
+        end: paragraphs[paragraphs.len() - PREVIEW_PARAGRAPHS..].to_vec(),
+    }
+}
+
+fn shorten(paragraph: &str) -> String {
+    if paragraph.chars().count() <= PREVIEW_CHARS {
+        return paragraph.to_string();
+    }
+    let cut: String = paragraph.chars().take(PREVIEW_CHARS).collect();
+    let cut = cut
+        .rsplit_once(' ')
+        .map_or(cut.as_str(), |(start, _)| start);
+    format!("{}…", cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preview, DocumentEdit};
+    use crate::state::Document;
+    use crate::tokens::Block;
+
+    fn document() -> Document {
+        Document::new(
+            "scan_0042".into(),
+            vec![
+                Block::paragraph("Contents 1 Intro 3"),
+                Block::heading("Introduction"),
+                Block::paragraph("Journal of Tests Page 1 The first finding holds."),
+                Block::paragraph("Journal of Tests Page 2 The second one does too."),
+                Block::paragraph("References Smith 2020"),
+            ],
+            "PDF".into(),
+        )
+    }
+
+    #[test]
+    fn edits_trim_both_ends_and_remove_matched_text() {
+        let doc = document();
+        let edit = DocumentEdit {
+            title: " Findings ".into(),
+            author: "A. Author".into(),
+            skip_start: 1,
+            skip_end: 1,
+            remove: "journal of tests\n\npage #\n".into(),
+        };
+
+        let edited = edit.apply(&doc);
+
+        assert_eq!(edited.document.id, doc.id);
+        assert_eq!(edited.document.title, "Findings");
+        assert_eq!(edited.document.author.as_deref(), Some("A. Author"));
+        assert_eq!(
+            edited.document.content,
+            "Introduction\n\nThe first finding holds.\n\nThe second one does too."
+        );
+        assert_eq!(edited.document.headings, vec![0]);
+        assert_eq!(edited.document.word_count, 10);
+        assert_eq!(edited.removed_words(), doc.word_count - 10);
+    }
+
+    #[test]
+    fn unchanged_edits_keep_the_document() {
+        let doc = document();
+        let edited = DocumentEdit::new(&doc).apply(&doc);
+
+        assert!(!edited.text_changed());
+        assert_eq!(edited.document, doc);
+
+        let emptied = DocumentEdit {
+            title: "  ".into(),
+            ..DocumentEdit::new(&doc)
+        };
+        assert_eq!(emptied.apply(&doc).document.title, "scan_0042");
+    }
+
+    #[test]
+    fn positions_move_with_the_words_around_them() {
+        let doc = Document::new(
+            "Doc".into(),
+            vec![Block::paragraph("Header one two Header three")],
+            "TXT".into(),
+        );
+        let edit = DocumentEdit {
+            remove: "header".into(),
+            ..DocumentEdit::new(&doc)
+        };
+
+        let edited = edit.apply(&doc);
+
+        assert_eq!(edited.document.content, "one two three");
+        assert_eq!(edited.word_index(0), 0);
+        assert_eq!(edited.word_index(2), 1);
+        assert_eq!(edited.word_index(3), 2);
+        assert_eq!(edited.word_index(4), 2);
+        assert_eq!(edited.word_index(99), 3);
+    }
+
+    #[test]
+    fn previews_show_both_ends_of_long_texts() {
+        let blocks = (1..=10)
+            .map(|n| Block::paragraph(format!("Paragraph {}.", n)))
+            .collect();
+        let doc = Document::new("Doc".into(), blocks, "TXT".into());
+
+        let preview = preview(&doc);
+
+        assert_eq!(
+            preview.start,
+            vec!["Paragraph 1.", "Paragraph 2.", "Paragraph 3."]
+        );
+        assert_eq!(
+            preview.end,
+            vec!["Paragraph 8.", "Paragraph 9.", "Paragraph 10."]
+        );
+    }
+}
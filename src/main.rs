@@ -1,7 +1,13 @@
 mod components;
+mod export;
+mod import;
 mod parser;
+mod scheduler;
+mod search;
 mod state;
 mod storage;
+mod timing;
+mod tokens;
 
 use components::App;
 
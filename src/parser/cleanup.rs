@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+
+/// Lines at each end of a page checked for headers, footers, and page numbers.
+const EDGE_LINES: usize = 3;
+/// Pages a line must appear on to count as a running header or footer.
+const MIN_REPEATED_PAGES: usize = 3;
+/// Highest roman page number recognized. Roman numerals number front matter,
+/// and higher ones are more often words such as `lix`.
+const MAX_ROMAN_PAGE: u32 = 50;
+
+/// Cleanup applied to text extracted from a PDF. Each rule can be turned off
+/// at import.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PdfCleanup {
+    /// Drop lines repeated at the top or bottom of pages, such as running
+    /// headers and footers. Numbers in them may differ from page to page.
+    pub repeated_lines: bool,
+    /// Drop lines at the top or bottom of pages that hold only a page number.
+    pub page_numbers: bool,
+    /// Rejoin words hyphenated across a line end.
+    pub hyphenation: bool,
+    /// Rebuild paragraphs from lines, rejoining those split by a page break.
+    /// Without it each page is one paragraph.
+    pub paragraphs: bool,
+}
+
+impl Default for PdfCleanup {
+    fn default() -> Self {
+        Self {
+            repeated_lines: true,
+            page_numbers: true,
+            hyphenation: true,
+            paragraphs: true,
+        }
+    }
+}
+
+#[cfg(test)]
+impl PdfCleanup {
+    /// Every rule turned off.
+    pub const OFF: Self = Self {
+        repeated_lines: false,
+        page_numbers: false,
+        hyphenation: false,
+        paragraphs: false,
+    };
+}
+
+/// The paragraphs of one cleaned page.
+pub(super) struct CleanPage {
+    pub paragraphs: Vec<String>,
+    /// Whether the first paragraph carries on the last one of the page before.
+    pub continues: bool,
+}
+
+/// Applies `cleanup` to pages of text with one line of the PDF per line.
+pub(super) fn clean_pages(pages: &[String], cleanup: PdfCleanup) -> Vec<CleanPage> {
+    let mut pages: Vec<Vec<String>> = pages
+        .iter()
+        .map(|page| {
+            page.lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect()
+        })
+        .collect();
+
+    if cleanup.page_numbers {
+        for lines in &mut pages {
+            retain_edges(lines, |line| !is_page_number(line));
+        }
+    }
+    if cleanup.repeated_lines {
+        remove_repeated_lines(&mut pages);
+    }
+
+    let typical = typical_line_chars(&pages);
+    let mut previous_ended = true;
+    pages
+        .into_iter()
+        .map(|lines| {
+            let continues = cleanup.paragraphs && !previous_ended && !lines.is_empty();
+            if let Some(last) = lines.last() {
+                previous_ended = is_short(last, typical);
+            }
+
+            let mut paragraphs = Vec::new();
+            let mut current = String::new();
+            for line in &lines {
+                join_line(&mut current, line, cleanup.hyphenation);
+                if cleanup.paragraphs && is_short(line, typical) {
+                    paragraphs.push(std::mem::take(&mut current));
+                }
+            }
+            if !current.is_empty() {
+                paragraphs.push(current);
+            }
+            CleanPage {
+                paragraphs,
+                continues,
+            }
+        })
+        .collect()
+}
+
+/// Appends `line` to `text` with a space, or, for a word hyphenated across the
+/// line end, without the hyphen.
+pub(super) fn join_line(text: &mut String, line: &str, hyphenation: bool) {
+    if !text.is_empty() {
+        if hyphenation && ends_with_split_word(text) && line.starts_with(char::is_lowercase) {
+            text.pop();
+        } else {
+            text.push(' ');
+        }
+    }
+    text.push_str(line);
+}
+
+fn ends_with_split_word(text: &str) -> bool {
+    let mut chars = text.chars().rev();
+    chars.next() == Some('-') && chars.next().is_some_and(char::is_alphabetic)
+}
+
+fn is_edge(index: usize, lines: usize) -> bool {
+    index < EDGE_LINES || index + EDGE_LINES >= lines
+}
+
+/// Drops lines at either end of the page that fail `keep`.
+fn retain_edges(lines: &mut Vec<String>, keep: impl Fn(&str) -> bool) {
+    let count = lines.len();
+    let mut index = 0;
+    lines.retain(|line| {
+        let edge = is_edge(index, count);
+        index += 1;
+        !edge || keep(line)
+    });
+}
+
+fn remove_repeated_lines(pages: &mut [Vec<String>]) {
+    let mut pages_with_line: HashMap<String, usize> = HashMap::new();
+    for lines in pages.iter() {
+        let keys: HashSet<String> = lines
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| is_edge(*index, lines.len()))
+            .map(|(_, line)| repeat_key(line))
+            .collect();
+        for key in keys {
+            *pages_with_line.entry(key).or_default() += 1;
+        }
+    }
+
+    for lines in pages {
+        retain_edges(lines, |line| {
+            pages_with_line
+                .get(&repeat_key(line))
+                .is_none_or(|&pages| pages < MIN_REPEATED_PAGES)
+        });
+    }
+}
+
+/// A line lowercased with each run of digits replaced by `#`, so a header
+/// holding the page number matches on every page.
+fn repeat_key(line: &str) -> String {
+    let mut key = String::with_capacity(line.len());
+    for c in line.chars().flat_map(char::to_lowercase) {
+        if !c.is_ascii_digit() {
+            key.push(c);
+        } else if !key.ends_with('#') {
+            key.push('#');
+        }
+    }
+    key
+}
+
+/// Whether `line` is only a page number, such as `12`, `- 12 -`, `xii`,
+/// `Page 12`, `12 of 300`, or `12/300`.
+fn is_page_number(line: &str) -> bool {
+    let line = line.trim_matches(|c: char| c.is_whitespace() || "-–—|·•[]()".contains(c));
+    let number = ["page", "p."]
+        .iter()
+        .find_map(|prefix| {
+            line.get(..prefix.len())
+                .filter(|start| start.eq_ignore_ascii_case(prefix))
+                .map(|_| &line[prefix.len()..])
+        })
+        .unwrap_or(line);
+    let words: Vec<&str> = number.split_whitespace().collect();
+    match words.as_slice() {
+        [page] => match page.split_once('/') {
+            Some((page, total)) => is_number(page) && is_number(total),
+            None => is_number(page),
+        },
+        [page, of, total] if of.eq_ignore_ascii_case("of") || *of == "/" => {
+            is_number(page) && is_number(total)
+        }
+        _ => false,
+    }
+}
+
+fn is_number(word: &str) -> bool {
+    let digits = !word.is_empty() && word.len() <= 5 && word.chars().all(|c| c.is_ascii_digit());
+    digits || roman_value(word).is_some_and(|value| value <= MAX_ROMAN_PAGE)
+}
+
+const ROMAN_NUMERALS: [(u32, &str); 9] = [
+    (100, "c"),
+    (90, "xc"),
+    (50, "l"),
+    (40, "xl"),
+    (10, "x"),
+    (9, "ix"),
+    (5, "v"),
+    (4, "iv"),
+    (1, "i"),
+];
+
+/// The value of a lowercase roman numeral written the usual way, so `xiv` is
+/// 14 but `xiiii`, `ill`, and `I` are not numerals.
+fn roman_value(word: &str) -> Option<u32> {
+    let mut rest = word;
+    let mut value = 0;
+    for (numeral_value, numeral) in ROMAN_NUMERALS {
+        while let Some(after) = rest.strip_prefix(numeral) {
+            rest = after;
+            value += numeral_value;
+        }
+    }
+    if !rest.is_empty() || value == 0 {
+        return None;
+    }
+
+    // Only the usual spelling of the value is accepted.
+    let mut remaining = value;
+    let mut canonical = String::new();
+    for (numeral_value, numeral) in ROMAN_NUMERALS {
+        while remaining >= numeral_value {
+            canonical.push_str(numeral);
+            remaining -= numeral_value;
+        }
+    }
+    (canonical == word).then_some(value)
+}
+
+/// The median line length in characters; body text lines run about this long.
+fn typical_line_chars(pages: &[Vec<String>]) -> usize {
+    let mut lengths: Vec<usize> = pages
+        .iter()
+        .flatten()
+        .map(|line| line.chars().count())
+        .collect();
+    lengths.sort_unstable();
+    lengths.get(lengths.len() / 2).copied().unwrap_or(0)
+}
+
+/// A line well short of the typical length ends its paragraph.
+fn is_short(line: &str, typical: usize) -> bool {
+    line.chars().count() * 5 < typical * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clean_pages, is_page_number, PdfCleanup};
+
+    fn page(number: usize, lines: &[&str]) -> String {
+        let mut page = vec![format!("Journal of Tests, vol. {}", number)];
+        page.extend(lines.iter().map(|line| line.to_string()));
+        page.push(format!("- {} -", number));
+        page.join("\n")
+    }
+
+    fn pages() -> Vec<String> {
+        vec![
+            page(
+                1,
+                &[
+                    "The quick brown fox jumps over the lazy dog",
+                    "so the dog wakes and chases after the infor-",
+                    "mation that the quick fox left behind him.",
+                    "Neither animal knows the road out of town",
+                ],
+            ),
+            page(
+                2,
+                &[
+                    "and both end up by the river bank at dusk,",
+                    "where the fox finally stops running away.",
+                    "Short end.",
+                    "Then a new paragraph starts with its first",
+                ],
+            ),
+            page(
+                3,
+                &[
+                    "line and runs on until the very last page",
+                    "page 3 of 3",
+                    "Done.",
+                ],
+            ),
+        ]
+    }
+
+    fn paragraphs(pages: &[String], cleanup: PdfCleanup) -> Vec<Vec<String>> {
+        clean_pages(pages, cleanup)
+            .into_iter()
+            .map(|page| page.paragraphs)
+            .collect()
+    }
+
+    #[test]
+    fn running_headers_page_numbers_and_hyphens_are_cleaned() {
+        let pages = paragraphs(
+            &pages(),
+            PdfCleanup {
+                paragraphs: false,
+                ..PdfCleanup::default()
+            },
+        );
+
+        assert_eq!(
+            pages[0],
+            vec![
+                "The quick brown fox jumps over the lazy dog so the dog wakes and chases after \
+                 the information that the quick fox left behind him. Neither animal knows the \
+                 road out of town"
+            ]
+        );
+        assert!(!pages[1][0].contains("Journal"));
+        assert_eq!(
+            pages[2],
+            vec!["line and runs on until the very last page Done."]
+        );
+    }
+
+    #[test]
+    fn paragraphs_break_at_short_lines_and_continue_across_pages() {
+        let pages = clean_pages(&pages(), PdfCleanup::default());
+
+        assert!(pages[1].continues);
+        assert_eq!(
+            pages[1].paragraphs,
+            vec![
+                "and both end up by the river bank at dusk, where the fox finally stops running \
+                 away. Short end.",
+                "Then a new paragraph starts with its first",
+            ]
+        );
+        assert!(pages[2].continues);
+        assert_eq!(
+            pages[2].paragraphs,
+            vec!["line and runs on until the very last page Done."]
+        );
+    }
+
+    #[test]
+    fn only_page_numbers_look_like_page_numbers() {
+        for line in [
+            "12",
+            "- 12 -",
+            "[xii]",
+            "xlix",
+            "Page 12",
+            "p. 7",
+            "12 of 300",
+            "12 / 300",
+            "12/300",
+        ] {
+            assert!(is_page_number(line), "{:?} is a page number", line);
+        }
+        for line in [
+            "civil",
+            "ill",
+            "lix",
+            "I",
+            "XII",
+            "iiii",
+            "vx",
+            "12 300",
+            "1999 2000",
+            "12 of",
+        ] {
+            assert!(!is_page_number(line), "{:?} is not a page number", line);
+        }
+    }
+
+    #[test]
+    fn rules_that_are_off_leave_the_text() {
+        let pages = paragraphs(&pages(), PdfCleanup::OFF);
+
+        assert!(pages[0][0].starts_with("Journal of Tests, vol. 1"));
+        assert!(pages[0][0].contains("infor- mation"));
+        assert!(pages[0][0].ends_with("- 1 -"));
+        assert!(pages[2][0].contains("page 3 of 3"));
+        assert_eq!(pages[2].len(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+use serde::Deserialize;
+
+/// Title, author, language, and publication date read from a file. Any of them
+/// may be missing.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub(super) struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub published: Option<String>,
+}
+
+impl Metadata {
+    /// Fills fields missing here from `other`.
+    pub fn or(self, other: Metadata) -> Metadata {
+        Metadata {
+            title: self.title.or(other.title),
+            author: self.author.or(other.author),
+            language: self.language.or(other.language),
+            published: self.published.or(other.published),
+        }
+    }
+
+    /// Collapses whitespace, drops blank fields, and reduces the publication
+    /// date to `YYYY`, `YYYY-MM`, or `YYYY-MM-DD`, dropping dates that are not
+    /// in ISO 8601 or PDF date form.
+    pub fn normalized(self) -> Metadata {
+        let clean = |value: Option<String>| {
+            value
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|value| !value.is_empty())
+        };
+        Metadata {
+            title: clean(self.title),
+            author: clean(self.author),
+            language: clean(self.language),
+            published: clean(self.published).and_then(|date| publication_date(&date)),
+        }
+    }
+}
+
+/// The date part of an ISO 8601 date such as `2020-03-14T10:00:00Z`, or of a
+/// PDF date such as `D:20200314100000Z`.
+fn publication_date(raw: &str) -> Option<String> {
+    let raw = raw.strip_prefix("D:").unwrap_or(raw);
+    let digits: String = raw
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '-')
+        .filter(char::is_ascii_digit)
+        .take(8)
+        .collect();
+
+    let mut date = digits.get(0..4)?.to_string();
+    for (part, max) in [(4..6, 12), (6..8, 31)] {
+        let Some(part) = digits.get(part) else {
+            break;
+        };
+        if !part.parse().is_ok_and(|n: u32| (1..=max).contains(&n)) {
+            break;
+        }
+        date.push('-');
+        date.push_str(part);
+    }
+    Some(date)
+}
+
+/// Splits YAML front matter, fenced by `---` lines, off the start of a
+/// Markdown file. Returns the front matter, if any, and the rest.
+pub(super) fn split_front_matter(markdown: &str) -> (Option<&str>, &str) {
+    let text = markdown.strip_prefix('\u{feff}').unwrap_or(markdown);
+    let Some(rest) = text
+        .strip_prefix("---")
+        .filter(|rest| rest.starts_with('\n') || rest.starts_with("\r\n"))
+    else {
+        return (None, markdown);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let fence = line.trim_end();
+        if offset > 0 && (fence == "---" || fence == "...") {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, markdown)
+}
+
+/// Reads the `title`, `author`, `lang`, and `date` keys of YAML front matter.
+/// Values may be plain, quoted, or lists; lists of authors are joined.
+pub(super) fn front_matter_metadata(yaml: &str) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut lines = yaml.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let values: Vec<String> = if value.is_empty() {
+            let mut items = Vec::new();
+            while let Some(item) = lines
+                .peek()
+                .and_then(|line| line.trim_start().strip_prefix('-'))
+            {
+                items.push(unquote(item));
+                lines.next();
+            }
+            items
+        } else if let Some(list) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            list.split(',').map(unquote).collect()
+        } else {
+            vec![unquote(value)]
+        };
+        let values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+        let first = values.first().cloned();
+
+        match key.trim().to_lowercase().as_str() {
+            "title" => metadata.title = first,
+            "author" | "authors" => metadata.author = Some(values.join(", ")),
+            "lang" | "language" => metadata.language = first,
+            "date" => metadata.published = first,
+            _ => {}
+        }
+    }
+    metadata.normalized()
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{front_matter_metadata, split_front_matter, Metadata};
+
+    #[test]
+    fn dates_keep_only_their_valid_date_part() {
+        let published = |date: &str| {
+            Metadata {
+                published: Some(date.into()),
+                ..Metadata::default()
+            }
+            .normalized()
+            .published
+        };
+
+        assert_eq!(
+            published("D:20200314100000+01'00'").as_deref(),
+            Some("2020-03-14")
+        );
+        assert_eq!(
+            published("2019-05-01T00:00:00Z").as_deref(),
+            Some("2019-05-01")
+        );
+        assert_eq!(published("1851").as_deref(), Some("1851"));
+        assert_eq!(published("2021-13-40").as_deref(), Some("2021"));
+        assert_eq!(published("Spring 1851"), None);
+    }
+
+    #[test]
+    fn missing_fields_come_from_the_fallback() {
+        let xmp = Metadata {
+            title: Some("From XMP".into()),
+            ..Metadata::default()
+        };
+        let info = Metadata {
+            title: Some("From Info".into()),
+            author: Some("Author".into()),
+            ..Metadata::default()
+        };
+
+        let metadata = xmp.or(info);
+
+        assert_eq!(metadata.title.as_deref(), Some("From XMP"));
+        assert_eq!(metadata.author.as_deref(), Some("Author"));
+    }
+
+    #[test]
+    fn front_matter_is_split_off_and_read() {
+        let markdown = "---\r\ntitle: \"Notes: Part 1\"\r\nauthors:\r\n  - Ada Lovelace\r\n  - 'Charles Babbage'\r\nlang: en\r\ndate: 1843-09-05\r\ntags: [a, b]\r\n---\r\n# Heading\n";
+
+        let (front_matter, rest) = split_front_matter(markdown);
+        let metadata = front_matter_metadata(front_matter.unwrap());
+
+        assert_eq!(rest, "# Heading\n");
+        assert_eq!(
+            metadata,
+            Metadata {
+                title: Some("Notes: Part 1".into()),
+                author: Some("Ada Lovelace, Charles Babbage".into()),
+                language: Some("en".into()),
+                published: Some("1843-09-05".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn text_without_front_matter_is_left_alone() {
+        for markdown in [
+            "# Title\n---\n",
+            "---\nnever closed\n",
+            "--- \ntitle: x\n---\n",
+        ] {
+            assert_eq!(split_front_matter(markdown), (None, markdown));
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use js_sys::{Promise, Uint8Array};
 use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::Deserialize;
 use thiserror::Error;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -7,6 +8,15 @@ use wasm_bindgen_futures::JsFuture;
 use web_sys::{File, FileReader};
 
 use crate::state::Document;
+use crate::tokens::{paragraphs, Block};
+
+mod cleanup;
+mod metadata;
+
+use cleanup::{clean_pages, join_line};
+use metadata::{front_matter_metadata, split_front_matter, Metadata};
+
+pub use cleanup::PdfCleanup;
 
 #[wasm_bindgen]
 extern "C" {
@@ -15,9 +25,16 @@ extern "C" {
 
     #[wasm_bindgen(js_namespace = window, js_name = extractDocxText)]
     fn extract_docx_text(array_buffer: &JsValue) -> Promise;
+
+    #[wasm_bindgen(js_namespace = window, js_name = extractEpubText)]
+    fn extract_epub_text(array_buffer: &JsValue) -> Promise;
 }
 
 const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024; // 50MB
+/// Type shown for documents imported from pasted text.
+const PASTED_FILE_TYPE: &str = "PASTE";
+/// Longest title generated for pasted text, in characters.
+const PASTED_TITLE_CHARS: usize = 60;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
@@ -31,9 +48,80 @@ pub enum ParseError {
     ParseFailure(String),
     #[error("Invalid file format")]
     InvalidFormat,
+    #[error("There is no text to import")]
+    NoText,
+}
+
+/// Text blocks and any embedded metadata recovered from a file before sanitization.
+struct Extracted {
+    blocks: Vec<Block>,
+    metadata: Metadata,
+}
+
+impl From<Vec<Block>> for Extracted {
+    fn from(blocks: Vec<Block>) -> Self {
+        Self {
+            blocks,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+/// Plain text is split into paragraphs at blank lines.
+impl From<String> for Extracted {
+    fn from(text: String) -> Self {
+        paragraphs(&text)
+            .into_iter()
+            .map(Block::paragraph)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+#[derive(Deserialize)]
+struct PdfDocument {
+    pages: Vec<String>,
+    #[serde(default)]
+    outline: Vec<PdfOutlineEntry>,
+    /// Metadata from the Info dictionary.
+    #[serde(default)]
+    info: Metadata,
+    /// Metadata from the XMP packet, preferred over `info` where both have a
+    /// field.
+    #[serde(default)]
+    xmp: Metadata,
+}
+
+#[derive(Deserialize)]
+struct PdfOutlineEntry {
+    title: String,
+    /// Zero-based page the entry points to.
+    page: usize,
+}
+
+#[derive(Deserialize)]
+struct DocxDocument {
+    blocks: Vec<Block>,
+    /// From `docProps/core.xml`.
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Deserialize)]
+struct EpubBook {
+    /// From the package document.
+    #[serde(flatten)]
+    metadata: Metadata,
+    chapters: Vec<EpubChapter>,
+}
+
+#[derive(Deserialize)]
+struct EpubChapter {
+    blocks: Vec<Block>,
 }
 
-pub async fn parse_file(file: File) -> Result<Document, ParseError> {
+/// Parses `file`, applying `cleanup` if it is a PDF.
+pub async fn parse_file(file: File, cleanup: PdfCleanup) -> Result<Document, ParseError> {
     let size = file.size() as u64;
     if size > MAX_FILE_SIZE {
         return Err(ParseError::FileTooLarge);
@@ -44,22 +132,98 @@ pub async fn parse_file(file: File) -> Result<Document, ParseError> {
 
     validate_magic_bytes(&file, &file_type).await?;
 
-    let content = match file_type.as_str() {
-        "TXT" => parse_txt(file).await?,
+    let extracted = match file_type.as_str() {
+        "TXT" => parse_txt(file).await?.into(),
         "MD" => parse_markdown(file).await?,
-        "PDF" => parse_pdf(file).await?,
+        "PDF" => parse_pdf(file, cleanup).await?,
         "DOCX" => parse_docx(file).await?,
+        "EPUB" => parse_epub(file).await?,
         _ => return Err(ParseError::UnsupportedType(file_type)),
     };
 
-    let sanitized = sanitize_content(&content);
+    Ok(build_document(
+        extracted,
+        || extract_title(&name),
+        file_type,
+    ))
+}
+
+/// A document from pasted text, titled `title` or, when that is blank, after
+/// the text's first line.
+pub fn parse_pasted_text(text: &str, title: &str) -> Result<Document, ParseError> {
+    if text.len() as u64 > MAX_FILE_SIZE {
+        return Err(ParseError::FileTooLarge);
+    }
 
-    let title = extract_title(&name);
-    let doc = Document::new(title, sanitized, file_type);
+    let extracted = Extracted {
+        metadata: Metadata {
+            title: Some(title.to_string()),
+            ..Metadata::default()
+        },
+        ..Extracted::from(text.to_string())
+    };
+    let doc = build_document(extracted, || pasted_title(text), PASTED_FILE_TYPE.into());
+    if doc.word_count == 0 {
+        return Err(ParseError::NoText);
+    }
 
     Ok(doc)
 }
 
+/// The first line of `text`, cut at a word boundary if it is long.
+pub fn pasted_title(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    if line.chars().count() <= PASTED_TITLE_CHARS {
+        return line;
+    }
+
+    let mut title = String::new();
+    for word in line.split(' ') {
+        if title.chars().count() + word.chars().count() + 1 > PASTED_TITLE_CHARS {
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    if title.is_empty() {
+        // One long word, such as a URL.
+        title = line.chars().take(PASTED_TITLE_CHARS).collect();
+    }
+    title.push('…');
+    title
+}
+
+/// Sanitizes extracted text and builds the document. Blocks are sanitized one
+/// at a time so removed markup cannot merge or shift paragraphs.
+fn build_document(
+    extracted: Extracted,
+    fallback_title: impl FnOnce() -> String,
+    file_type: String,
+) -> Document {
+    let blocks = extracted
+        .blocks
+        .into_iter()
+        .map(|block| Block {
+            text: sanitize_content(&block.text),
+            ..block
+        })
+        .collect();
+
+    let metadata = extracted.metadata.normalized();
+    let title = metadata.title.unwrap_or_else(fallback_title);
+    let mut doc = Document::new(title, blocks, file_type);
+    doc.author = metadata.author;
+    doc.language = metadata.language;
+    doc.published = metadata.published;
+    doc
+}
+
 fn get_file_type(name: &str) -> Result<String, ParseError> {
     let ext = name.rsplit('.').next().unwrap_or("").to_lowercase();
     match ext.as_str() {
@@ -67,6 +231,7 @@ fn get_file_type(name: &str) -> Result<String, ParseError> {
         "md" | "markdown" => Ok("MD".to_string()),
         "pdf" => Ok("PDF".to_string()),
         "docx" => Ok("DOCX".to_string()),
+        "epub" => Ok("EPUB".to_string()),
         _ => Err(ParseError::UnsupportedType(ext)),
     }
 }
@@ -84,7 +249,7 @@ async fn validate_magic_bytes(file: &File, file_type: &str) -> Result<(), ParseE
 
     match file_type {
         "PDF" if !bytes.starts_with(b"%PDF") => return Err(ParseError::InvalidFormat),
-        "DOCX" if !bytes.starts_with(&[0x50, 0x4B, 0x03, 0x04]) => {
+        "DOCX" | "EPUB" if !bytes.starts_with(&[0x50, 0x4B, 0x03, 0x04]) => {
             return Err(ParseError::InvalidFormat);
         }
         _ => {}
@@ -149,14 +314,35 @@ async fn parse_txt(file: File) -> Result<String, ParseError> {
     read_file_as_text(file).await
 }
 
-async fn parse_markdown(file: File) -> Result<String, ParseError> {
+async fn parse_markdown(file: File) -> Result<Extracted, ParseError> {
     let md_content = read_file_as_text(file).await?;
+    Ok(markdown_extracted(&md_content))
+}
+
+/// Blocks from the Markdown, with metadata from its YAML front matter.
+fn markdown_extracted(markdown: &str) -> Extracted {
+    let (front_matter, body) = split_front_matter(markdown);
+    Extracted {
+        blocks: markdown_blocks(body),
+        metadata: front_matter.map(front_matter_metadata).unwrap_or_default(),
+    }
+}
 
-    let parser = Parser::new(&md_content);
+/// Paragraphs, list items, and table rows become paragraphs; headings keep
+/// their own blocks. Code blocks are skipped.
+fn markdown_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
     let mut text = String::new();
     let mut in_code_block = false;
 
-    for event in parser {
+    let mut flush = |text: &mut String, block: fn(String) -> Block| {
+        if !text.trim().is_empty() {
+            blocks.push(block(text.trim().to_string()));
+        }
+        text.clear();
+    };
+
+    for event in Parser::new(markdown) {
         match event {
             Event::Text(t) => {
                 if !in_code_block {
@@ -167,14 +353,20 @@ async fn parse_markdown(file: File) -> Result<String, ParseError> {
             Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
             Event::End(TagEnd::CodeBlock) => in_code_block = false,
             Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::Start(Tag::Heading { .. } | Tag::List(_)) => flush(&mut text, Block::paragraph),
+            Event::End(TagEnd::Heading(_)) => flush(&mut text, Block::heading),
+            Event::End(TagEnd::Paragraph | TagEnd::Item | TagEnd::TableHead | TagEnd::TableRow) => {
+                flush(&mut text, Block::paragraph)
+            }
             _ => {}
         }
     }
+    flush(&mut text, Block::paragraph);
 
-    Ok(text.trim().to_string())
+    blocks
 }
 
-async fn parse_pdf(file: File) -> Result<String, ParseError> {
+async fn parse_pdf(file: File, cleanup: PdfCleanup) -> Result<Extracted, ParseError> {
     let array_buffer = JsFuture::from(file.array_buffer())
         .await
         .map_err(|_| ParseError::ReadError("Failed to read file as array buffer".into()))?;
@@ -186,12 +378,68 @@ async fn parse_pdf(file: File) -> Result<String, ParseError> {
             ParseError::ParseFailure(msg)
         })?;
 
-    result
+    let json = result
         .as_string()
-        .ok_or_else(|| ParseError::ParseFailure("PDF extraction returned non-string".into()))
+        .ok_or_else(|| ParseError::ParseFailure("PDF extraction returned non-string".into()))?;
+
+    let mut document: PdfDocument =
+        serde_json::from_str(&json).map_err(|e| ParseError::ParseFailure(e.to_string()))?;
+    let metadata = std::mem::take(&mut document.xmp).or(std::mem::take(&mut document.info));
+    Ok(Extracted {
+        blocks: pdf_blocks(document, cleanup),
+        metadata,
+    })
 }
 
-async fn parse_docx(file: File) -> Result<String, ParseError> {
+/// Each page's cleaned paragraphs, preceded by headings for the outline entries
+/// that point to it. A page that starts with an entry's title loses that copy
+/// so the title is not read twice. A paragraph carried over a page break is
+/// rejoined unless an outline entry starts a section there.
+fn pdf_blocks(document: PdfDocument, cleanup: PdfCleanup) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for (index, page) in clean_pages(&document.pages, cleanup)
+        .into_iter()
+        .enumerate()
+    {
+        let mut paragraphs = page.paragraphs.into_iter();
+        let mut first = paragraphs.next().unwrap_or_default();
+        let mut starts_section = false;
+
+        for entry in document.outline.iter().filter(|entry| entry.page == index) {
+            let title = entry.title.split_whitespace().collect::<Vec<_>>().join(" ");
+            first = strip_leading_title(&first, &title).to_string();
+            blocks.push(Block::heading(title));
+            starts_section = true;
+        }
+
+        match blocks.last_mut() {
+            _ if first.is_empty() => {}
+            Some(last) if page.continues && !starts_section && !last.heading => {
+                join_line(&mut last.text, &first, cleanup.hyphenation);
+            }
+            _ => blocks.push(Block::paragraph(first)),
+        }
+        blocks.extend(paragraphs.map(Block::paragraph));
+    }
+
+    blocks
+}
+
+fn strip_leading_title<'a>(text: &'a str, title: &str) -> &'a str {
+    match (text.get(..title.len()), text.get(title.len()..)) {
+        (Some(prefix), Some(rest))
+            if !title.is_empty()
+                && prefix.eq_ignore_ascii_case(title)
+                && (rest.is_empty() || rest.starts_with(' ')) =>
+        {
+            rest.trim_start()
+        }
+        _ => text,
+    }
+}
+
+async fn parse_docx(file: File) -> Result<Extracted, ParseError> {
     let array_buffer = JsFuture::from(file.array_buffer())
         .await
         .map_err(|_| ParseError::ReadError("Failed to read file as array buffer".into()))?;
@@ -205,9 +453,55 @@ async fn parse_docx(file: File) -> Result<String, ParseError> {
             ParseError::ParseFailure(msg)
         })?;
 
-    result
+    let json = result
         .as_string()
-        .ok_or_else(|| ParseError::ParseFailure("DOCX extraction returned non-string".into()))
+        .ok_or_else(|| ParseError::ParseFailure("DOCX extraction returned non-string".into()))?;
+
+    let document: DocxDocument =
+        serde_json::from_str(&json).map_err(|e| ParseError::ParseFailure(e.to_string()))?;
+    Ok(Extracted {
+        blocks: document.blocks,
+        metadata: document.metadata,
+    })
+}
+
+async fn parse_epub(file: File) -> Result<Extracted, ParseError> {
+    let array_buffer = JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|_| ParseError::ReadError("Failed to read file as array buffer".into()))?;
+
+    let result = JsFuture::from(extract_epub_text(&array_buffer))
+        .await
+        .map_err(|e| {
+            let msg = e
+                .as_string()
+                .unwrap_or_else(|| "EPUB parsing failed".into());
+            ParseError::ParseFailure(msg)
+        })?;
+
+    let json = result
+        .as_string()
+        .ok_or_else(|| ParseError::ParseFailure("EPUB extraction returned non-string".into()))?;
+
+    parse_epub_json(&json)
+}
+
+/// Concatenates spine chapters' blocks in reading order.
+fn parse_epub_json(json: &str) -> Result<Extracted, ParseError> {
+    let book: EpubBook =
+        serde_json::from_str(json).map_err(|e| ParseError::ParseFailure(e.to_string()))?;
+
+    let blocks = book
+        .chapters
+        .into_iter()
+        .flat_map(|chapter| chapter.blocks)
+        .filter(|block| !block.text.trim().is_empty())
+        .collect();
+
+    Ok(Extracted {
+        blocks,
+        metadata: book.metadata,
+    })
 }
 
 fn sanitize_content(content: &str) -> String {
@@ -233,7 +527,12 @@ fn extract_title(filename: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::sanitize_content;
+    use super::{
+        get_file_type, markdown_blocks, markdown_extracted, parse_epub_json, parse_pasted_text,
+        pasted_title, pdf_blocks, sanitize_content, Extracted, Metadata, ParseError, PdfCleanup,
+        PdfDocument,
+    };
+    use crate::tokens::Block;
 
     #[test]
     fn sanitizer_removes_active_content_and_event_handlers() {
@@ -257,4 +556,178 @@ mod tests {
         assert!(!sanitized.contains("onerror"));
         assert!(!sanitized.contains("<style"));
     }
+
+    #[test]
+    fn epub_files_are_recognized() {
+        assert_eq!(get_file_type("Book.EPUB").unwrap(), "EPUB");
+    }
+
+    #[test]
+    fn epub_chapters_keep_order_boundaries_and_metadata() {
+        let extracted = parse_epub_json(
+            r#"{"title":" Moby Dick ","author":"Herman Melville","language":"en",
+                "published":"1851-10-18","chapters":[
+                {"title":"Loomings","blocks":[
+                    {"text":"Loomings","heading":true},{"text":"Call me Ishmael."}]},
+                {"title":null,"blocks":[{"text":"  "}]},
+                {"title":"The Carpet-Bag","blocks":[
+                    {"text":"The Carpet-Bag","heading":true},{"text":"I stuffed a shirt or two."}]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let metadata = extracted.metadata.normalized();
+        assert_eq!(metadata.title.as_deref(), Some("Moby Dick"));
+        assert_eq!(metadata.author.as_deref(), Some("Herman Melville"));
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!(metadata.published.as_deref(), Some("1851-10-18"));
+        assert_eq!(
+            extracted.blocks,
+            vec![
+                Block::heading("Loomings"),
+                Block::paragraph("Call me Ishmael."),
+                Block::heading("The Carpet-Bag"),
+                Block::paragraph("I stuffed a shirt or two."),
+            ]
+        );
+    }
+
+    #[test]
+    fn epub_without_metadata_falls_back_to_none() {
+        let extracted = parse_epub_json(
+            r#"{"title":"","author":null,"chapters":[{"blocks":[{"text":"Body"}]}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(extracted.metadata.normalized(), Metadata::default());
+    }
+
+    #[test]
+    fn markdown_front_matter_becomes_metadata_not_text() {
+        let extracted =
+            markdown_extracted("---\ntitle: Field Notes\nauthor: Ada\n---\n# Day One\n\nRain.\n");
+
+        assert_eq!(extracted.metadata.title.as_deref(), Some("Field Notes"));
+        assert_eq!(extracted.metadata.author.as_deref(), Some("Ada"));
+        assert_eq!(
+            extracted.blocks,
+            vec![Block::heading("Day One"), Block::paragraph("Rain.")]
+        );
+    }
+
+    #[test]
+    fn markdown_keeps_headings_paragraphs_and_list_items() {
+        let blocks = markdown_blocks(
+            "# Title\n\nFirst line\nsame paragraph.\n\n- one\n- two\n\n```\nlet code = 1;\n```\n\n## Next\n",
+        );
+
+        assert_eq!(
+            blocks,
+            vec![
+                Block::heading("Title"),
+                Block::paragraph("First line  same paragraph."),
+                Block::paragraph("one"),
+                Block::paragraph("two"),
+                Block::heading("Next"),
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_text_splits_at_blank_lines() {
+        let extracted = Extracted::from("One\ntwo\n\nThree".to_string());
+
+        assert_eq!(
+            extracted.blocks,
+            vec![Block::paragraph("One two"), Block::paragraph("Three")]
+        );
+    }
+
+    #[test]
+    fn pdf_outline_entries_become_headings_at_their_pages() {
+        let document: PdfDocument = serde_json::from_str(
+            r#"{"pages":["Cover page","CHAPTER  1 It was a dark night.","More text","Chapter 10"],
+                "outline":[{"title":"Chapter 1","page":1},{"title":"Chapter 10","page":3},
+                           {"title":"Missing","page":9},{"title":"Chapter","page":3}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            pdf_blocks(document, PdfCleanup::OFF),
+            vec![
+                Block::paragraph("Cover page"),
+                Block::heading("Chapter 1"),
+                Block::paragraph("It was a dark night."),
+                Block::paragraph("More text"),
+                Block::heading("Chapter 10"),
+                Block::heading("Chapter"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pdf_paragraphs_rejoin_across_pages_but_not_into_sections() {
+        let document: PdfDocument = serde_json::from_str(
+            r#"{"pages":["It was a bright cold day in April and the",
+                         "clocks were striking thirteen in the town.\nShort.\nWinston Smith, his chin nuzzled into his",
+                         "Chapter 2 A new start for the whole story here."],
+                "outline":[{"title":"Chapter 2","page":2}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            pdf_blocks(document, PdfCleanup::default()),
+            vec![
+                Block::paragraph(
+                    "It was a bright cold day in April and the clocks were striking thirteen in the town. Short."
+                ),
+                Block::paragraph("Winston Smith, his chin nuzzled into his"),
+                Block::heading("Chapter 2"),
+                Block::paragraph("A new start for the whole story here."),
+            ]
+        );
+    }
+
+    #[test]
+    fn pasted_text_is_sanitized_and_split_into_paragraphs() {
+        let doc = parse_pasted_text(
+            "Hi team,\n\nThe <script>alert(1)</script>report is attached.\n",
+            "  ",
+        )
+        .unwrap();
+
+        assert_eq!(doc.title, "Hi team,");
+        assert_eq!(doc.file_type, "PASTE");
+        assert_eq!(doc.content, "Hi team,\n\nThe report is attached.");
+        assert_eq!(
+            parse_pasted_text("Some text", " Notes ").unwrap().title,
+            "Notes"
+        );
+    }
+
+    #[test]
+    fn blank_pastes_are_rejected() {
+        assert!(matches!(
+            parse_pasted_text(" \n\t", ""),
+            Err(ParseError::NoText)
+        ));
+        assert!(matches!(
+            parse_pasted_text("<script>alert(1)</script>", ""),
+            Err(ParseError::NoText)
+        ));
+    }
+
+    #[test]
+    fn generated_titles_stop_at_a_word_boundary() {
+        let text =
+            "\n\n  The quick brown fox jumps over the lazy dog and keeps running far away\nmore";
+        let title = pasted_title(text);
+
+        assert_eq!(
+            title,
+            "The quick brown fox jumps over the lazy dog and keeps…"
+        );
+        assert_eq!(pasted_title(&"x".repeat(100)).chars().count(), 61);
+        assert_eq!(pasted_title(""), "");
+    }
 }
@@ -0,0 +1,94 @@
+/// A timer callback more than this late starts a fresh frame instead of
+/// replaying the missed ones, e.g. after the tab was in the background.
+const MAX_CATCH_UP_MS: f64 = 1000.0;
+
+/// Time one flash of `words` words stays on screen at `wpm`.
+pub fn frame_duration_ms(wpm: u32, words: usize) -> f64 {
+    60_000.0 / wpm.max(1) as f64 * words.max(1) as f64
+}
+
+/// Deadlines for playback frames on a monotonic clock such as
+/// `Performance::now`.
+///
+/// Each frame is scheduled from the previous frame's deadline rather than from
+/// when the timer actually fired, so late callbacks shorten the next delay and
+/// timer drift does not accumulate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameClock {
+    frame_start: f64,
+    deadline: f64,
+}
+
+impl FrameClock {
+    pub fn start(now: f64, duration_ms: f64) -> Self {
+        Self {
+            frame_start: now,
+            deadline: now + duration_ms,
+        }
+    }
+
+    /// Begins the next frame once the current deadline has passed.
+    pub fn advance(&mut self, now: f64, duration_ms: f64) {
+        let start = if now - self.deadline > MAX_CATCH_UP_MS {
+            now
+        } else {
+            self.deadline
+        };
+        self.frame_start = start;
+        self.deadline = start + duration_ms;
+    }
+
+    /// Applies a new duration to the frame on screen, keeping its start time.
+    pub fn retime(&mut self, duration_ms: f64) {
+        self.deadline = self.frame_start + duration_ms;
+    }
+
+    /// Milliseconds to wait before the current frame ends.
+    pub fn delay_ms(&self, now: f64) -> f64 {
+        (self.deadline - now).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame_duration_ms, FrameClock};
+
+    #[test]
+    fn frame_duration_scales_with_words_per_flash() {
+        assert_eq!(frame_duration_ms(300, 1), 200.0);
+        assert_eq!(frame_duration_ms(300, 5), 1000.0);
+        assert_eq!(frame_duration_ms(0, 0), 60_000.0);
+    }
+
+    #[test]
+    fn late_callbacks_do_not_accumulate_drift() {
+        let mut clock = FrameClock::start(0.0, 200.0);
+
+        // The timer fires 15ms late; the next frame still ends at 400ms.
+        clock.advance(215.0, 200.0);
+        assert_eq!(clock.delay_ms(215.0), 185.0);
+
+        clock.advance(404.0, 200.0);
+        assert_eq!(clock.delay_ms(404.0), 196.0);
+    }
+
+    #[test]
+    fn long_stalls_restart_from_now_instead_of_bursting() {
+        let mut clock = FrameClock::start(0.0, 200.0);
+
+        clock.advance(5000.0, 200.0);
+
+        assert_eq!(clock.delay_ms(5000.0), 200.0);
+    }
+
+    #[test]
+    fn retiming_applies_to_the_frame_on_screen() {
+        let mut clock = FrameClock::start(1000.0, 200.0);
+
+        clock.retime(100.0);
+        assert_eq!(clock.delay_ms(1050.0), 50.0);
+
+        clock.retime(50.0);
+        assert_eq!(clock.delay_ms(1080.0), 0.0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::state::{DocumentMeta, LibraryView, ReadingProgress, SortKey};
+use crate::tokens::Token;
+
+/// Most hits listed for one search; counting continues past it.
+pub const MAX_LISTED_HITS: usize = 500;
+
+/// Lowercase alphanumeric form used to compare words, e.g. `"Ishmael."` to `ishmael`.
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Token ranges matching `query`. Each query word must appear inside the
+/// matching token, so `whale` also finds `whales,` and `Whale-ship`.
+pub fn find_in_tokens(tokens: &[Token], query: &str) -> Vec<Range<usize>> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(normalize)
+        .filter(|term| !term.is_empty())
+        .collect();
+    if terms.is_empty() || terms.len() > tokens.len() {
+        return Vec::new();
+    }
+
+    let words: Vec<String> = tokens.iter().map(|token| normalize(&token.text)).collect();
+    (0..=words.len() - terms.len())
+        .filter(|&start| {
+            terms
+                .iter()
+                .zip(&words[start..])
+                .all(|(term, word)| word.contains(term.as_str()))
+        })
+        .map(|start| start..start + terms.len())
+        .collect()
+}
+
+/// Start of the first hit after `index`, wrapping to the first hit.
+pub fn next_hit(hits: &[Range<usize>], index: usize) -> Option<usize> {
+    hits.iter()
+        .map(|hit| hit.start)
+        .find(|&start| start > index)
+        .or_else(|| hits.first().map(|hit| hit.start))
+}
+
+/// Start of the last hit before `index`, wrapping to the last hit.
+pub fn previous_hit(hits: &[Range<usize>], index: usize) -> Option<usize> {
+    hits.iter()
+        .map(|hit| hit.start)
+        .rev()
+        .find(|&start| start < index)
+        .or_else(|| hits.last().map(|hit| hit.start))
+}
+
+/// Whether every word of `query` appears in the document's title, author, or
+/// `content`, ignoring case. Pass empty content to match metadata alone.
+pub fn document_matches(doc: &DocumentMeta, content: &str, query: &str) -> bool {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return true;
+    }
+
+    let fields = [
+        doc.title.to_lowercase(),
+        doc.author.as_deref().unwrap_or_default().to_lowercase(),
+        content.to_lowercase(),
+    ];
+    terms
+        .iter()
+        .all(|term| fields.iter().any(|field| field.contains(term.as_str())))
+}
+
+/// Documents passing the view's filter and `query`, in the view's order. A
+/// document matches on its metadata or by being in `content_hits`, the ids
+/// found by searching document text. Ties fall back to title order. When
+/// sorting by last opened, documents never opened count as opened before all
+/// others, so they come first in ascending order and last in descending order.
+pub fn library_documents(
+    documents: &[DocumentMeta],
+    progress: &HashMap<String, ReadingProgress>,
+    view: &LibraryView,
+    query: &str,
+    content_hits: &HashSet<String>,
+) -> Vec<DocumentMeta> {
+    let mut shown: Vec<DocumentMeta> = documents
+        .iter()
+        .filter(|doc| {
+            view.file_type
+                .as_ref()
+                .is_none_or(|file_type| &doc.file_type == file_type)
+        })
+        .filter(|doc| document_matches(doc, "", query) || content_hits.contains(&doc.id))
+        .cloned()
+        .collect();
+
+    let compare = |a: &DocumentMeta, b: &DocumentMeta| -> Ordering {
+        let by_key = match view.sort {
+            SortKey::Title => Ordering::Equal,
+            SortKey::Added => a.created_at.cmp(&b.created_at),
+            SortKey::WordCount => a.word_count.cmp(&b.word_count),
+            SortKey::LastOpened => {
+                let opened =
+                    |doc: &DocumentMeta| progress.get(&doc.id).map(|p| p.updated_at.clone());
+                opened(a).cmp(&opened(b))
+            }
+            SortKey::Progress => {
+                let percent = |doc: &DocumentMeta| progress.get(&doc.id).map_or(0, |p| p.percent());
+                percent(a).cmp(&percent(b))
+            }
+        };
+        let by_key = if view.descending {
+            by_key.reverse()
+        } else {
+            by_key
+        };
+        by_key.then_with(|| {
+            let by_title = a.title.to_lowercase().cmp(&b.title.to_lowercase());
+            if view.descending && view.sort == SortKey::Title {
+                by_title.reverse()
+            } else {
+                by_title
+            }
+        })
+    };
+    shown.sort_by(compare);
+
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::{document_matches, find_in_tokens, library_documents, next_hit, previous_hit};
+    use crate::state::{Document, DocumentMeta, LibraryView, ReadingProgress, SortKey};
+    use crate::tokens::{tokenize, Block};
+
+    fn document(title: &str, file_type: &str, text: &str, created_at: &str) -> DocumentMeta {
+        let mut doc = Document::new(title.into(), vec![Block::paragraph(text)], file_type.into());
+        doc.id = title.into();
+        doc.created_at = created_at.into();
+        doc.meta()
+    }
+
+    #[test]
+    fn finds_words_and_phrases_ignoring_case_and_punctuation() {
+        let tokens = tokenize(
+            "The Whale. A white whale, whales!\n\nWhite whale again",
+            &[],
+        );
+
+        assert_eq!(
+            find_in_tokens(&tokens, "whale"),
+            vec![1..2, 4..5, 5..6, 7..8]
+        );
+        assert_eq!(find_in_tokens(&tokens, "WHITE whale"), vec![3..5, 6..8]);
+        assert!(find_in_tokens(&tokens, " ,. ").is_empty());
+        assert!(find_in_tokens(&tokens, "absent").is_empty());
+    }
+
+    #[test]
+    fn hit_navigation_wraps_around() {
+        let hits = [3..4, 8..10, 20..21];
+
+        assert_eq!(next_hit(&hits, 3), Some(8));
+        assert_eq!(next_hit(&hits, 20), Some(3));
+        assert_eq!(previous_hit(&hits, 8), Some(3));
+        assert_eq!(previous_hit(&hits, 2), Some(20));
+        assert_eq!(next_hit(&[], 0), None);
+    }
+
+    #[test]
+    fn library_search_covers_title_author_and_content() {
+        let mut doc = document("Moby Dick", "EPUB", "", "2024");
+        doc.author = Some("Herman Melville".into());
+        let content = "Call me Ishmael.";
+
+        assert!(document_matches(&doc, "", "moby"));
+        assert!(document_matches(&doc, content, "melville ISHMAEL"));
+        assert!(!document_matches(&doc, "", "ishmael"));
+        assert!(document_matches(&doc, "", "  "));
+        assert!(!document_matches(&doc, content, "ishmael ahab"));
+    }
+
+    #[test]
+    fn library_view_filters_then_sorts() {
+        let docs = vec![
+            document("b", "PDF", "one two three", "2024-02"),
+            document("a", "TXT", "one", "2024-03"),
+            document("c", "PDF", "one two", "2024-01"),
+        ];
+        let mut progress = HashMap::new();
+        progress.insert("c".to_string(), ReadingProgress::new("c".into(), 1, 2));
+        let content_hits: HashSet<String> = ["b".to_string()].into();
+        let titles = |view: &LibraryView, query: &str| -> Vec<String> {
+            library_documents(&docs, &progress, view, query, &content_hits)
+                .into_iter()
+                .map(|doc| doc.title)
+                .collect()
+        };
+
+        assert_eq!(titles(&LibraryView::default(), ""), ["a", "b", "c"]);
+        let by_words = LibraryView {
+            sort: SortKey::WordCount,
+            descending: false,
+            file_type: Some("PDF".into()),
+        };
+        assert_eq!(titles(&by_words, ""), ["c", "b"]);
+        let by_progress = LibraryView {
+            sort: SortKey::Progress,
+            descending: true,
+            file_type: None,
+        };
+        assert_eq!(titles(&by_progress, ""), ["c", "a", "b"]);
+        let by_title = LibraryView {
+            sort: SortKey::Title,
+            descending: false,
+            file_type: None,
+        };
+        // Text is only searched through the content hits, here just "b".
+        assert_eq!(titles(&by_title, "two"), ["b"]);
+        assert_eq!(titles(&by_title, "c"), ["b", "c"]);
+    }
+}
@@ -1,59 +1,406 @@
+use std::collections::HashMap;
+
 use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::timing::TimingConfig;
+use crate::tokens::{tokenize, Block, Token};
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Document {
     pub id: String,
     pub title: String,
     pub author: Option<String>,
+    /// Language tag from the file's metadata, such as `en` or `fr-CA`.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Publication date from the file's metadata, as `YYYY`, `YYYY-MM`, or
+    /// `YYYY-MM-DD`.
+    #[serde(default)]
+    pub published: Option<String>,
+    /// Plain text with paragraphs separated by blank lines.
     pub content: String,
+    /// Indices of the paragraphs in `content` that are headings.
+    pub headings: Vec<usize>,
     pub file_type: String,
     pub created_at: String,
     pub word_count: usize,
 }
 
 impl Document {
-    pub fn new(title: String, content: String, file_type: String) -> Self {
+    pub fn new(title: String, blocks: Vec<Block>, file_type: String) -> Self {
+        let mut paragraphs = Vec::new();
+        let mut headings = Vec::new();
+        for block in blocks {
+            let text = block.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                continue;
+            }
+            if block.heading {
+                headings.push(paragraphs.len());
+            }
+            paragraphs.push(text);
+        }
+
+        let content = paragraphs.join("\n\n");
         let word_count = content.split_whitespace().count();
         Self {
             id: uuid(),
             title,
             author: None,
+            language: None,
+            published: None,
             content,
+            headings,
             file_type,
             created_at: chrono::Utc::now().to_rfc3339(),
             word_count,
         }
     }
+
+    pub fn tokens(&self) -> Vec<Token> {
+        tokenize(&self.content, &self.headings)
+    }
+
+    pub fn meta(&self) -> DocumentMeta {
+        DocumentMeta {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            author: self.author.clone(),
+            language: self.language.clone(),
+            published: self.published.clone(),
+            file_type: self.file_type.clone(),
+            created_at: self.created_at.clone(),
+            word_count: self.word_count,
+        }
+    }
+
+    pub fn body(&self) -> DocumentBody {
+        DocumentBody {
+            content: self.content.clone(),
+            headings: self.headings.clone(),
+        }
+    }
+
+    pub fn from_parts(meta: DocumentMeta, body: DocumentBody) -> Self {
+        Self {
+            id: meta.id,
+            title: meta.title,
+            author: meta.author,
+            language: meta.language,
+            published: meta.published,
+            content: body.content,
+            headings: body.headings,
+            file_type: meta.file_type,
+            created_at: meta.created_at,
+            word_count: meta.word_count,
+        }
+    }
+}
+
+/// The fields the library lists, stored apart from the document text so the
+/// library never decrypts document bodies.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DocumentMeta {
+    pub id: String,
+    pub title: String,
+    pub author: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub published: Option<String>,
+    pub file_type: String,
+    pub created_at: String,
+    pub word_count: usize,
+}
+
+/// The text of a document, read only when the document is opened or searched.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DocumentBody {
+    pub content: String,
+    pub headings: Vec<usize>,
+}
+
+/// Last reading position saved for a document, stored apart from its content.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReadingProgress {
+    pub document_id: String,
+    pub word_index: usize,
+    pub word_count: usize,
+    pub updated_at: String,
+}
+
+impl ReadingProgress {
+    pub fn new(document_id: String, word_index: usize, word_count: usize) -> Self {
+        Self {
+            document_id,
+            word_index,
+            word_count,
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    pub fn percent(&self) -> u32 {
+        if self.word_count == 0 {
+            return 0;
+        }
+        ((self.word_index as f64 / self.word_count as f64) * 100.0).round() as u32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.word_count > 0 && self.word_index + 1 >= self.word_count
+    }
+
+    /// Index to reopen the reader at. Finished documents start over.
+    pub fn resume_index(&self) -> usize {
+        if self.is_finished() {
+            0
+        } else {
+            self.word_index.min(self.word_count.saturating_sub(1))
+        }
+    }
+
+    /// Whether this position replaces `other` when both were saved for the
+    /// same document, e.g. from two tabs. The later save wins; a tie goes to
+    /// the position further along, so every tab settles on the same one.
+    pub fn supersedes(&self, other: &ReadingProgress) -> bool {
+        (&self.updated_at, self.word_index) > (&other.updated_at, other.word_index)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AnnotationKind {
+    Bookmark,
+    Highlight,
+}
+
+/// A bookmark or highlighted passage, with an optional note.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Annotation {
+    pub id: String,
+    pub document_id: String,
+    pub kind: AnnotationKind,
+    /// Word range the annotation covers; a bookmark covers one word.
+    pub start: usize,
+    pub end: usize,
+    /// The highlighted sentence, or the sentence around a bookmark.
+    pub text: String,
+    pub note: String,
+    pub created_at: String,
+}
+
+impl Annotation {
+    pub fn new(
+        document_id: String,
+        kind: AnnotationKind,
+        start: usize,
+        end: usize,
+        text: String,
+    ) -> Self {
+        Self {
+            id: uuid(),
+            document_id,
+            kind,
+            start,
+            end,
+            text,
+            note: String::new(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+pub const MIN_WPM: u32 = 100;
+pub const MAX_WPM: u32 = 1000;
+pub const FONT_SIZES: [&str; 4] = ["S", "M", "L", "XL"];
+pub const CHUNK_SIZES: [u8; 5] = [1, 3, 5, 10, 20];
+/// Minutes without input before the vault locks; 0 never locks.
+pub const AUTO_LOCK_MINUTES: [u32; 5] = [0, 5, 15, 30, 60];
+
+/// Reader preferences persisted across sessions. New options must have a
+/// default so settings saved by older versions keep loading.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub wpm: u32,
+    pub font_size: String,
+    pub chunk_size: u8,
+    pub timing: TimingConfig,
+    /// Only used when the passphrase vault is on.
+    pub auto_lock_minutes: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            wpm: 300,
+            font_size: "L".to_string(),
+            chunk_size: 1,
+            timing: TimingConfig::default(),
+            auto_lock_minutes: 15,
+        }
+    }
+}
+
+impl Settings {
+    /// Replaces out-of-range values, e.g. from a hand-edited or older record.
+    pub fn normalized(self) -> Self {
+        let defaults = Self::default();
+        Self {
+            wpm: self.wpm.clamp(MIN_WPM, MAX_WPM),
+            font_size: if FONT_SIZES.contains(&self.font_size.as_str()) {
+                self.font_size
+            } else {
+                defaults.font_size
+            },
+            chunk_size: nearest_chunk_size(self.chunk_size),
+            timing: self.timing.normalized(),
+            auto_lock_minutes: if AUTO_LOCK_MINUTES.contains(&self.auto_lock_minutes) {
+                self.auto_lock_minutes
+            } else {
+                defaults.auto_lock_minutes
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SortKey {
+    Title,
+    #[default]
+    Added,
+    WordCount,
+    LastOpened,
+    Progress,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 5] = [
+        SortKey::Title,
+        SortKey::Added,
+        SortKey::WordCount,
+        SortKey::LastOpened,
+        SortKey::Progress,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Title => "Title",
+            SortKey::Added => "Date added",
+            SortKey::WordCount => "Word count",
+            SortKey::LastOpened => "Last opened",
+            SortKey::Progress => "Progress",
+        }
+    }
+}
+
+/// How the library is sorted and filtered, persisted across sessions.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LibraryView {
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Only documents of this `file_type`, or all when `None`.
+    pub file_type: Option<String>,
+}
+
+impl Default for LibraryView {
+    fn default() -> Self {
+        Self {
+            sort: SortKey::Added,
+            descending: true,
+            file_type: None,
+        }
+    }
+}
+
+fn nearest_chunk_size(size: u8) -> u8 {
+    *CHUNK_SIZES
+        .iter()
+        .min_by_key(|&&s| (s as i32 - size as i32).abs())
+        .unwrap_or(&1)
 }
 
 #[derive(Clone, Copy)]
 pub struct AppState {
-    pub documents: RwSignal<Vec<Document>>,
-    pub current_words: RwSignal<Vec<String>>,
+    pub documents: RwSignal<Vec<DocumentMeta>>,
+    pub progress: RwSignal<HashMap<String, ReadingProgress>>,
+    pub current_tokens: RwSignal<Vec<Token>>,
     pub wpm: RwSignal<u32>,
     pub font_size: RwSignal<String>,
     pub chunk_size: RwSignal<u8>,
+    pub timing: RwSignal<TimingConfig>,
+    pub auto_lock_minutes: RwSignal<u32>,
+    /// Whether the passphrase vault is locked; nothing is loaded until it opens.
+    pub locked: RwSignal<bool>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(settings: Settings) -> Self {
+        let settings = settings.normalized();
         Self {
             documents: RwSignal::new(Vec::new()),
-            current_words: RwSignal::new(Vec::new()),
-            wpm: RwSignal::new(300),
-            font_size: RwSignal::new("L".to_string()),
-            chunk_size: RwSignal::new(1),
+            progress: RwSignal::new(HashMap::new()),
+            current_tokens: RwSignal::new(Vec::new()),
+            wpm: RwSignal::new(settings.wpm),
+            font_size: RwSignal::new(settings.font_size),
+            chunk_size: RwSignal::new(settings.chunk_size),
+            timing: RwSignal::new(settings.timing),
+            auto_lock_minutes: RwSignal::new(settings.auto_lock_minutes),
+            locked: RwSignal::new(false),
         }
     }
 
-    pub fn add_document(&self, doc: Document) {
-        self.documents.update(|docs| docs.push(doc));
+    /// Current settings; tracked when called inside a reactive scope.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            wpm: self.wpm.get(),
+            font_size: self.font_size.get(),
+            chunk_size: self.chunk_size.get(),
+            timing: self.timing.get(),
+            auto_lock_minutes: self.auto_lock_minutes.get(),
+        }
+    }
+
+    pub fn apply_settings(&self, settings: Settings) {
+        let settings = settings.normalized();
+        self.wpm.set(settings.wpm);
+        self.font_size.set(settings.font_size);
+        self.chunk_size.set(settings.chunk_size);
+        self.timing.set(settings.timing);
+        self.auto_lock_minutes.set(settings.auto_lock_minutes);
+    }
+
+    pub fn reset_settings(&self) {
+        self.apply_settings(Settings::default());
+    }
+
+    /// Lists a saved document; imports may finish after the page that started
+    /// them is gone, so a disposed signal is ignored.
+    pub fn add_document(&self, doc: DocumentMeta) {
+        let _ = self.documents.try_update(|docs| docs.push(doc));
+    }
+
+    pub fn set_progress(&self, progress: ReadingProgress) {
+        self.progress.update(|all| {
+            all.insert(progress.document_id.clone(), progress);
+        });
+    }
+
+    /// Keeps `progress` unless the position already known for its document
+    /// supersedes it.
+    pub fn merge_progress(&self, progress: ReadingProgress) {
+        let _ = self.progress.try_update(|all| {
+            let known = all.get(&progress.document_id);
+            if known.is_none_or(|known| progress.supersedes(known)) {
+                all.insert(progress.document_id.clone(), progress);
+            }
+        });
     }
 
     pub fn adjust_wpm(&self, delta: i32) {
         let current = self.wpm.get() as i32;
-        let new_value = (current + delta).clamp(100, 1000) as u32;
+        let new_value = (current + delta).clamp(MIN_WPM as i32, MAX_WPM as i32) as u32;
         self.wpm.set(new_value);
     }
 
@@ -61,19 +408,19 @@ impl AppState {
         self.font_size.set(size);
     }
 
+    pub fn set_wpm(&self, wpm: u32) {
+        self.wpm.set(wpm.clamp(MIN_WPM, MAX_WPM));
+    }
+
     pub fn set_chunk_size(&self, size: u8) {
-        // Valid chunk sizes: 1, 3, 5, 10, 20
-        let valid_sizes = [1, 3, 5, 10, 20];
-        let clamped = if valid_sizes.contains(&size) {
-            size
-        } else {
-            // Find nearest valid size
-            *valid_sizes
-                .iter()
-                .min_by_key(|&&s| (s as i32 - size as i32).abs())
-                .unwrap_or(&1)
-        };
-        self.chunk_size.set(clamped);
+        self.chunk_size.set(nearest_chunk_size(size));
+    }
+
+    pub fn update_timing(&self, f: impl FnOnce(&mut TimingConfig)) {
+        self.timing.update(|timing| {
+            f(timing);
+            *timing = std::mem::take(timing).normalized();
+        });
     }
 }
 
@@ -96,3 +443,114 @@ fn uuid() -> String {
         ]) & 0xffffffffffff
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, ReadingProgress, Settings};
+    use crate::tokens::Block;
+
+    #[test]
+    fn documents_keep_paragraphs_and_heading_positions() {
+        let doc = Document::new(
+            "Book".into(),
+            vec![
+                Block::heading(" Part\n One "),
+                Block::paragraph("   "),
+                Block::paragraph("First  paragraph."),
+                Block::heading("Part Two"),
+            ],
+            "MD".into(),
+        );
+
+        assert_eq!(doc.content, "Part One\n\nFirst paragraph.\n\nPart Two");
+        assert_eq!(doc.headings, vec![0, 2]);
+        assert_eq!(doc.word_count, 6);
+        assert_eq!(doc.tokens().len(), 6);
+    }
+
+    #[test]
+    fn documents_split_into_metadata_and_body_and_back() {
+        let doc = Document::new(
+            "Book".into(),
+            vec![Block::heading("One"), Block::paragraph("Text")],
+            "MD".into(),
+        );
+
+        let rebuilt = Document::from_parts(doc.meta(), doc.body());
+
+        assert_eq!(rebuilt, doc);
+        assert!(!serde_json::to_string(&doc.meta()).unwrap().contains("Text"));
+    }
+
+    #[test]
+    fn progress_reports_percent_and_resumes_mid_document() {
+        let progress = ReadingProgress::new("doc".into(), 250, 1000);
+
+        assert_eq!(progress.percent(), 25);
+        assert!(!progress.is_finished());
+        assert_eq!(progress.resume_index(), 250);
+    }
+
+    #[test]
+    fn later_positions_supersede_earlier_ones() {
+        let mut earlier = ReadingProgress::new("doc".into(), 500, 1000);
+        earlier.updated_at = "2024-01-01T00:00:00+00:00".into();
+        let mut later = ReadingProgress::new("doc".into(), 100, 1000);
+        later.updated_at = "2024-01-01T00:00:01+00:00".into();
+        let mut tied = later.clone();
+        tied.word_index = 200;
+
+        assert!(later.supersedes(&earlier));
+        assert!(!earlier.supersedes(&later));
+        assert!(tied.supersedes(&later));
+        assert!(!later.supersedes(&tied));
+        assert!(!later.supersedes(&later));
+    }
+
+    #[test]
+    fn finished_progress_starts_over() {
+        let progress = ReadingProgress::new("doc".into(), 999, 1000);
+
+        assert!(progress.is_finished());
+        assert_eq!(progress.resume_index(), 0);
+    }
+
+    #[test]
+    fn stale_progress_is_clamped_to_document_length() {
+        let progress = ReadingProgress::new("doc".into(), 40, 0);
+
+        assert_eq!(progress.percent(), 0);
+        assert_eq!(progress.resume_index(), 0);
+    }
+
+    #[test]
+    fn settings_from_older_records_fill_missing_options_with_defaults() {
+        let settings: Settings = serde_json::from_str(r#"{"wpm":450}"#).unwrap();
+
+        assert_eq!(settings.wpm, 450);
+        assert_eq!(
+            settings,
+            Settings {
+                wpm: 450,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn settings_normalize_out_of_range_values() {
+        let settings = Settings {
+            wpm: 5000,
+            font_size: "XXL".into(),
+            chunk_size: 7,
+            auto_lock_minutes: 7,
+            ..Settings::default()
+        }
+        .normalized();
+
+        assert_eq!(settings.wpm, 1000);
+        assert_eq!(settings.font_size, "L");
+        assert_eq!(settings.chunk_size, 5);
+        assert_eq!(settings.auto_lock_minutes, 15);
+    }
+}
@@ -0,0 +1,462 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::vault::KdfParams;
+use super::{decrypt_with, encrypt_with, DocumentStore, RecordBatch, StorageError};
+use crate::state::{Annotation, Document, ReadingProgress, Settings};
+
+const BACKUP_FORMAT: &str = "nabu-backup";
+/// Newest backup layout this build reads and the one it writes.
+const BACKUP_VERSION: u32 = 1;
+
+/// Everything a backup restores. This is the JSON inside the encrypted part of
+/// a backup file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BackupContents {
+    pub documents: Vec<Document>,
+    #[serde(default)]
+    pub progress: Vec<ReadingProgress>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub settings: Option<Settings>,
+}
+
+/// A backup file. Only `format`, `version`, `created_at`, and `kdf` are
+/// readable without the passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    version: u32,
+    created_at: String,
+    kdf: KdfParams,
+    /// Nonce followed by the AES-GCM encrypted `BackupContents`, base64 encoded.
+    contents: String,
+}
+
+fn seal_backup(
+    contents: &BackupContents,
+    passphrase: &str,
+    kdf: KdfParams,
+) -> Result<String, StorageError> {
+    let key = kdf.derive_key(passphrase)?;
+    let json =
+        serde_json::to_vec(contents).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    let file = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        kdf,
+        contents: BASE64.encode(encrypt_with(&key, &json)?),
+    };
+    serde_json::to_string_pretty(&file).map_err(|e| StorageError::Serialization(e.to_string()))
+}
+
+fn open_backup(text: &str, passphrase: &str) -> Result<BackupContents, StorageError> {
+    let file: BackupFile = serde_json::from_str(text)
+        .map_err(|_| StorageError::Backup("This is not a Nabu backup file".into()))?;
+    if file.format != BACKUP_FORMAT {
+        return Err(StorageError::Backup(
+            "This is not a Nabu backup file".into(),
+        ));
+    }
+    if file.version > BACKUP_VERSION {
+        return Err(StorageError::Backup(format!(
+            "This backup was made by a newer version of Nabu (format {})",
+            file.version
+        )));
+    }
+
+    let encrypted = BASE64
+        .decode(&file.contents)
+        .map_err(|e| StorageError::Backup(format!("The backup is damaged: {}", e)))?;
+    if !file.kdf.within_limits() {
+        return Err(StorageError::Backup(
+            "The backup is damaged: its key settings are out of range".into(),
+        ));
+    }
+    let key = file.kdf.derive_key(passphrase)?;
+    // Authentication fails for a wrong passphrase or a modified file.
+    let json = decrypt_with(&key, &encrypted).map_err(|_| StorageError::WrongPassphrase)?;
+    serde_json::from_slice(&json)
+        .map_err(|e| StorageError::Backup(format!("The backup is damaged: {}", e)))
+}
+
+/// How a backed-up document relates to the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Merge {
+    Add,
+    Unchanged,
+    /// Same id, different document; the library copy is kept.
+    Conflict,
+}
+
+fn merge_kind(existing: Option<&Document>, incoming: &Document) -> Merge {
+    match existing {
+        None => Merge::Add,
+        Some(doc) if doc == incoming => Merge::Unchanged,
+        Some(_) => Merge::Conflict,
+    }
+}
+
+/// Whether a backed-up reading position should replace `existing`.
+fn is_newer_progress(existing: Option<&ReadingProgress>, incoming: &ReadingProgress) -> bool {
+    existing.is_none_or(|progress| incoming.supersedes(progress))
+}
+
+/// What an import changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub added: usize,
+    pub unchanged: usize,
+    /// Documents restored over a library copy that could not be read.
+    pub repaired: usize,
+    /// Titles of backed-up documents whose id is already used by a different
+    /// document in the library. The library copy is kept.
+    pub conflicts: Vec<String>,
+    /// The backup's settings, returned only when importing into an empty library.
+    pub settings: Option<Settings>,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Added {} document{}",
+            self.added,
+            if self.added == 1 { "" } else { "s" }
+        );
+        if self.unchanged > 0 {
+            summary.push_str(&format!("; {} already in the library", self.unchanged));
+        }
+        if self.repaired > 0 {
+            summary.push_str(&format!(
+                "; restored {} over unreadable library copies",
+                self.repaired
+            ));
+        }
+        if !self.conflicts.is_empty() {
+            summary.push_str(&format!(
+                "; kept the library copy of {} that differ: {}",
+                self.conflicts.len(),
+                self.conflicts.join(", ")
+            ));
+        }
+        summary.push('.');
+        summary
+    }
+}
+
+/// A backup file and the documents left out of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupExport {
+    pub text: String,
+    /// Titles, or ids, of documents that could not be read.
+    pub unreadable: Vec<String>,
+}
+
+impl BackupExport {
+    pub fn summary(&self) -> String {
+        let mut summary = "Backup saved".to_string();
+        if !self.unreadable.is_empty() {
+            summary.push_str(&format!(
+                " without {} document{} that could not be read: {}",
+                self.unreadable.len(),
+                if self.unreadable.len() == 1 { "" } else { "s" },
+                self.unreadable.join(", ")
+            ));
+        }
+        summary.push_str(". Keep the passphrase; it cannot be recovered.");
+        summary
+    }
+}
+
+/// The whole library, reading positions, and `settings` as one backup file
+/// encrypted with `passphrase`. Documents that cannot be read are left out
+/// and reported.
+pub async fn export_backup(
+    store: &dyn DocumentStore,
+    passphrase: &str,
+    settings: Settings,
+) -> Result<BackupExport, StorageError> {
+    let read = store.get_all_documents().await?;
+    let contents = BackupContents {
+        documents: read.documents,
+        progress: store.get_all_progress().await?,
+        annotations: store.get_all_annotations().await?,
+        settings: Some(settings),
+    };
+
+    Ok(BackupExport {
+        text: seal_backup(&contents, passphrase, KdfParams::new()?)?,
+        unreadable: read.unreadable,
+    })
+}
+
+/// Adds the documents in a backup that are not in the library yet. A document
+/// whose id is taken by a different document is reported and skipped, along
+/// with its position and annotations. Everything is saved in one batch, so a
+/// failed import leaves the library as it was.
+pub async fn import_backup(
+    store: &dyn DocumentStore,
+    text: &str,
+    passphrase: &str,
+) -> Result<ImportReport, StorageError> {
+    let contents = open_backup(text, passphrase)?;
+    let library_was_empty = store.get_all_metadata().await?.is_empty();
+    let mut report = ImportReport::default();
+    let mut batch = RecordBatch::default();
+
+    // Documents whose positions and annotations come along.
+    let mut merged = HashSet::new();
+    for doc in &contents.documents {
+        let existing = match store.get_document(&doc.id).await {
+            Ok(existing) => existing,
+            // A library copy that cannot be decrypted or decoded is what a
+            // backup is for; the backup's copy replaces it.
+            Err(_) => {
+                batch.documents.push(doc.clone());
+                report.repaired += 1;
+                merged.insert(doc.id.as_str());
+                continue;
+            }
+        };
+        match merge_kind(existing.as_ref(), doc) {
+            Merge::Add => {
+                batch.documents.push(doc.clone());
+                report.added += 1;
+                merged.insert(doc.id.as_str());
+            }
+            Merge::Unchanged => {
+                report.unchanged += 1;
+                merged.insert(doc.id.as_str());
+            }
+            Merge::Conflict => report.conflicts.push(doc.title.clone()),
+        }
+    }
+
+    let saved_progress: HashMap<String, ReadingProgress> = store
+        .get_all_progress()
+        .await?
+        .into_iter()
+        .map(|progress| (progress.document_id.clone(), progress))
+        .collect();
+    for progress in &contents.progress {
+        if merged.contains(progress.document_id.as_str())
+            && is_newer_progress(saved_progress.get(&progress.document_id), progress)
+        {
+            batch.progress.push(progress.clone());
+        }
+    }
+
+    let mut saved_annotations: HashMap<&str, HashSet<String>> = HashMap::new();
+    for annotation in &contents.annotations {
+        let document_id = annotation.document_id.as_str();
+        if !merged.contains(document_id) {
+            continue;
+        }
+        if !saved_annotations.contains_key(document_id) {
+            let ids = store
+                .get_annotations(document_id)
+                .await?
+                .into_iter()
+                .map(|saved| saved.id)
+                .collect();
+            saved_annotations.insert(document_id, ids);
+        }
+        if !saved_annotations[document_id].contains(&annotation.id) {
+            batch.annotations.push(annotation.clone());
+        }
+    }
+    if !batch.is_empty() {
+        store.save_batch(&batch).await?;
+    }
+
+    if library_was_empty {
+        report.settings = contents.settings;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::vault::tests::test_kdf;
+    use super::{
+        import_backup, is_newer_progress, merge_kind, open_backup, seal_backup, BackupContents,
+        BackupExport, ImportReport, Merge,
+    };
+    use crate::state::{Annotation, AnnotationKind, Document, ReadingProgress, Settings};
+    use crate::storage::{DocumentStore, MemoryStore, StorageError, CONTENT_STORE, METADATA_STORE};
+    use crate::tokens::Block;
+    use futures::executor::block_on;
+
+    fn contents() -> BackupContents {
+        let doc = Document::new(
+            "Moby Dick".into(),
+            vec![Block::paragraph("Call me Ishmael.")],
+            "TXT".into(),
+        );
+        BackupContents {
+            progress: vec![ReadingProgress::new(doc.id.clone(), 1, 3)],
+            documents: vec![doc],
+            annotations: Vec::new(),
+            settings: Some(Settings::default()),
+        }
+    }
+
+    #[test]
+    fn backups_round_trip_with_the_passphrase() {
+        let contents = contents();
+        let text = seal_backup(&contents, "backup passphrase", test_kdf()).unwrap();
+
+        assert!(!text.contains("Ishmael"));
+        assert_eq!(open_backup(&text, "backup passphrase").unwrap(), contents);
+        assert!(matches!(
+            open_backup(&text, "wrong passphrase"),
+            Err(StorageError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn other_files_and_newer_formats_are_rejected() {
+        let text = seal_backup(&contents(), "backup passphrase", test_kdf()).unwrap();
+        let newer = text.replace("\"version\": 1", "\"version\": 99");
+
+        assert!(matches!(
+            open_backup("{\"pages\": []}", "backup passphrase"),
+            Err(StorageError::Backup(_))
+        ));
+        assert!(matches!(
+            open_backup(&newer, "backup passphrase"),
+            Err(StorageError::Backup(message)) if message.contains("newer")
+        ));
+    }
+
+    #[test]
+    fn inflated_key_settings_are_rejected_before_deriving() {
+        let text = seal_backup(&contents(), "backup passphrase", test_kdf()).unwrap();
+
+        for (setting, inflated) in [
+            ("\"memory_kib\": 64", "\"memory_kib\": 4194304"),
+            ("\"iterations\": 1", "\"iterations\": 4000000000"),
+            ("\"parallelism\": 1", "\"parallelism\": 16"),
+        ] {
+            assert!(text.contains(setting));
+            let inflated = text.replace(setting, inflated);
+            assert!(matches!(
+                open_backup(&inflated, "backup passphrase"),
+                Err(StorageError::Backup(message)) if message.contains("damaged")
+            ));
+        }
+    }
+
+    #[test]
+    fn documents_merge_by_id() {
+        let doc = contents().documents.remove(0);
+        let mut edited = doc.clone();
+        edited.title = "Moby-Dick; or, The Whale".into();
+
+        assert_eq!(merge_kind(None, &doc), Merge::Add);
+        assert_eq!(merge_kind(Some(&doc), &doc), Merge::Unchanged);
+        assert_eq!(merge_kind(Some(&edited), &doc), Merge::Conflict);
+    }
+
+    #[test]
+    fn only_newer_positions_replace_saved_ones() {
+        let mut older = ReadingProgress::new("doc".into(), 5, 10);
+        older.updated_at = "2024-01-01T00:00:00+00:00".into();
+        let mut newer = older.clone();
+        newer.updated_at = "2024-06-01T00:00:00+00:00".into();
+
+        assert!(is_newer_progress(None, &older));
+        assert!(is_newer_progress(Some(&older), &newer));
+        assert!(!is_newer_progress(Some(&newer), &older));
+    }
+
+    #[test]
+    fn importing_restores_positions_and_annotations_once() {
+        let mut contents = contents();
+        let doc = contents.documents[0].clone();
+        let note = Annotation::new(doc.id.clone(), AnnotationKind::Bookmark, 1, 2, "".into());
+        contents.annotations.push(note.clone());
+        let text = seal_backup(&contents, "backup passphrase", test_kdf()).unwrap();
+        let store = MemoryStore::new();
+
+        let report = block_on(import_backup(&store, &text, "backup passphrase")).unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.settings, Some(Settings::default()));
+        assert_eq!(
+            block_on(store.get_document(&doc.id)).unwrap(),
+            Some(doc.clone())
+        );
+        assert_eq!(
+            block_on(store.get_all_progress()).unwrap(),
+            contents.progress
+        );
+        assert_eq!(
+            block_on(store.get_annotations(&doc.id)).unwrap(),
+            vec![note]
+        );
+
+        let again = block_on(import_backup(&store, &text, "backup passphrase")).unwrap();
+        assert_eq!((again.added, again.unchanged), (0, 1));
+        assert_eq!(again.settings, None);
+        assert_eq!(block_on(store.get_all_annotations()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn export_summary_names_unreadable_documents() {
+        let export = BackupExport {
+            text: String::new(),
+            unreadable: vec!["Notes".into(), "Draft".into()],
+        };
+
+        assert_eq!(
+            export.summary(),
+            "Backup saved without 2 documents that could not be read: Notes, Draft. \
+             Keep the passphrase; it cannot be recovered."
+        );
+    }
+
+    #[test]
+    fn unreadable_library_copies_are_replaced_from_the_backup() {
+        let contents = contents();
+        let doc = contents.documents[0].clone();
+        let text = seal_backup(&contents, "backup passphrase", test_kdf()).unwrap();
+        let lost_key = MemoryStore::new();
+        block_on(lost_key.save_document(&doc)).unwrap();
+        let store = MemoryStore::new();
+        for record_store in [METADATA_STORE, CONTENT_STORE] {
+            let sealed = lost_key.record(record_store, &doc.id).unwrap();
+            store.set_record(record_store, &doc.id, sealed);
+        }
+        assert!(block_on(store.get_document(&doc.id)).is_err());
+
+        let report = block_on(import_backup(&store, &text, "backup passphrase")).unwrap();
+
+        assert_eq!((report.added, report.repaired), (0, 1));
+        assert!(report.conflicts.is_empty());
+        assert_eq!(block_on(store.get_document(&doc.id)).unwrap(), Some(doc));
+        assert_eq!(
+            block_on(store.get_all_progress()).unwrap(),
+            contents.progress
+        );
+    }
+
+    #[test]
+    fn report_lists_conflicts() {
+        let report = ImportReport {
+            added: 1,
+            unchanged: 2,
+            repaired: 0,
+            conflicts: vec!["Notes".into()],
+            settings: None,
+        };
+
+        assert_eq!(
+            report.summary(),
+            "Added 1 document; 2 already in the library; \
+             kept the library copy of 1 that differ: Notes."
+        );
+    }
+}
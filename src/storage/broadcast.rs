@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+use web_sys::{BroadcastChannel, MessageEvent};
+
+const CHANNEL_NAME: &str = "nabu_library";
+
+/// A write other tabs should pick up. Only ids cross the channel; each tab
+/// reads the records itself, so nothing decrypted leaves a tab.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum LibraryChange {
+    /// Documents were added or recovered, possibly with reading positions.
+    DocumentsSaved,
+    DocumentDeleted {
+        id: String,
+    },
+    ProgressSaved {
+        document_id: String,
+    },
+}
+
+thread_local! {
+    static CHANNEL: RefCell<Option<BroadcastChannel>> = const { RefCell::new(None) };
+}
+
+/// The page's channel, or `None` where `BroadcastChannel` is unsupported.
+/// A channel does not receive its own messages, so one serves both ways.
+fn channel() -> Option<BroadcastChannel> {
+    CHANNEL.with(|channel| {
+        let mut channel = channel.borrow_mut();
+        if channel.is_none() {
+            *channel = BroadcastChannel::new(CHANNEL_NAME).ok();
+        }
+        channel.clone()
+    })
+}
+
+/// Tells other tabs about a committed write.
+pub(super) fn publish(change: &LibraryChange) {
+    let (Some(channel), Ok(message)) = (channel(), serde_json::to_string(change)) else {
+        return;
+    };
+    if let Err(e) = channel.post_message(&JsValue::from_str(&message)) {
+        web_sys::console::warn_1(&format!("Failed to notify other tabs: {:?}", e).into());
+    }
+}
+
+/// Calls `on_change` for every change another tab publishes.
+pub fn subscribe(on_change: impl Fn(LibraryChange) + 'static) {
+    let Some(channel) = channel() else {
+        return;
+    };
+    let listener = Closure::<dyn Fn(MessageEvent)>::new(move |event: MessageEvent| {
+        let change = event
+            .data()
+            .as_string()
+            .and_then(|message| serde_json::from_str(&message).ok());
+        if let Some(change) = change {
+            on_change(change);
+        }
+    });
+    channel.set_onmessage(Some(listener.as_ref().unchecked_ref()));
+    // The listener lives as long as the page.
+    listener.forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LibraryChange;
+
+    #[test]
+    fn changes_carry_only_ids() {
+        let change = LibraryChange::ProgressSaved {
+            document_id: "doc".into(),
+        };
+        let message = serde_json::to_string(&change).unwrap();
+
+        assert_eq!(
+            message,
+            r#"{"change":"progress_saved","document_id":"doc"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<LibraryChange>(&message).unwrap(),
+            change
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::Serialize;
+
+use super::{decrypt_with, envelope, StorageError, CONTENT_STORE, METADATA_STORE};
+use crate::state::DocumentMeta;
+
+/// A stored record that could not be read.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct DamagedRecord {
+    pub store: String,
+    pub key: String,
+    /// Why decoding failed: bad base64, a key that does not decrypt it, or
+    /// JSON that does not match any known record version.
+    pub reason: String,
+    /// The stored bytes as is, base64 encoded.
+    pub raw: String,
+}
+
+impl DamagedRecord {
+    /// Whether deleting the record deletes its whole document, as for a
+    /// document's listing or text; other records are deleted alone.
+    pub fn is_document(&self) -> bool {
+        self.store == METADATA_STORE || self.store == CONTENT_STORE
+    }
+
+    /// The record as a JSON file that can be inspected or decrypted elsewhere.
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// The library listing and every record that could not be read: listings,
+/// text, reading positions, and annotations.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LibraryScan {
+    pub documents: Vec<DocumentMeta>,
+    pub damaged: Vec<DamagedRecord>,
+}
+
+/// Parses a data key as stored in `nabu_key`: 32 bytes, base64 encoded.
+pub fn parse_key(text: &str) -> Result<[u8; 32], StorageError> {
+    BASE64
+        .decode(text.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| StorageError::Encryption("A key is 32 bytes in base64".into()))
+}
+
+/// `data` sealed with `key`, if only `old_key` decrypts it. Records from
+/// before envelopes existed are accepted too, since a lost key may be older
+/// than the envelope migration.
+pub(super) fn reencrypt(
+    key: &[u8; 32],
+    old_key: &[u8; 32],
+    store: &str,
+    id: &str,
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    if envelope::open(key, store, id, data).is_ok() {
+        return None;
+    }
+    let plaintext = envelope::open(old_key, store, id, data)
+        .or_else(|_| decrypt_with(old_key, data))
+        .ok()?;
+    envelope::seal(key, store, id, &plaintext).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_key, reencrypt, DamagedRecord};
+    use crate::storage::{encrypt_with, envelope};
+
+    #[test]
+    fn keys_must_be_32_bytes_of_base64() {
+        let key = [9u8; 32];
+        let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, key);
+
+        assert_eq!(parse_key(&format!(" {}\n", encoded)).unwrap(), key);
+        assert!(parse_key("c2hvcnQ=").is_err());
+        assert!(parse_key("not base64!").is_err());
+    }
+
+    #[test]
+    fn only_records_the_old_key_opens_are_reencrypted() {
+        let (key, old_key) = ([1u8; 32], [2u8; 32]);
+        let current = envelope::seal(&key, "progress", "a", b"{}").unwrap();
+        let old = envelope::seal(&old_key, "progress", "a", b"{}").unwrap();
+        let unsealed = encrypt_with(&old_key, b"{}").unwrap();
+
+        assert_eq!(reencrypt(&key, &old_key, "progress", "a", &current), None);
+        assert_eq!(reencrypt(&key, &[3u8; 32], "progress", "a", &old), None);
+        for data in [old, unsealed] {
+            let sealed = reencrypt(&key, &old_key, "progress", "a", &data).unwrap();
+            assert_eq!(
+                envelope::open(&key, "progress", "a", &sealed).unwrap(),
+                b"{}"
+            );
+        }
+    }
+
+    #[test]
+    fn exports_keep_the_raw_value_and_reason() {
+        let record = DamagedRecord {
+            store: "metadata".into(),
+            key: "doc".into(),
+            reason: "Encryption error: the key does not match or the data was changed".into(),
+            raw: "AAEC".into(),
+        };
+        let json: serde_json::Value = serde_json::from_str(&record.export_json()).unwrap();
+
+        assert_eq!(json["raw"], "AAEC");
+        assert_eq!(
+            json["reason"],
+            "Encryption error: the key does not match or the data was changed"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use js_sys::Uint8Array;
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+use wasm_bindgen::{JsCast, JsValue};
+
+use super::{
+    decrypt_with_aad, encrypt_with_aad, StorageError, CONTENT_STORE, LEGACY_DOCUMENTS_STORE,
+};
+
+/// Starts every encrypted record, followed by the envelope version.
+const MAGIC: &[u8; 4] = b"NABU";
+/// Layout of the bytes after the magic. Version 1 is the version byte, the
+/// nonce, and AES-GCM output, stored as base64. Version 2 adds a codec byte
+/// after the version and is stored as raw bytes.
+pub(super) const ENVELOPE_VERSION: u8 = 2;
+
+/// How the plaintext was packed before encryption.
+const CODEC_NONE: u8 = 0;
+const CODEC_DEFLATE: u8 = 1;
+/// Fast enough for a whole book on a phone while still shrinking text well.
+const DEFLATE_LEVEL: u8 = 6;
+
+/// What a record's ciphertext is bound to: the envelope header, the object
+/// store, and the record's key. Moving a record to another key or store, or
+/// changing its header, makes it fail to decrypt.
+fn associated_data(header: &[u8], store: &str, id: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(store.as_bytes());
+    aad.push(0);
+    aad.extend_from_slice(id.as_bytes());
+    aad
+}
+
+/// Document text is compressed; the other records are too small to gain.
+fn codec_for(store: &str) -> u8 {
+    if store == CONTENT_STORE || store == LEGACY_DOCUMENTS_STORE {
+        CODEC_DEFLATE
+    } else {
+        CODEC_NONE
+    }
+}
+
+/// Encrypts the record stored under `id` in `store`.
+pub(super) fn seal(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, StorageError> {
+    let codec = codec_for(store);
+    let packed = match codec {
+        CODEC_DEFLATE => compress_to_vec(plaintext, DEFLATE_LEVEL),
+        _ => plaintext.to_vec(),
+    };
+
+    let mut sealed = MAGIC.to_vec();
+    sealed.extend([ENVELOPE_VERSION, codec]);
+    let aad = associated_data(&sealed, store, id);
+    sealed.extend(encrypt_with_aad(key, &packed, &aad)?);
+    Ok(sealed)
+}
+
+/// Reverses [`seal`] for any envelope version; fails if the record was written
+/// for another key or store, or was changed.
+pub(super) fn open(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, StorageError> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| StorageError::Encryption("the record has no envelope header".into()))?;
+    let (header_len, codec) = match rest {
+        [1, ..] => (MAGIC.len() + 1, CODEC_NONE),
+        [2, codec, ..] => (MAGIC.len() + 2, *codec),
+        [version, ..] if *version > ENVELOPE_VERSION => {
+            return Err(StorageError::Encryption(format!(
+                "record envelope {} is newer than this app supports",
+                version
+            )))
+        }
+        _ => return Err(StorageError::Encryption("Data too short".into())),
+    };
+
+    let (header, encrypted) = data.split_at(header_len);
+    let packed = decrypt_with_aad(key, encrypted, &associated_data(header, store, id))?;
+    match codec {
+        CODEC_NONE => Ok(packed),
+        CODEC_DEFLATE => decompress_to_vec(&packed)
+            .map_err(|e| StorageError::Encryption(format!("could not decompress: {:?}", e))),
+        codec => Err(StorageError::Encryption(format!(
+            "unknown record codec {}",
+            codec
+        ))),
+    }
+}
+
+pub(super) fn envelope_version(data: &[u8]) -> Option<u8> {
+    data.strip_prefix(MAGIC)?.first().copied()
+}
+
+/// The bytes of a stored record: a `Uint8Array`, or base64 text for records
+/// written before envelope version 2.
+pub(super) fn record_bytes(value: &JsValue) -> Result<Vec<u8>, StorageError> {
+    if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+        return Ok(bytes.to_vec());
+    }
+    let b64 = value
+        .as_string()
+        .ok_or_else(|| StorageError::Serialization("Record is not binary or text".into()))?;
+    BASE64
+        .decode(b64)
+        .map_err(|e| StorageError::Serialization(format!("Record is not base64: {}", e)))
+}
+
+pub(super) fn record_value(bytes: &[u8]) -> JsValue {
+    Uint8Array::from(bytes).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{associated_data, open, seal, CODEC_DEFLATE, CODEC_NONE, ENVELOPE_VERSION};
+    use crate::storage::{encrypt_with, encrypt_with_aad};
+
+    const KEY: [u8; 32] = [3u8; 32];
+
+    #[test]
+    fn records_open_only_under_their_own_store_and_id() {
+        let sealed = seal(&KEY, "metadata", "a", b"{}").unwrap();
+
+        assert_eq!(open(&KEY, "metadata", "a", &sealed).unwrap(), b"{}");
+        assert!(open(&KEY, "metadata", "b", &sealed).is_err());
+        assert!(open(&KEY, "progress", "a", &sealed).is_err());
+        assert!(open(&[4u8; 32], "metadata", "a", &sealed).is_err());
+    }
+
+    #[test]
+    fn changed_headers_and_ciphertext_are_rejected() {
+        let sealed = seal(&KEY, "progress", "a", b"{}").unwrap();
+        assert_eq!(sealed[4..6], [ENVELOPE_VERSION, CODEC_NONE]);
+
+        let mut newer = sealed.clone();
+        newer[4] = ENVELOPE_VERSION + 1;
+        assert!(open(&KEY, "progress", "a", &newer)
+            .unwrap_err()
+            .to_string()
+            .contains("newer"));
+
+        let mut recoded = sealed.clone();
+        recoded[5] = CODEC_DEFLATE;
+        assert!(open(&KEY, "progress", "a", &recoded).is_err());
+
+        let mut tampered = sealed;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&KEY, "progress", "a", &tampered).is_err());
+    }
+
+    #[test]
+    fn document_text_is_compressed() {
+        let text = "Call me Ishmael. ".repeat(500);
+        let sealed = seal(&KEY, "content", "a", text.as_bytes()).unwrap();
+
+        assert_eq!(sealed[5], CODEC_DEFLATE);
+        assert!(sealed.len() < text.len() / 10);
+        assert_eq!(
+            open(&KEY, "content", "a", &sealed).unwrap(),
+            text.as_bytes()
+        );
+    }
+
+    #[test]
+    fn version_1_envelopes_still_open() {
+        let header = b"NABU\x01";
+        let mut sealed = header.to_vec();
+        sealed.extend(
+            encrypt_with_aad(&KEY, b"{}", &associated_data(header, "metadata", "a")).unwrap(),
+        );
+
+        assert_eq!(open(&KEY, "metadata", "a", &sealed).unwrap(), b"{}");
+        assert!(open(&KEY, "metadata", "b", &sealed).is_err());
+    }
+
+    #[test]
+    fn records_without_an_envelope_are_rejected() {
+        let unsealed = encrypt_with(&KEY, b"{}").unwrap();
+
+        assert!(open(&KEY, "progress", "a", &unsealed).is_err());
+    }
+}
@@ -0,0 +1,784 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use indexed_db_futures::prelude::*;
+use indexed_db_futures::web_sys::IdbKeyRange;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use wasm_bindgen::{JsCast, JsValue};
+
+use super::broadcast::{publish, LibraryChange};
+use super::damaged::{self, DamagedRecord, LibraryScan};
+use super::usage::{StorageUsage, StoreUsage};
+use super::{
+    annotation_key, current_key, decode_record, decrypt_with, encode_record, envelope, load_local,
+    records, save_local, DocumentStore, ReadDocuments, Record, RecordBatch, StorageError,
+    ANNOTATIONS_STORE, CONTENT_STORE, LEGACY_DOCUMENTS_STORE, LIBRARY_VIEW_KEY, METADATA_STORE,
+    PROGRESS_STORE, RECORD_FORMAT_KEY, SETTINGS_KEY,
+};
+use crate::state::{
+    Annotation, Document, DocumentBody, DocumentMeta, LibraryView, ReadingProgress, Settings,
+};
+
+const DB_NAME: &str = "nabu_db";
+const DB_VERSION: u32 = 4;
+
+fn annotation_range(document_id: &str) -> Result<IdbKeyRange, StorageError> {
+    // '0' is the character after '/', so the upper bound excludes other documents.
+    Ok(IdbKeyRange::bound_with_lower_open_and_upper_open(
+        &JsValue::from_str(&format!("{}/", document_id)),
+        &JsValue::from_str(&format!("{}0", document_id)),
+        false,
+        true,
+    )?)
+}
+
+/// Every object store that holds encrypted records and is present in `db`.
+fn existing_stores(db: &IdbDatabase) -> Vec<&'static str> {
+    [
+        LEGACY_DOCUMENTS_STORE,
+        METADATA_STORE,
+        CONTENT_STORE,
+        PROGRESS_STORE,
+        ANNOTATIONS_STORE,
+    ]
+    .into_iter()
+    .filter(|store| db.object_store_names().any(|name| name == *store))
+    .collect()
+}
+
+fn to_value<T: Record>(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    value: &T,
+) -> Result<JsValue, StorageError> {
+    Ok(envelope::record_value(&encode_record(
+        key, store, id, value,
+    )?))
+}
+
+fn from_value<T: Record>(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    value: &JsValue,
+) -> Result<T, StorageError> {
+    decode_record(key, store, id, &envelope::record_bytes(value)?)
+}
+
+fn raw_text(value: &JsValue) -> String {
+    value
+        .as_string()
+        .or_else(|| js_sys::JSON::stringify(value).ok().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Stored size of a record: binary values by length, older base64 values by
+/// their text length.
+fn stored_len(value: &JsValue) -> u64 {
+    match value.dyn_ref::<js_sys::Uint8Array>() {
+        Some(bytes) => bytes.length().into(),
+        None => value.as_string().map_or(0, |text| text.len() as u64),
+    }
+}
+
+/// Opens the database, upgrading its schema if needed. The connection closes
+/// itself when another tab asks to upgrade the database, so that tab is not
+/// blocked.
+async fn connect() -> Result<Rc<IdbDatabase>, StorageError> {
+    let mut db_req = IdbDatabase::open_u32(DB_NAME, DB_VERSION)?;
+
+    db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+        records::upgrade_schema(evt.db(), evt.old_version() as u32)
+    }));
+
+    let db = db_req
+        .await
+        .map_err(|e| StorageError::IndexedDb(format!("{:?}", e)))?;
+
+    Ok(Rc::new_cyclic(|this: &Weak<IdbDatabase>| {
+        let mut db = db;
+        let this = this.clone();
+        db.set_on_version_change(Some(move |_: &IdbVersionChangeEvent| {
+            if let Some(db) = this.upgrade() {
+                db.close();
+            }
+            Ok(())
+        }));
+        db
+    }))
+}
+
+/// Documents, progress, and annotations in IndexedDB; settings in
+/// `localStorage`. One instance is shared through context and keeps one
+/// connection open. The data key is looked up for each call, so the instance
+/// keeps working across vault locks and unlocks.
+#[derive(Default)]
+pub struct Storage {
+    db: RefCell<Option<Rc<IdbDatabase>>>,
+    /// Whether old records were upgraded since the app started or records
+    /// were last recovered.
+    records_upgraded: Cell<bool>,
+}
+
+impl Storage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared connection, opened on first use and reopened after it
+    /// closes.
+    async fn open_db(&self) -> Result<Rc<IdbDatabase>, StorageError> {
+        let db = match self.open_connection() {
+            Some(db) => db,
+            None => {
+                let db = connect().await?;
+                // Another call may have connected while this one waited.
+                match self.open_connection() {
+                    Some(open) => {
+                        db.close();
+                        open
+                    }
+                    None => {
+                        *self.db.borrow_mut() = Some(db.clone());
+                        db
+                    }
+                }
+            }
+        };
+
+        if !self.records_upgraded.get() {
+            // A locked vault leaves the upgrade for a call with the key.
+            if let Ok(key) = current_key() {
+                self.upgrade_envelopes(&db, &key).await?;
+                self.migrate_legacy_documents(&db, &key).await?;
+                self.records_upgraded.set(true);
+            }
+        }
+
+        Ok(db)
+    }
+
+    /// The cached connection if it still accepts transactions. The browser
+    /// closes connections on a version change or when site data is cleared;
+    /// `IdbDatabase` does not expose its `close` event, so a closed connection
+    /// is recognized by refusing a transaction.
+    fn open_connection(&self) -> Option<Rc<IdbDatabase>> {
+        let db = self.db.borrow().clone()?;
+        if db.transaction_on_one(METADATA_STORE).is_ok() {
+            return Some(db);
+        }
+        self.db.borrow_mut().take();
+        None
+    }
+
+    /// Rewrites every record in an older envelope, once per browser profile.
+    /// Runs before anything is decoded, since decoding does not accept records
+    /// without an envelope. Records `key` cannot decrypt are left as they are
+    /// and show up as damaged.
+    async fn upgrade_envelopes(
+        &self,
+        db: &IdbDatabase,
+        key: &[u8; 32],
+    ) -> Result<(), StorageError> {
+        if load_local::<u8>(RECORD_FORMAT_KEY) >= envelope::ENVELOPE_VERSION {
+            return Ok(());
+        }
+
+        let stores = existing_stores(db);
+        let tx = db.transaction_on_multi_with_mode(&stores, IdbTransactionMode::Readwrite)?;
+
+        for store in stores {
+            let object_store = tx.object_store(store)?;
+            let Some(cursor) = object_store.open_cursor()?.await? else {
+                continue;
+            };
+            loop {
+                let id = cursor.key().and_then(|key| key.as_string());
+                let data = envelope::record_bytes(&cursor.value()).ok();
+                if let (Some(id), Some(data)) = (id, data) {
+                    let plaintext = match envelope::envelope_version(&data) {
+                        Some(envelope::ENVELOPE_VERSION) => None,
+                        Some(_) => envelope::open(key, store, &id, &data).ok(),
+                        None => decrypt_with(key, &data).ok(),
+                    };
+                    if let Some(plaintext) = plaintext {
+                        let sealed = envelope::seal(key, store, &id, &plaintext)?;
+                        cursor.update(&envelope::record_value(&sealed))?.await?;
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        tx.await.into_result()?;
+
+        save_local(RECORD_FORMAT_KEY, &envelope::ENVELOPE_VERSION)
+    }
+
+    /// Splits each pre-version-4 document record into a metadata and a content
+    /// record. Records that cannot be decrypted are left where they are.
+    async fn migrate_legacy_documents(
+        &self,
+        db: &IdbDatabase,
+        key: &[u8; 32],
+    ) -> Result<(), StorageError> {
+        if !db.object_store_names().any(|n| n == LEGACY_DOCUMENTS_STORE) {
+            return Ok(());
+        }
+
+        let tx = db.transaction_on_multi_with_mode(
+            &[LEGACY_DOCUMENTS_STORE, METADATA_STORE, CONTENT_STORE],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let metadata = tx.object_store(METADATA_STORE)?;
+        let content = tx.object_store(CONTENT_STORE)?;
+
+        if let Some(cursor) = tx
+            .object_store(LEGACY_DOCUMENTS_STORE)?
+            .open_cursor()?
+            .await?
+        {
+            loop {
+                let id = cursor.key().and_then(|key| key.as_string());
+                if let Some(Ok(doc)) = id.map(|id| {
+                    from_value::<Document>(key, LEGACY_DOCUMENTS_STORE, &id, &cursor.value())
+                }) {
+                    let id = JsValue::from_str(&doc.id);
+                    let meta = to_value(key, METADATA_STORE, &doc.id, &doc.meta())?;
+                    let body = to_value(key, CONTENT_STORE, &doc.id, &doc.body())?;
+                    metadata.put_key_val(&id, &meta)?;
+                    content.put_key_val(&id, &body)?;
+                    cursor.delete()?;
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    /// Annotations for one document, or all of them, skipping unreadable ones.
+    async fn read_annotations(
+        &self,
+        document_id: Option<&str>,
+    ) -> Result<Vec<Annotation>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one(ANNOTATIONS_STORE)?;
+        let store = tx.object_store(ANNOTATIONS_STORE)?;
+
+        let cursor = match document_id {
+            Some(id) => {
+                store
+                    .open_cursor_with_range(&annotation_range(id)?)?
+                    .await?
+            }
+            None => store.open_cursor()?.await?,
+        };
+        let mut annotations = Vec::new();
+
+        if let Some(cursor) = cursor {
+            loop {
+                let id = cursor.key().and_then(|key| key.as_string());
+                if let Some(Ok(annotation)) = id.map(|id| {
+                    from_value::<Annotation>(&key, ANNOTATIONS_STORE, &id, &cursor.value())
+                }) {
+                    annotations.push(annotation);
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(annotations)
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentStore for Storage {
+    async fn save_document(&self, doc: &Document) -> Result<(), StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_multi_with_mode(
+            &[METADATA_STORE, CONTENT_STORE],
+            IdbTransactionMode::Readwrite,
+        )?;
+
+        let id = JsValue::from_str(&doc.id);
+        let meta = to_value(&key, METADATA_STORE, &doc.id, &doc.meta())?;
+        let body = to_value(&key, CONTENT_STORE, &doc.id, &doc.body())?;
+
+        tx.object_store(METADATA_STORE)?.put_key_val(&id, &meta)?;
+        tx.object_store(CONTENT_STORE)?.put_key_val(&id, &body)?;
+        tx.await.into_result()?;
+
+        publish(&LibraryChange::DocumentsSaved);
+        Ok(())
+    }
+
+    async fn get_document(&self, id: &str) -> Result<Option<Document>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_multi(&[METADATA_STORE, CONTENT_STORE])?;
+
+        let js_id = JsValue::from_str(id);
+        let meta = tx.object_store(METADATA_STORE)?.get(&js_id)?.await?;
+        let body = tx.object_store(CONTENT_STORE)?.get(&js_id)?.await?;
+
+        match (meta, body) {
+            (Some(meta), Some(body)) => Ok(Some(Document::from_parts(
+                from_value(&key, METADATA_STORE, id, &meta)?,
+                from_value(&key, CONTENT_STORE, id, &body)?,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads both stores in one transaction.
+    async fn get_all_documents(&self) -> Result<ReadDocuments, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_multi(&[METADATA_STORE, CONTENT_STORE])?;
+        let metadata = tx.object_store(METADATA_STORE)?;
+        let content = tx.object_store(CONTENT_STORE)?;
+
+        let mut read = ReadDocuments::default();
+        let mut listed = Vec::new();
+        if let Some(cursor) = metadata.open_cursor()?.await? {
+            loop {
+                let id = cursor.key().map(|id| raw_text(&id)).unwrap_or_default();
+                match from_value::<DocumentMeta>(&key, METADATA_STORE, &id, &cursor.value()) {
+                    Ok(meta) => listed.push(meta),
+                    Err(_) => read.unreadable.push(id),
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        for meta in listed {
+            let body = content
+                .get(&JsValue::from_str(&meta.id))?
+                .await?
+                .and_then(|body| from_value(&key, CONTENT_STORE, &meta.id, &body).ok());
+            match body {
+                Some(body) => read.documents.push(Document::from_parts(meta, body)),
+                None => read.unreadable.push(meta.title),
+            }
+        }
+
+        Ok(read)
+    }
+
+    async fn save_batch(&self, batch: &RecordBatch) -> Result<(), StorageError> {
+        let key = current_key()?;
+        let mut writes = Vec::new();
+        for doc in &batch.documents {
+            let meta = to_value(&key, METADATA_STORE, &doc.id, &doc.meta())?;
+            let body = to_value(&key, CONTENT_STORE, &doc.id, &doc.body())?;
+            writes.push((METADATA_STORE, doc.id.clone(), meta));
+            writes.push((CONTENT_STORE, doc.id.clone(), body));
+        }
+        for progress in &batch.progress {
+            let id = &progress.document_id;
+            let record = to_value(&key, PROGRESS_STORE, id, progress)?;
+            writes.push((PROGRESS_STORE, id.clone(), record));
+        }
+        for annotation in &batch.annotations {
+            let id = annotation_key(annotation);
+            let record = to_value(&key, ANNOTATIONS_STORE, &id, annotation)?;
+            writes.push((ANNOTATIONS_STORE, id, record));
+        }
+
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_multi_with_mode(
+            &[
+                METADATA_STORE,
+                CONTENT_STORE,
+                PROGRESS_STORE,
+                ANNOTATIONS_STORE,
+            ],
+            IdbTransactionMode::Readwrite,
+        )?;
+        for (store, id, record) in writes {
+            tx.object_store(store)?
+                .put_key_val(&JsValue::from_str(&id), &record)?;
+        }
+        for annotation in &batch.deleted_annotations {
+            tx.object_store(ANNOTATIONS_STORE)?
+                .delete(&JsValue::from_str(&annotation_key(annotation)))?;
+        }
+        tx.await.into_result()?;
+
+        publish(&LibraryChange::DocumentsSaved);
+        Ok(())
+    }
+
+    /// Old-format records left behind by the version 4 migration are always
+    /// damaged, since readable ones were moved. Document text is decrypted one
+    /// record at a time and dropped.
+    async fn scan_library(&self) -> Result<LibraryScan, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let mut scan = LibraryScan::default();
+
+        let stores = existing_stores(&db);
+        let tx = db.transaction_on_multi(&stores)?;
+        for store in stores {
+            let object_store = tx.object_store(store)?;
+            let Some(cursor) = object_store.open_cursor()?.await? else {
+                continue;
+            };
+
+            loop {
+                let id = cursor.key().map(|id| raw_text(&id)).unwrap_or_default();
+                let value = cursor.value();
+                let decoded = match store {
+                    METADATA_STORE => from_value::<DocumentMeta>(&key, store, &id, &value)
+                        .map(|doc| scan.documents.push(doc)),
+                    CONTENT_STORE => {
+                        from_value::<DocumentBody>(&key, store, &id, &value).map(|_| ())
+                    }
+                    PROGRESS_STORE => {
+                        from_value::<ReadingProgress>(&key, store, &id, &value).map(|_| ())
+                    }
+                    ANNOTATIONS_STORE => {
+                        from_value::<Annotation>(&key, store, &id, &value).map(|_| ())
+                    }
+                    _ => from_value::<Document>(&key, store, &id, &value).map(|_| ()),
+                };
+                if let Err(e) = decoded {
+                    scan.damaged.push(DamagedRecord {
+                        store: store.to_string(),
+                        key: id,
+                        reason: e.to_string(),
+                        raw: envelope::record_bytes(&value)
+                            .map(|bytes| BASE64.encode(bytes))
+                            .unwrap_or_else(|_| raw_text(&value)),
+                    });
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Reads the metadata store alone; damaged records are skipped here and
+    /// reported by `scan_library`.
+    async fn get_all_metadata(&self) -> Result<Vec<DocumentMeta>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one(METADATA_STORE)?;
+        let store = tx.object_store(METADATA_STORE)?;
+
+        let mut documents = Vec::new();
+        if let Some(cursor) = store.open_cursor()?.await? {
+            loop {
+                let id = cursor.key().map(|id| raw_text(&id)).unwrap_or_default();
+                if let Ok(meta) = from_value(&key, METADATA_STORE, &id, &cursor.value()) {
+                    documents.push(meta);
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Bodies are decrypted one at a time and dropped, so memory does not
+    /// grow with the library.
+    async fn find_documents(
+        &self,
+        matches: &dyn for<'a> Fn(&'a str, &'a DocumentBody) -> bool,
+    ) -> Result<Vec<String>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one(CONTENT_STORE)?;
+        let store = tx.object_store(CONTENT_STORE)?;
+
+        let cursor = store.open_cursor()?.await?;
+        let mut ids = Vec::new();
+
+        if let Some(cursor) = cursor {
+            loop {
+                if let Some(id) = cursor.key().and_then(|key| key.as_string()) {
+                    if let Ok(body) =
+                        from_value::<DocumentBody>(&key, CONTENT_STORE, &id, &cursor.value())
+                    {
+                        if matches(&id, &body) {
+                            ids.push(id);
+                        }
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn delete_document(&self, id: &str) -> Result<(), StorageError> {
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_multi_with_mode(
+            &[
+                METADATA_STORE,
+                CONTENT_STORE,
+                PROGRESS_STORE,
+                ANNOTATIONS_STORE,
+            ],
+            IdbTransactionMode::Readwrite,
+        )?;
+
+        let key = JsValue::from_str(id);
+        tx.object_store(METADATA_STORE)?.delete(&key)?;
+        tx.object_store(CONTENT_STORE)?.delete(&key)?;
+        tx.object_store(PROGRESS_STORE)?.delete(&key)?;
+        tx.object_store(ANNOTATIONS_STORE)?
+            .delete(&annotation_range(id)?)?;
+        tx.await.into_result()?;
+
+        publish(&LibraryChange::DocumentDeleted { id: id.to_string() });
+        Ok(())
+    }
+
+    async fn save_progress(&self, progress: &ReadingProgress) -> Result<(), StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one_with_mode(PROGRESS_STORE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(PROGRESS_STORE)?;
+
+        let id = &progress.document_id;
+        let js_id = JsValue::from_str(id);
+        // Read and written in one transaction, so another tab cannot save in
+        // between.
+        let saved = store
+            .get(&js_id)?
+            .await?
+            .and_then(|value| from_value::<ReadingProgress>(&key, PROGRESS_STORE, id, &value).ok());
+        let is_newer = saved.is_none_or(|saved| progress.supersedes(&saved));
+        if is_newer {
+            let record = to_value(&key, PROGRESS_STORE, id, progress)?;
+            store.put_key_val(&js_id, &record)?;
+        }
+        tx.await.into_result()?;
+
+        if is_newer {
+            publish(&LibraryChange::ProgressSaved {
+                document_id: id.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn get_progress(&self, id: &str) -> Result<Option<ReadingProgress>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one(PROGRESS_STORE)?;
+        let store = tx.object_store(PROGRESS_STORE)?;
+
+        let result = store.get(&JsValue::from_str(id))?.await?;
+
+        result
+            .map(|js_val| from_value(&key, PROGRESS_STORE, id, &js_val))
+            .transpose()
+    }
+
+    async fn get_all_progress(&self) -> Result<Vec<ReadingProgress>, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one(PROGRESS_STORE)?;
+        let store = tx.object_store(PROGRESS_STORE)?;
+
+        let cursor = store.open_cursor()?.await?;
+        let mut progress = Vec::new();
+
+        if let Some(cursor) = cursor {
+            loop {
+                let id = cursor.key().and_then(|key| key.as_string());
+                if let Some(Ok(entry)) = id.map(|id| {
+                    from_value::<ReadingProgress>(&key, PROGRESS_STORE, &id, &cursor.value())
+                }) {
+                    progress.push(entry);
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(progress)
+    }
+
+    async fn save_annotation(&self, annotation: &Annotation) -> Result<(), StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let tx =
+            db.transaction_on_one_with_mode(ANNOTATIONS_STORE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(ANNOTATIONS_STORE)?;
+
+        let id = annotation_key(annotation);
+        let record = to_value(&key, ANNOTATIONS_STORE, &id, annotation)?;
+
+        store.put_key_val(&JsValue::from_str(&id), &record)?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn delete_annotation(&self, annotation: &Annotation) -> Result<(), StorageError> {
+        let db = self.open_db().await?;
+        let tx =
+            db.transaction_on_one_with_mode(ANNOTATIONS_STORE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(ANNOTATIONS_STORE)?;
+
+        store.delete(&JsValue::from_str(&annotation_key(annotation)))?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn get_annotations(&self, document_id: &str) -> Result<Vec<Annotation>, StorageError> {
+        let mut annotations = self.read_annotations(Some(document_id)).await?;
+        annotations.sort_by_key(|annotation| (annotation.start, annotation.end));
+
+        Ok(annotations)
+    }
+
+    async fn get_all_annotations(&self) -> Result<Vec<Annotation>, StorageError> {
+        self.read_annotations(None).await
+    }
+
+    async fn recover_with_key(&self, old_key: &[u8; 32]) -> Result<usize, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let stores = existing_stores(&db);
+        let tx = db.transaction_on_multi_with_mode(&stores, IdbTransactionMode::Readwrite)?;
+
+        let mut recovered = 0;
+        for store in stores {
+            let object_store = tx.object_store(store)?;
+            let Some(cursor) = object_store.open_cursor()?.await? else {
+                continue;
+            };
+            loop {
+                let id = cursor.key().and_then(|key| key.as_string());
+                let data = envelope::record_bytes(&cursor.value()).ok();
+                if let (Some(id), Some(data)) = (id, data) {
+                    if let Some(sealed) = damaged::reencrypt(&key, old_key, store, &id, &data) {
+                        cursor.update(&envelope::record_value(&sealed))?.await?;
+                        recovered += 1;
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        tx.await.into_result()?;
+
+        if recovered > 0 {
+            // Recovered old-format documents can now be split.
+            self.records_upgraded.set(false);
+            publish(&LibraryChange::DocumentsSaved);
+        }
+        Ok(recovered)
+    }
+
+    async fn delete_damaged(&self, record: &DamagedRecord) -> Result<(), StorageError> {
+        if record.is_document() {
+            return self.delete_document(&record.key).await;
+        }
+
+        let db = self.open_db().await?;
+        let tx = db.transaction_on_one_with_mode(&record.store, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&record.store)?
+            .delete(&JsValue::from_str(&record.key))?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn storage_usage(&self) -> Result<StorageUsage, StorageError> {
+        let key = current_key()?;
+        let db = self.open_db().await?;
+        let mut usage = StorageUsage::default();
+
+        let stores = existing_stores(&db);
+        let tx = db.transaction_on_multi(&stores)?;
+        for store in stores {
+            let object_store = tx.object_store(store)?;
+            let mut store_usage = StoreUsage {
+                store,
+                records: 0,
+                bytes: 0,
+            };
+
+            if let Some(cursor) = object_store.open_cursor()?.await? {
+                loop {
+                    let value = cursor.value();
+                    let stored = stored_len(&value);
+                    store_usage.records += 1;
+                    store_usage.bytes += stored;
+
+                    if store == CONTENT_STORE {
+                        let id = cursor.key().and_then(|key| key.as_string());
+                        let plaintext = id.and_then(|id| {
+                            envelope::record_bytes(&value)
+                                .and_then(|data| envelope::open(&key, store, &id, &data))
+                                .ok()
+                        });
+                        if let Some(plaintext) = plaintext {
+                            usage.content_bytes += plaintext.len() as u64;
+                            usage.stored_content_bytes += stored;
+                        }
+                    }
+
+                    if !cursor.continue_cursor()?.await? {
+                        break;
+                    }
+                }
+            }
+            usage.stores.push(store_usage);
+        }
+
+        Ok(usage)
+    }
+
+    fn load_settings(&self) -> Settings {
+        load_local::<Settings>(SETTINGS_KEY).normalized()
+    }
+
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError> {
+        save_local(SETTINGS_KEY, settings)
+    }
+
+    fn load_library_view(&self) -> LibraryView {
+        load_local(LIBRARY_VIEW_KEY)
+    }
+
+    fn save_library_view(&self, view: &LibraryView) -> Result<(), StorageError> {
+        save_local(LIBRARY_VIEW_KEY, view)
+    }
+}
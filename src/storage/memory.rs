@@ -0,0 +1,505 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::damaged::{self, DamagedRecord, LibraryScan};
+use super::usage::{StorageUsage, StoreUsage};
+use super::{
+    annotation_key, decode_record, encode_record, envelope, generate_key, DocumentStore, Record,
+    RecordBatch, StorageError, ANNOTATIONS_STORE, CONTENT_STORE, METADATA_STORE, PROGRESS_STORE,
+};
+use crate::state::{
+    Annotation, Document, DocumentBody, DocumentMeta, LibraryView, ReadingProgress, Settings,
+};
+
+/// Records sealed exactly as in IndexedDB, kept in memory. For tests.
+pub struct MemoryStore {
+    key: [u8; 32],
+    /// Sealed records by store and key, in key order like IndexedDB.
+    records: RefCell<BTreeMap<(String, String), Vec<u8>>>,
+    settings: RefCell<Settings>,
+    library_view: RefCell<LibraryView>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::with_key(generate_key())
+    }
+
+    pub fn with_key(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            records: RefCell::new(BTreeMap::new()),
+            settings: RefCell::new(Settings::default()),
+            library_view: RefCell::new(LibraryView::default()),
+        }
+    }
+
+    /// The stored bytes of a record.
+    pub fn record(&self, store: &str, id: &str) -> Option<Vec<u8>> {
+        self.records
+            .borrow()
+            .get(&(store.to_string(), id.to_string()))
+            .cloned()
+    }
+
+    /// Replaces the stored bytes of a record, as a damaged or moved record
+    /// would.
+    pub fn set_record(&self, store: &str, id: &str, data: Vec<u8>) {
+        self.records
+            .borrow_mut()
+            .insert((store.to_string(), id.to_string()), data);
+    }
+
+    fn seal<T: Record>(&self, store: &str, id: &str, value: &T) -> Result<Vec<u8>, StorageError> {
+        encode_record(&self.key, store, id, value)
+    }
+
+    fn put<T: Record>(&self, store: &str, id: &str, value: &T) -> Result<(), StorageError> {
+        let data = self.seal(store, id, value)?;
+        self.set_record(store, id, data);
+        Ok(())
+    }
+
+    fn get<T: Record>(&self, store: &str, id: &str) -> Result<Option<T>, StorageError> {
+        self.record(store, id)
+            .map(|data| decode_record(&self.key, store, id, &data))
+            .transpose()
+    }
+
+    fn remove(&self, store: &str, id: &str) {
+        self.records
+            .borrow_mut()
+            .remove(&(store.to_string(), id.to_string()));
+    }
+
+    /// Every record in `store` as `(key, bytes)`.
+    fn entries(&self, store: &str) -> Vec<(String, Vec<u8>)> {
+        self.records
+            .borrow()
+            .iter()
+            .filter(|((name, _), _)| name == store)
+            .map(|((_, id), data)| (id.clone(), data.clone()))
+            .collect()
+    }
+
+    /// Every readable record in `store`, listing the others in `damaged`.
+    fn scan_store<T: Record>(&self, store: &str, damaged: &mut Vec<DamagedRecord>) -> Vec<T> {
+        let mut decoded = Vec::new();
+        for (id, data) in self.entries(store) {
+            match decode_record(&self.key, store, &id, &data) {
+                Ok(value) => decoded.push(value),
+                Err(e) => damaged.push(DamagedRecord {
+                    store: store.to_string(),
+                    key: id,
+                    reason: e.to_string(),
+                    raw: BASE64.encode(data),
+                }),
+            }
+        }
+        decoded
+    }
+
+    /// Every readable record in `store`, skipping the others.
+    fn decode_all<T: Record>(&self, store: &str) -> Vec<(String, T)> {
+        self.entries(store)
+            .into_iter()
+            .filter_map(|(id, data)| {
+                let value = decode_record(&self.key, store, &id, &data).ok()?;
+                Some((id, value))
+            })
+            .collect()
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentStore for MemoryStore {
+    async fn save_document(&self, doc: &Document) -> Result<(), StorageError> {
+        self.put(METADATA_STORE, &doc.id, &doc.meta())?;
+        self.put(CONTENT_STORE, &doc.id, &doc.body())
+    }
+
+    async fn get_document(&self, id: &str) -> Result<Option<Document>, StorageError> {
+        match (self.get(METADATA_STORE, id)?, self.get(CONTENT_STORE, id)?) {
+            (Some(meta), Some(body)) => Ok(Some(Document::from_parts(meta, body))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Checks every record before saving any, so a batch saves whole or not
+    /// at all like an IndexedDB transaction.
+    async fn save_batch(&self, batch: &RecordBatch) -> Result<(), StorageError> {
+        let mut sealed = Vec::new();
+        for doc in &batch.documents {
+            sealed.push((
+                METADATA_STORE,
+                doc.id.clone(),
+                self.seal(METADATA_STORE, &doc.id, &doc.meta())?,
+            ));
+            sealed.push((
+                CONTENT_STORE,
+                doc.id.clone(),
+                self.seal(CONTENT_STORE, &doc.id, &doc.body())?,
+            ));
+        }
+        for progress in &batch.progress {
+            let id = &progress.document_id;
+            sealed.push((
+                PROGRESS_STORE,
+                id.clone(),
+                self.seal(PROGRESS_STORE, id, progress)?,
+            ));
+        }
+        for annotation in &batch.annotations {
+            let id = annotation_key(annotation);
+            let data = self.seal(ANNOTATIONS_STORE, &id, annotation)?;
+            sealed.push((ANNOTATIONS_STORE, id, data));
+        }
+        for (store, id, data) in sealed {
+            self.set_record(store, &id, data);
+        }
+        for annotation in &batch.deleted_annotations {
+            self.remove(ANNOTATIONS_STORE, &annotation_key(annotation));
+        }
+        Ok(())
+    }
+
+    async fn scan_library(&self) -> Result<LibraryScan, StorageError> {
+        let mut scan = LibraryScan::default();
+        scan.documents = self.scan_store(METADATA_STORE, &mut scan.damaged);
+        self.scan_store::<DocumentBody>(CONTENT_STORE, &mut scan.damaged);
+        self.scan_store::<ReadingProgress>(PROGRESS_STORE, &mut scan.damaged);
+        self.scan_store::<Annotation>(ANNOTATIONS_STORE, &mut scan.damaged);
+        Ok(scan)
+    }
+
+    async fn get_all_metadata(&self) -> Result<Vec<DocumentMeta>, StorageError> {
+        Ok(self
+            .decode_all(METADATA_STORE)
+            .into_iter()
+            .map(|(_, meta)| meta)
+            .collect())
+    }
+
+    async fn find_documents(
+        &self,
+        matches: &dyn for<'a> Fn(&'a str, &'a DocumentBody) -> bool,
+    ) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .decode_all::<DocumentBody>(CONTENT_STORE)
+            .into_iter()
+            .filter(|(id, body)| matches(id, body))
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    async fn delete_document(&self, id: &str) -> Result<(), StorageError> {
+        self.remove(METADATA_STORE, id);
+        self.remove(CONTENT_STORE, id);
+        self.remove(PROGRESS_STORE, id);
+        let prefix = format!("{}/", id);
+        self.records
+            .borrow_mut()
+            .retain(|(store, key), _| store != ANNOTATIONS_STORE || !key.starts_with(&prefix));
+        Ok(())
+    }
+
+    async fn save_progress(&self, progress: &ReadingProgress) -> Result<(), StorageError> {
+        let saved = self
+            .get::<ReadingProgress>(PROGRESS_STORE, &progress.document_id)
+            .unwrap_or(None);
+        if saved.is_none_or(|saved| progress.supersedes(&saved)) {
+            self.put(PROGRESS_STORE, &progress.document_id, progress)?;
+        }
+        Ok(())
+    }
+
+    async fn get_progress(&self, id: &str) -> Result<Option<ReadingProgress>, StorageError> {
+        self.get(PROGRESS_STORE, id)
+    }
+
+    async fn get_all_progress(&self) -> Result<Vec<ReadingProgress>, StorageError> {
+        Ok(self
+            .decode_all(PROGRESS_STORE)
+            .into_iter()
+            .map(|(_, progress)| progress)
+            .collect())
+    }
+
+    async fn save_annotation(&self, annotation: &Annotation) -> Result<(), StorageError> {
+        self.put(ANNOTATIONS_STORE, &annotation_key(annotation), annotation)
+    }
+
+    async fn delete_annotation(&self, annotation: &Annotation) -> Result<(), StorageError> {
+        self.remove(ANNOTATIONS_STORE, &annotation_key(annotation));
+        Ok(())
+    }
+
+    async fn get_annotations(&self, document_id: &str) -> Result<Vec<Annotation>, StorageError> {
+        let prefix = format!("{}/", document_id);
+        let mut annotations: Vec<Annotation> = self
+            .decode_all(ANNOTATIONS_STORE)
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, annotation)| annotation)
+            .collect();
+        annotations.sort_by_key(|annotation| (annotation.start, annotation.end));
+        Ok(annotations)
+    }
+
+    async fn get_all_annotations(&self) -> Result<Vec<Annotation>, StorageError> {
+        Ok(self
+            .decode_all(ANNOTATIONS_STORE)
+            .into_iter()
+            .map(|(_, annotation)| annotation)
+            .collect())
+    }
+
+    async fn recover_with_key(&self, old_key: &[u8; 32]) -> Result<usize, StorageError> {
+        let mut recovered = 0;
+        for ((store, id), data) in self.records.borrow_mut().iter_mut() {
+            if let Some(sealed) = damaged::reencrypt(&self.key, old_key, store, id, data) {
+                *data = sealed;
+                recovered += 1;
+            }
+        }
+        Ok(recovered)
+    }
+
+    async fn delete_damaged(&self, record: &DamagedRecord) -> Result<(), StorageError> {
+        if record.is_document() {
+            return self.delete_document(&record.key).await;
+        }
+        self.remove(&record.store, &record.key);
+        Ok(())
+    }
+
+    async fn storage_usage(&self) -> Result<StorageUsage, StorageError> {
+        let mut usage = StorageUsage::default();
+        for store in [
+            METADATA_STORE,
+            CONTENT_STORE,
+            PROGRESS_STORE,
+            ANNOTATIONS_STORE,
+        ] {
+            let entries = self.entries(store);
+            usage.stores.push(StoreUsage {
+                store,
+                records: entries.len(),
+                bytes: entries.iter().map(|(_, data)| data.len() as u64).sum(),
+            });
+            if store != CONTENT_STORE {
+                continue;
+            }
+            for (id, data) in entries {
+                if let Ok(plaintext) = envelope::open(&self.key, store, &id, &data) {
+                    usage.content_bytes += plaintext.len() as u64;
+                    usage.stored_content_bytes += data.len() as u64;
+                }
+            }
+        }
+        Ok(usage)
+    }
+
+    fn load_settings(&self) -> Settings {
+        self.settings.borrow().clone()
+    }
+
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError> {
+        *self.settings.borrow_mut() = settings.clone();
+        Ok(())
+    }
+
+    fn load_library_view(&self) -> LibraryView {
+        self.library_view.borrow().clone()
+    }
+
+    fn save_library_view(&self, view: &LibraryView) -> Result<(), StorageError> {
+        *self.library_view.borrow_mut() = view.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::state::{Annotation, AnnotationKind, Document, ReadingProgress};
+    use crate::storage::{
+        annotation_key, DocumentStore, StorageError, ANNOTATIONS_STORE, CONTENT_STORE,
+        METADATA_STORE, PROGRESS_STORE,
+    };
+    use crate::tokens::Block;
+    use futures::executor::block_on;
+
+    fn document(title: &str, text: &str) -> Document {
+        Document::new(title.into(), vec![Block::paragraph(text)], "TXT".into())
+    }
+
+    #[test]
+    fn unreadable_documents_are_listed_not_dropped() {
+        let store = MemoryStore::new();
+        let kept = document("Kept", "Still here.");
+        let damaged = document("Damaged", "Lost text.");
+        block_on(store.save_document(&kept)).unwrap();
+        block_on(store.save_document(&damaged)).unwrap();
+        store.set_record(CONTENT_STORE, &damaged.id, b"not a record".to_vec());
+
+        let read = block_on(store.get_all_documents()).unwrap();
+
+        assert_eq!(read.documents, vec![kept]);
+        assert_eq!(read.unreadable, vec!["Damaged".to_string()]);
+    }
+
+    #[test]
+    fn documents_are_encrypted_and_read_back() {
+        let store = MemoryStore::new();
+        let doc = document("Moby Dick", "Call me Ishmael.");
+        block_on(store.save_document(&doc)).unwrap();
+
+        let meta = store.record(METADATA_STORE, &doc.id).unwrap();
+        assert!(!String::from_utf8_lossy(&meta).contains("Moby Dick"));
+        assert_eq!(block_on(store.get_document(&doc.id)).unwrap(), Some(doc));
+    }
+
+    #[test]
+    fn records_moved_to_another_id_are_reported_as_damaged() {
+        let store = MemoryStore::new();
+        let (first, second) = (document("First", "One."), document("Second", "Two."));
+        block_on(store.save_document(&first)).unwrap();
+        block_on(store.save_document(&second)).unwrap();
+
+        let moved = store.record(CONTENT_STORE, &first.id).unwrap();
+        store.set_record(CONTENT_STORE, &second.id, moved);
+        let moved = store.record(METADATA_STORE, &first.id).unwrap();
+        store.set_record(METADATA_STORE, &second.id, moved);
+
+        let scan = block_on(store.scan_library()).unwrap();
+        assert_eq!(scan.documents, vec![first.meta()]);
+        let damaged: Vec<(&str, &str)> = scan
+            .damaged
+            .iter()
+            .map(|record| (record.store.as_str(), record.key.as_str()))
+            .collect();
+        assert_eq!(
+            damaged,
+            vec![
+                (METADATA_STORE, second.id.as_str()),
+                (CONTENT_STORE, second.id.as_str())
+            ]
+        );
+        assert!(matches!(
+            block_on(store.get_document(&second.id)),
+            Err(StorageError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn unreadable_text_positions_and_annotations_are_reported() {
+        let store = MemoryStore::new();
+        let doc = document("Moby Dick", "Call me Ishmael.");
+        block_on(store.save_document(&doc)).unwrap();
+        block_on(store.save_progress(&ReadingProgress::new(doc.id.clone(), 1, 3))).unwrap();
+        let note = Annotation::new(doc.id.clone(), AnnotationKind::Bookmark, 0, 1, "".into());
+        block_on(store.save_annotation(&note)).unwrap();
+        let note_key = annotation_key(&note);
+        for (name, id) in [
+            (CONTENT_STORE, doc.id.as_str()),
+            (PROGRESS_STORE, doc.id.as_str()),
+            (ANNOTATIONS_STORE, note_key.as_str()),
+        ] {
+            store.set_record(name, id, b"damaged".to_vec());
+        }
+
+        let scan = block_on(store.scan_library()).unwrap();
+        assert_eq!(scan.documents, vec![doc.meta()]);
+        let mut damaged: Vec<&str> = scan.damaged.iter().map(|r| r.store.as_str()).collect();
+        damaged.sort_unstable();
+        assert_eq!(
+            damaged,
+            vec![ANNOTATIONS_STORE, CONTENT_STORE, PROGRESS_STORE]
+        );
+        assert_eq!(
+            block_on(store.get_all_metadata()).unwrap(),
+            vec![doc.meta()]
+        );
+
+        let text = scan.damaged.iter().find(|r| r.store == CONTENT_STORE);
+        block_on(store.delete_damaged(text.unwrap())).unwrap();
+        let scan = block_on(store.scan_library()).unwrap();
+        assert!(scan.documents.is_empty());
+        assert!(scan.damaged.is_empty());
+    }
+
+    #[test]
+    fn older_positions_do_not_overwrite_newer_ones() {
+        let store = MemoryStore::new();
+        let mut newer = ReadingProgress::new("doc".into(), 500, 1000);
+        newer.updated_at = "2024-06-01T00:00:00+00:00".into();
+        let mut older = ReadingProgress::new("doc".into(), 100, 1000);
+        older.updated_at = "2024-01-01T00:00:00+00:00".into();
+
+        block_on(store.save_progress(&newer)).unwrap();
+        block_on(store.save_progress(&older)).unwrap();
+
+        assert_eq!(block_on(store.get_progress("doc")).unwrap(), Some(newer));
+    }
+
+    #[test]
+    fn deleting_a_document_removes_only_its_records() {
+        let store = MemoryStore::new();
+        let (kept, deleted) = (document("Kept", "One."), document("Deleted", "Two."));
+        for doc in [&kept, &deleted] {
+            block_on(store.save_document(doc)).unwrap();
+            block_on(store.save_progress(&ReadingProgress::new(doc.id.clone(), 0, 1))).unwrap();
+            let note = Annotation::new(doc.id.clone(), AnnotationKind::Bookmark, 0, 1, "".into());
+            block_on(store.save_annotation(&note)).unwrap();
+        }
+
+        block_on(store.delete_document(&deleted.id)).unwrap();
+
+        assert_eq!(
+            block_on(store.get_all_metadata()).unwrap(),
+            vec![kept.meta()]
+        );
+        assert_eq!(block_on(store.get_all_progress()).unwrap().len(), 1);
+        assert!(block_on(store.get_annotations(&deleted.id))
+            .unwrap()
+            .is_empty());
+        assert_eq!(block_on(store.get_annotations(&kept.id)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn records_from_a_lost_key_are_recovered() {
+        let old = MemoryStore::with_key([1u8; 32]);
+        let doc = document("Moby Dick", "Call me Ishmael.");
+        block_on(old.save_document(&doc)).unwrap();
+
+        let store = MemoryStore::with_key([2u8; 32]);
+        for name in [METADATA_STORE, CONTENT_STORE] {
+            store.set_record(name, &doc.id, old.record(name, &doc.id).unwrap());
+        }
+        assert_eq!(block_on(store.scan_library()).unwrap().damaged.len(), 2);
+
+        assert_eq!(block_on(store.recover_with_key(&[3u8; 32])).unwrap(), 0);
+        assert_eq!(block_on(store.recover_with_key(&[1u8; 32])).unwrap(), 2);
+        assert_eq!(block_on(store.get_document(&doc.id)).unwrap(), Some(doc));
+    }
+
+    #[test]
+    fn usage_shows_what_compression_saves() {
+        let store = MemoryStore::new();
+        let doc = document("Moby Dick", &"Call me Ishmael. ".repeat(1000));
+        block_on(store.save_document(&doc)).unwrap();
+
+        let usage = block_on(store.storage_usage()).unwrap();
+        assert!(usage.saved_percent() > 80);
+        assert_eq!(usage.stores.iter().map(|s| s.records).sum::<usize>(), 2);
+    }
+}
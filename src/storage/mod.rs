@@ -1,16 +1,56 @@
-use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use indexed_db_futures::prelude::*;
 use indexed_db_futures::web_sys::DomException;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
 use thiserror::Error;
 use wasm_bindgen::JsValue;
 
-use crate::state::Document;
-
-const DB_NAME: &str = "nabu_db";
-const DB_VERSION: u32 = 1;
-const DOCUMENTS_STORE: &str = "documents";
+use crate::state::{
+    Annotation, Document, DocumentBody, DocumentMeta, LibraryView, ReadingProgress, Settings,
+};
+
+mod backup;
+mod broadcast;
+mod damaged;
+mod envelope;
+mod indexed_db;
+#[cfg(test)]
+mod memory;
+mod records;
+mod usage;
+mod vault;
+
+use records::Record;
+
+pub use backup::{export_backup, import_backup};
+pub use broadcast::{subscribe, LibraryChange};
+pub use damaged::{parse_key, DamagedRecord, LibraryScan};
+pub use indexed_db::Storage;
+#[cfg(test)]
+pub use memory::MemoryStore;
+pub use usage::{format_bytes, StorageUsage};
+pub use vault::{
+    change_passphrase, disable_vault, enable_vault, is_locked, lock, subscribe_vault_changes,
+    unlock, vault_enabled, MIN_PASSPHRASE_CHARS,
+};
+
+/// Whole-document records written before version 4; moved to the metadata and
+/// content stores the first time the database is opened.
+const LEGACY_DOCUMENTS_STORE: &str = "documents";
+const METADATA_STORE: &str = "metadata";
+const CONTENT_STORE: &str = "content";
+const PROGRESS_STORE: &str = "progress";
+const ANNOTATIONS_STORE: &str = "annotations";
+const SETTINGS_KEY: &str = "nabu_settings";
+const LIBRARY_VIEW_KEY: &str = "nabu_library_view";
+/// The data key when no vault is set up.
+const KEY_STORAGE_KEY: &str = "nabu_key";
+/// Newest record envelope every stored record has been rewritten to.
+const RECORD_FORMAT_KEY: &str = "nabu_record_format";
 
 #[derive(Error, Debug)]
 pub enum StorageError {
@@ -20,6 +60,16 @@ pub enum StorageError {
     Serialization(String),
     #[error("Encryption error: {0}")]
     Encryption(String),
+    #[error("localStorage error: {0}")]
+    LocalStorage(String),
+    #[error("The vault is locked")]
+    Locked,
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("The vault is not turned on")]
+    VaultDisabled,
+    #[error("{0}")]
+    Backup(String),
 }
 
 impl From<JsValue> for StorageError {
@@ -34,172 +84,266 @@ impl From<DomException> for StorageError {
     }
 }
 
-pub struct Storage {
-    key: [u8; 32],
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
 }
 
-impl Storage {
-    pub fn new() -> Self {
-        let key = Self::get_or_create_key();
-        Self { key }
-    }
+fn load_local<T: DeserializeOwned + Default>(key: &str) -> T {
+    local_storage()
+        .and_then(|storage| storage.get_item(key).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_local<T: Serialize>(key: &str, value: &T) -> Result<(), StorageError> {
+    let storage = local_storage()
+        .ok_or_else(|| StorageError::LocalStorage("localStorage is unavailable".into()))?;
+    let json =
+        serde_json::to_string(value).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    storage
+        .set_item(key, &json)
+        .map_err(|e| StorageError::LocalStorage(format!("{:?}", e)))
+}
+
+/// Annotation keys start with the document id so one key range covers a
+/// document's annotations without decrypting them.
+fn annotation_key(annotation: &Annotation) -> String {
+    format!("{}/{}", annotation.document_id, annotation.id)
+}
+
+/// AES-256-GCM with a random 12-byte nonce, returned in front of the ciphertext.
+fn encrypt_with(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, StorageError> {
+    encrypt_with_aad(key, data, &[])
+}
+
+/// Like [`encrypt_with`], also authenticating `aad`, which is not stored.
+fn encrypt_with_aad(key: &[u8; 32], data: &[u8], aad: &[u8]) -> Result<Vec<u8>, StorageError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+
+    let mut nonce_bytes = [0u8; 12];
+    getrandom::getrandom(&mut nonce_bytes).map_err(|e| StorageError::Encryption(e.to_string()))?;
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, Payload { msg: data, aad })
+        .map_err(|e| StorageError::Encryption(e.to_string()))?;
 
-    fn generate_key() -> [u8; 32] {
-        let mut key = [0u8; 32];
-        getrandom::getrandom(&mut key)
-            .expect("browser cryptographic random source is required for document encryption");
-        key
+    let mut result = nonce_bytes.to_vec();
+    result.extend(ciphertext);
+    Ok(result)
+}
+
+/// Reverses [`encrypt_with`]; fails if the key is wrong or the data was changed.
+fn decrypt_with(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, StorageError> {
+    decrypt_with_aad(key, data, &[])
+}
+
+/// Reverses [`encrypt_with_aad`]; also fails if `aad` differs.
+fn decrypt_with_aad(key: &[u8; 32], data: &[u8], aad: &[u8]) -> Result<Vec<u8>, StorageError> {
+    if data.len() < 12 {
+        return Err(StorageError::Encryption("Data too short".into()));
     }
 
-    fn get_or_create_key() -> [u8; 32] {
-        if let Some(window) = web_sys::window() {
-            if let Ok(Some(storage)) = window.local_storage() {
-                if let Ok(Some(key_b64)) = storage.get_item("nabu_key") {
-                    if let Ok(key_bytes) = BASE64.decode(&key_b64) {
-                        if key_bytes.len() == 32 {
-                            let mut key = [0u8; 32];
-                            key.copy_from_slice(&key_bytes);
-                            return key;
-                        }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Nonce::from_slice(&data[..12]);
+    let ciphertext = &data[12..];
+
+    cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| {
+            StorageError::Encryption("the key does not match or the data was changed".into())
+        })
+}
+
+fn generate_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    getrandom::getrandom(&mut key)
+        .expect("browser cryptographic random source is required for document encryption");
+    key
+}
+
+fn get_or_create_key() -> [u8; 32] {
+    if let Some(window) = web_sys::window() {
+        if let Ok(Some(storage)) = window.local_storage() {
+            if let Ok(Some(key_b64)) = storage.get_item(KEY_STORAGE_KEY) {
+                if let Ok(key_bytes) = BASE64.decode(&key_b64) {
+                    if key_bytes.len() == 32 {
+                        let mut key = [0u8; 32];
+                        key.copy_from_slice(&key_bytes);
+                        return key;
                     }
                 }
-
-                let key = Self::generate_key();
-                let key_b64 = BASE64.encode(key);
-                let _ = storage.set_item("nabu_key", &key_b64);
-                return key;
             }
+
+            let key = generate_key();
+            let key_b64 = BASE64.encode(key);
+            let _ = storage.set_item(KEY_STORAGE_KEY, &key_b64);
+            return key;
         }
+    }
 
-        Self::generate_key()
+    generate_key()
+}
+
+/// The data key: the vault's session key, or the one in `localStorage` when
+/// there is no vault.
+fn current_key() -> Result<[u8; 32], StorageError> {
+    if vault_enabled() {
+        vault::session_key().ok_or(StorageError::Locked)
+    } else {
+        Ok(get_or_create_key())
     }
+}
 
-    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
-        let key = Key::<Aes256Gcm>::from_slice(&self.key);
-        let cipher = Aes256Gcm::new(key);
+/// `value` as a versioned record, encrypted for `id` in `store`.
+fn encode_record<T: Record>(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    value: &T,
+) -> Result<Vec<u8>, StorageError> {
+    envelope::seal(key, store, id, &records::to_record_json(value)?)
+}
 
-        let mut nonce_bytes = [0u8; 12];
-        getrandom::getrandom(&mut nonce_bytes)
-            .map_err(|e| StorageError::Encryption(e.to_string()))?;
-        let nonce = Nonce::from_slice(&nonce_bytes);
+/// Reverses [`encode_record`], upgrading records written by older versions.
+fn decode_record<T: Record>(
+    key: &[u8; 32],
+    store: &str,
+    id: &str,
+    data: &[u8],
+) -> Result<T, StorageError> {
+    records::from_record_json(&envelope::open(key, store, id, data)?)
+}
 
-        let ciphertext = cipher
-            .encrypt(nonce, data)
-            .map_err(|e| StorageError::Encryption(e.to_string()))?;
+/// Records written together by [`DocumentStore::save_batch`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordBatch {
+    pub documents: Vec<Document>,
+    pub progress: Vec<ReadingProgress>,
+    pub annotations: Vec<Annotation>,
+    /// Annotations to delete.
+    pub deleted_annotations: Vec<Annotation>,
+}
 
-        let mut result = nonce_bytes.to_vec();
-        result.extend(ciphertext);
-        Ok(result)
+impl RecordBatch {
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+            && self.progress.is_empty()
+            && self.annotations.is_empty()
+            && self.deleted_annotations.is_empty()
     }
+}
 
-    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
-        if data.len() < 12 {
-            return Err(StorageError::Encryption("Data too short".into()));
+/// Every document that could be read with its text, and the ones that could
+/// not.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadDocuments {
+    pub documents: Vec<Document>,
+    /// Titles of the unreadable documents, or their ids where even the
+    /// library listing cannot be read.
+    pub unreadable: Vec<String>,
+}
+
+/// Where documents, reading positions, annotations, and settings are kept.
+/// Components get the store through context, so tests can swap IndexedDB for
+/// an in-memory store.
+#[async_trait(?Send)]
+pub trait DocumentStore {
+    async fn save_document(&self, doc: &Document) -> Result<(), StorageError>;
+
+    async fn get_document(&self, id: &str) -> Result<Option<Document>, StorageError>;
+
+    /// Every document with its text. Documents whose records are missing or
+    /// cannot be decrypted are listed as unreadable rather than dropped.
+    async fn get_all_documents(&self) -> Result<ReadDocuments, StorageError> {
+        let scan = self.scan_library().await?;
+        // Documents with unreadable text are reported below by title.
+        let mut read = ReadDocuments {
+            unreadable: scan
+                .damaged
+                .into_iter()
+                .filter(|record| {
+                    matches!(
+                        record.store.as_str(),
+                        METADATA_STORE | LEGACY_DOCUMENTS_STORE
+                    )
+                })
+                .map(|record| record.key)
+                .collect(),
+            ..ReadDocuments::default()
+        };
+        for meta in scan.documents {
+            match self.get_document(&meta.id).await {
+                Ok(Some(doc)) => read.documents.push(doc),
+                Ok(None) | Err(_) => read.unreadable.push(meta.title),
+            }
         }
+        Ok(read)
+    }
 
-        let key = Key::<Aes256Gcm>::from_slice(&self.key);
-        let cipher = Aes256Gcm::new(key);
+    /// Saves and deletes every record in `batch`, or none of them if one fails.
+    async fn save_batch(&self, batch: &RecordBatch) -> Result<(), StorageError>;
 
-        let nonce = Nonce::from_slice(&data[..12]);
-        let ciphertext = &data[12..];
+    /// Library listing for every document, and every record in any store
+    /// that fails to decode, with the reason. Decrypts all records to check
+    /// them; use [`DocumentStore::get_all_metadata`] for the listing alone.
+    async fn scan_library(&self) -> Result<LibraryScan, StorageError>;
 
-        cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|e| StorageError::Encryption(e.to_string()))
-    }
+    /// Library listing from the metadata store alone, without the records
+    /// that cannot be read.
+    async fn get_all_metadata(&self) -> Result<Vec<DocumentMeta>, StorageError>;
 
-    async fn open_db(&self) -> Result<IdbDatabase, StorageError> {
-        let mut db_req = IdbDatabase::open_u32(DB_NAME, DB_VERSION)?;
+    /// Ids of documents whose text satisfies `matches`.
+    async fn find_documents(
+        &self,
+        matches: &dyn for<'a> Fn(&'a str, &'a DocumentBody) -> bool,
+    ) -> Result<Vec<String>, StorageError>;
 
-        db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
-            let db = evt.db();
+    /// Removes a document with its reading position and annotations.
+    async fn delete_document(&self, id: &str) -> Result<(), StorageError>;
 
-            if !db.object_store_names().any(|n| n == DOCUMENTS_STORE) {
-                db.create_object_store(DOCUMENTS_STORE)?;
-            }
-            Ok(())
-        }));
+    /// Saves `progress` unless the saved position supersedes it, so positions
+    /// written from two tabs settle on the later one.
+    async fn save_progress(&self, progress: &ReadingProgress) -> Result<(), StorageError>;
 
-        db_req
-            .await
-            .map_err(|e| StorageError::IndexedDb(format!("{:?}", e)))
-    }
+    async fn get_progress(&self, id: &str) -> Result<Option<ReadingProgress>, StorageError>;
 
-    pub async fn save_document(&self, doc: &Document) -> Result<(), StorageError> {
-        let db = self.open_db().await?;
-        let tx = db.transaction_on_one_with_mode(DOCUMENTS_STORE, IdbTransactionMode::Readwrite)?;
-        let store = tx.object_store(DOCUMENTS_STORE)?;
+    async fn get_all_progress(&self) -> Result<Vec<ReadingProgress>, StorageError>;
 
-        let json =
-            serde_json::to_vec(doc).map_err(|e| StorageError::Serialization(e.to_string()))?;
-        let encrypted = self.encrypt(&json)?;
-        let b64 = BASE64.encode(&encrypted);
+    async fn save_annotation(&self, annotation: &Annotation) -> Result<(), StorageError>;
 
-        store.put_key_val(&JsValue::from_str(&doc.id), &JsValue::from_str(&b64))?;
-        tx.await.into_result()?;
+    async fn delete_annotation(&self, annotation: &Annotation) -> Result<(), StorageError>;
 
-        Ok(())
-    }
+    /// A document's annotations in reading order.
+    async fn get_annotations(&self, document_id: &str) -> Result<Vec<Annotation>, StorageError>;
 
-    pub async fn get_document(&self, id: &str) -> Result<Option<Document>, StorageError> {
-        let db = self.open_db().await?;
-        let tx = db.transaction_on_one(DOCUMENTS_STORE)?;
-        let store = tx.object_store(DOCUMENTS_STORE)?;
-
-        let result = store.get(&JsValue::from_str(id))?.await?;
-
-        if let Some(js_val) = result {
-            if let Some(b64) = js_val.as_string() {
-                let encrypted = BASE64
-                    .decode(&b64)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                let decrypted = self.decrypt(&encrypted)?;
-                let doc: Document = serde_json::from_slice(&decrypted)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                return Ok(Some(doc));
-            }
-        }
+    async fn get_all_annotations(&self) -> Result<Vec<Annotation>, StorageError>;
 
-        Ok(None)
-    }
+    /// Re-encrypts with the current key every record that only `old_key`
+    /// decrypts, e.g. after the key in `localStorage` was lost and restored
+    /// from a copy. Returns how many records were recovered.
+    async fn recover_with_key(&self, old_key: &[u8; 32]) -> Result<usize, StorageError>;
 
-    pub async fn get_all_documents(&self) -> Result<Vec<Document>, StorageError> {
-        let db = self.open_db().await?;
-        let tx = db.transaction_on_one(DOCUMENTS_STORE)?;
-        let store = tx.object_store(DOCUMENTS_STORE)?;
-
-        let cursor = store.open_cursor()?.await?;
-        let mut documents = Vec::new();
-
-        if let Some(cursor) = cursor {
-            loop {
-                if let Some(b64) = cursor.value().as_string() {
-                    if let Ok(encrypted) = BASE64.decode(&b64) {
-                        if let Ok(decrypted) = self.decrypt(&encrypted) {
-                            if let Ok(doc) = serde_json::from_slice::<Document>(&decrypted) {
-                                documents.push(doc);
-                            }
-                        }
-                    }
-                }
+    /// Deletes a damaged record. A damaged library entry takes the rest of its
+    /// document with it.
+    async fn delete_damaged(&self, record: &DamagedRecord) -> Result<(), StorageError>;
 
-                if !cursor.continue_cursor()?.await? {
-                    break;
-                }
-            }
-        }
+    /// Measures every store. Document text is decrypted one record at a time
+    /// to compare its size with and without compression.
+    async fn storage_usage(&self) -> Result<StorageUsage, StorageError>;
 
-        Ok(documents)
-    }
+    /// Settings are read synchronously, since they are needed at startup.
+    fn load_settings(&self) -> Settings;
 
-    pub async fn delete_document(&self, id: &str) -> Result<(), StorageError> {
-        let db = self.open_db().await?;
-        let tx = db.transaction_on_one_with_mode(DOCUMENTS_STORE, IdbTransactionMode::Readwrite)?;
-        let store = tx.object_store(DOCUMENTS_STORE)?;
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError>;
 
-        store.delete(&JsValue::from_str(id))?;
-        tx.await.into_result()?;
+    fn load_library_view(&self) -> LibraryView;
 
-        Ok(())
-    }
+    fn save_library_view(&self, view: &LibraryView) -> Result<(), StorageError>;
 }
@@ -0,0 +1,167 @@
+use indexed_db_futures::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use wasm_bindgen::JsValue;
+
+use super::{StorageError, ANNOTATIONS_STORE, CONTENT_STORE, METADATA_STORE, PROGRESS_STORE};
+use crate::state::{Annotation, Document, DocumentBody, DocumentMeta, ReadingProgress};
+
+/// Version written into every record. Records written before records carried a
+/// version count as version 1.
+pub(super) const RECORD_VERSION: u32 = 2;
+const VERSION_FIELD: &str = "record_version";
+
+/// Object stores added by each database version. Version 1 only had the legacy
+/// `documents` store, which new databases never create.
+const SCHEMA: [(u32, &[&str]); 3] = [
+    (2, &[PROGRESS_STORE]),
+    (3, &[ANNOTATIONS_STORE]),
+    (4, &[METADATA_STORE, CONTENT_STORE]),
+];
+
+/// Brings the object stores from `old_version` up to date. Runs inside the
+/// upgrade transaction, before any record is read.
+pub(super) fn upgrade_schema(db: &IdbDatabase, old_version: u32) -> Result<(), JsValue> {
+    for (version, stores) in SCHEMA {
+        if old_version >= version {
+            continue;
+        }
+        for store in stores {
+            if !db.object_store_names().any(|name| name == *store) {
+                db.create_object_store(store)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A type stored as an encrypted record.
+pub(super) trait Record: Serialize + DeserializeOwned {
+    /// Rewrites a record from `version` to `version + 1`. Called for each
+    /// version between the stored one and [`RECORD_VERSION`].
+    fn upgrade(_version: u32, _record: &mut Map<String, Value>) {}
+}
+
+/// Version 1 documents may predate heading support.
+fn add_missing_headings(version: u32, record: &mut Map<String, Value>) {
+    if version == 1 {
+        record
+            .entry("headings")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+}
+
+impl Record for Document {
+    fn upgrade(version: u32, record: &mut Map<String, Value>) {
+        add_missing_headings(version, record);
+    }
+}
+
+impl Record for DocumentBody {
+    fn upgrade(version: u32, record: &mut Map<String, Value>) {
+        add_missing_headings(version, record);
+    }
+}
+
+impl Record for DocumentMeta {}
+
+impl Record for ReadingProgress {}
+
+impl Record for Annotation {}
+
+/// JSON for `value`, tagged with the current record version.
+pub(super) fn to_record_json<T: Record>(value: &T) -> Result<Vec<u8>, StorageError> {
+    let mut json =
+        serde_json::to_value(value).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    if let Value::Object(record) = &mut json {
+        record.insert(VERSION_FIELD.into(), RECORD_VERSION.into());
+    }
+    serde_json::to_vec(&json).map_err(|e| StorageError::Serialization(e.to_string()))
+}
+
+/// Reads a record of any version up to [`RECORD_VERSION`], upgrading it first.
+pub(super) fn from_record_json<T: Record>(json: &[u8]) -> Result<T, StorageError> {
+    let mut value: Value =
+        serde_json::from_slice(json).map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+    if let Value::Object(record) = &mut value {
+        let version = match record.remove(VERSION_FIELD) {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| StorageError::Serialization("Invalid record version".into()))?,
+        };
+        if version > RECORD_VERSION {
+            return Err(StorageError::Serialization(format!(
+                "Record version {} is newer than this app supports",
+                version
+            )));
+        }
+        for from in version..RECORD_VERSION {
+            T::upgrade(from, record);
+        }
+    }
+
+    serde_json::from_value(value).map_err(|e| StorageError::Serialization(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_record_json, to_record_json, RECORD_VERSION};
+    use crate::state::{Annotation, AnnotationKind, Document, DocumentBody, ReadingProgress};
+    use crate::storage::StorageError;
+
+    const DOCUMENT_V1: &str = include_str!("../../tests/fixtures/records/document-v1.json");
+    const ANNOTATION_V1: &str = include_str!("../../tests/fixtures/records/annotation-v1.json");
+    const PROGRESS_V1: &str = include_str!("../../tests/fixtures/records/progress-v1.json");
+
+    #[test]
+    fn version_1_documents_gain_empty_headings() {
+        let doc: Document = from_record_json(DOCUMENT_V1.as_bytes()).unwrap();
+        let body: DocumentBody = from_record_json(DOCUMENT_V1.as_bytes()).unwrap();
+
+        assert_eq!(doc.title, "Moby Dick");
+        assert_eq!(doc.content, "Call me Ishmael.\n\nSome years ago.");
+        assert!(doc.headings.is_empty());
+        assert!(doc.tokens().iter().all(|token| !token.heading));
+        assert_eq!(body, doc.body());
+    }
+
+    #[test]
+    fn version_1_annotations_and_progress_still_load() {
+        let annotation: Annotation = from_record_json(ANNOTATION_V1.as_bytes()).unwrap();
+        let progress: ReadingProgress = from_record_json(PROGRESS_V1.as_bytes()).unwrap();
+
+        assert_eq!(annotation.kind, AnnotationKind::Highlight);
+        assert_eq!(annotation.note, "The opening line.");
+        assert_eq!((progress.word_index, progress.word_count), (3, 7));
+    }
+
+    #[test]
+    fn records_round_trip_with_the_current_version() {
+        let annotation = Annotation::new(
+            "doc".into(),
+            AnnotationKind::Bookmark,
+            4,
+            5,
+            "Some years ago.".into(),
+        );
+        let json = to_record_json(&annotation).unwrap();
+
+        assert!(String::from_utf8_lossy(&json)
+            .contains(&format!("\"record_version\":{}", RECORD_VERSION)));
+        assert_eq!(from_record_json::<Annotation>(&json).unwrap(), annotation);
+    }
+
+    #[test]
+    fn records_from_newer_versions_are_rejected() {
+        let newer = br#"{"record_version":99,"document_id":"a","word_index":0,"word_count":1,"updated_at":""}"#;
+
+        assert!(matches!(
+            from_record_json::<ReadingProgress>(newer),
+            Err(StorageError::Serialization(message)) if message.contains("newer")
+        ));
+    }
+}
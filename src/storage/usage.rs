@@ -0,0 +1,89 @@
+/// Records and bytes in one object store.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreUsage {
+    pub store: &'static str,
+    pub records: usize,
+    pub bytes: u64,
+}
+
+/// Space the library takes in the document store.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageUsage {
+    pub stores: Vec<StoreUsage>,
+    /// Readable document text records as serialized, before compression and
+    /// encryption.
+    pub content_bytes: u64,
+    /// The same records as stored.
+    pub stored_content_bytes: u64,
+}
+
+impl StorageUsage {
+    pub fn total_bytes(&self) -> u64 {
+        self.stores.iter().map(|usage| usage.bytes).sum()
+    }
+
+    /// Bytes compression saves on document text.
+    pub fn saved_bytes(&self) -> u64 {
+        self.content_bytes.saturating_sub(self.stored_content_bytes)
+    }
+
+    pub fn saved_percent(&self) -> u64 {
+        (self.saved_bytes() * 100)
+            .checked_div(self.content_bytes)
+            .unwrap_or(0)
+    }
+}
+
+/// `bytes` in B, KB, MB, or GB with one decimal above a kilobyte.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_bytes, StorageUsage, StoreUsage};
+
+    #[test]
+    fn bytes_are_shown_in_the_largest_whole_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024 * 1024), "3072.0 GB");
+    }
+
+    #[test]
+    fn savings_compare_text_with_what_is_stored() {
+        let usage = StorageUsage {
+            stores: vec![
+                StoreUsage {
+                    store: "metadata",
+                    records: 2,
+                    bytes: 400,
+                },
+                StoreUsage {
+                    store: "content",
+                    records: 2,
+                    bytes: 2_500,
+                },
+            ],
+            content_bytes: 10_000,
+            stored_content_bytes: 2_500,
+        };
+
+        assert_eq!(usage.total_bytes(), 2_900);
+        assert_eq!(usage.saved_bytes(), 7_500);
+        assert_eq!(usage.saved_percent(), 75);
+        assert_eq!(StorageUsage::default().saved_percent(), 0);
+    }
+}
@@ -0,0 +1,266 @@
+use std::cell::Cell;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::StorageEvent;
+
+use super::{decrypt_with, encrypt_with, local_storage, save_local, StorageError, KEY_STORAGE_KEY};
+
+const VAULT_KEY: &str = "nabu_vault";
+
+/// Argon2id cost used for new passphrases: 19 MiB, two passes, one lane.
+const MEMORY_KIB: u32 = 19 * 1024;
+const ITERATIONS: u32 = 2;
+const PARALLELISM: u32 = 1;
+/// Highest Argon2id cost accepted from a file, so a crafted one cannot exhaust
+/// memory or hang the tab before the passphrase is checked.
+const MAX_MEMORY_KIB: u32 = 4 * MEMORY_KIB;
+const MAX_ITERATIONS: u32 = 4 * ITERATIONS;
+
+pub const MIN_PASSPHRASE_CHARS: usize = 8;
+
+// The unwrapped data key, held only in memory while the vault is unlocked.
+thread_local! {
+    static SESSION_KEY: Cell<Option<[u8; 32]>> = const { Cell::new(None) };
+}
+
+/// Argon2id salt and cost for turning a passphrase into an AES key. Stored
+/// next to whatever the key encrypts, so the cost can change later.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub(super) struct KdfParams {
+    /// 16 random bytes, base64 encoded.
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    /// Fresh salt with the given cost.
+    pub(super) fn with_cost(
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    ) -> Result<Self, StorageError> {
+        let mut salt = [0u8; 16];
+        getrandom::getrandom(&mut salt).map_err(|e| StorageError::Encryption(e.to_string()))?;
+        Ok(Self {
+            salt: BASE64.encode(salt),
+            memory_kib,
+            iterations,
+            parallelism,
+        })
+    }
+
+    /// Fresh salt with the default cost.
+    pub(super) fn new() -> Result<Self, StorageError> {
+        Self::with_cost(MEMORY_KIB, ITERATIONS, PARALLELISM)
+    }
+
+    /// Whether the cost is one this build would choose or close to it.
+    pub(super) fn within_limits(&self) -> bool {
+        self.memory_kib <= MAX_MEMORY_KIB
+            && self.iterations <= MAX_ITERATIONS
+            && self.parallelism == PARALLELISM
+    }
+
+    pub(super) fn derive_key(&self, passphrase: &str) -> Result<[u8; 32], StorageError> {
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|e| StorageError::Encryption(format!("Damaged salt: {}", e)))?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| StorageError::Encryption(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| StorageError::Encryption(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// The data key wrapped with a key derived from the passphrase. Changing the
+/// passphrase rewraps the same data key, so documents are not re-encrypted.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct VaultRecord {
+    #[serde(flatten)]
+    kdf: KdfParams,
+    /// Nonce followed by the AES-GCM encrypted data key, base64 encoded.
+    wrapped_key: String,
+}
+
+fn wrap_key(
+    data_key: &[u8; 32],
+    passphrase: &str,
+    kdf: KdfParams,
+) -> Result<VaultRecord, StorageError> {
+    let wrapping_key = kdf.derive_key(passphrase)?;
+    let wrapped = encrypt_with(&wrapping_key, data_key)?;
+    Ok(VaultRecord {
+        kdf,
+        wrapped_key: BASE64.encode(wrapped),
+    })
+}
+
+fn unwrap_key(record: &VaultRecord, passphrase: &str) -> Result<[u8; 32], StorageError> {
+    let damaged = |e: String| StorageError::Encryption(format!("Vault record is damaged: {}", e));
+    let wrapped = BASE64
+        .decode(&record.wrapped_key)
+        .map_err(|e| damaged(e.to_string()))?;
+
+    let wrapping_key = record.kdf.derive_key(passphrase)?;
+    // Authentication fails for a wrong passphrase.
+    let key = decrypt_with(&wrapping_key, &wrapped).map_err(|_| StorageError::WrongPassphrase)?;
+    key.try_into()
+        .map_err(|_| damaged("data key has the wrong length".into()))
+}
+
+/// The vault record in `json`, `None` without one, or an error for an entry
+/// that cannot be read. A damaged entry is never taken for no vault, since
+/// that would replace the data key with a new one stored in the clear.
+fn parse_vault(json: Option<&str>) -> Result<Option<VaultRecord>, StorageError> {
+    json.map(|json| {
+        serde_json::from_str(json)
+            .map_err(|e| StorageError::Encryption(format!("Vault record is damaged: {}", e)))
+    })
+    .transpose()
+}
+
+fn load_vault() -> Result<Option<VaultRecord>, StorageError> {
+    let json = local_storage().and_then(|storage| storage.get_item(VAULT_KEY).ok().flatten());
+    parse_vault(json.as_deref())
+}
+
+/// Whether documents are protected by a passphrase instead of a stored key.
+/// A damaged vault counts, so storage stays locked instead of using a new key.
+pub fn vault_enabled() -> bool {
+    !matches!(load_vault(), Ok(None))
+}
+
+pub fn is_locked() -> bool {
+    vault_enabled() && session_key().is_none()
+}
+
+pub(super) fn session_key() -> Option<[u8; 32]> {
+    SESSION_KEY.with(Cell::get)
+}
+
+/// Calls `on_change` when another tab turns the vault on or off or changes
+/// its passphrase. Browsers send `storage` events only to other tabs.
+pub fn subscribe_vault_changes(on_change: impl Fn() + 'static) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let listener = Closure::<dyn Fn(StorageEvent)>::new(move |event: StorageEvent| {
+        // No key means another tab cleared localStorage.
+        if event.key().is_none_or(|key| key == VAULT_KEY) {
+            on_change();
+        }
+    });
+    let _ = window.add_event_listener_with_callback("storage", listener.as_ref().unchecked_ref());
+    // The listener lives as long as the page.
+    listener.forget();
+}
+
+/// Unlocks the vault for this tab. Deriving the key takes about a second.
+pub fn unlock(passphrase: &str) -> Result<(), StorageError> {
+    let record = load_vault()?.ok_or(StorageError::VaultDisabled)?;
+    let key = unwrap_key(&record, passphrase)?;
+    SESSION_KEY.with(|session| session.set(Some(key)));
+    Ok(())
+}
+
+/// Forgets the data key; storage calls fail until the vault is unlocked again.
+pub fn lock() {
+    SESSION_KEY.with(|session| session.set(None));
+}
+
+/// Moves the current data key from `localStorage` into a new vault, so existing
+/// documents stay readable.
+pub fn enable_vault(passphrase: &str) -> Result<(), StorageError> {
+    if vault_enabled() {
+        return Err(StorageError::Encryption("The vault is already on".into()));
+    }
+    let key = super::get_or_create_key();
+    save_local(VAULT_KEY, &wrap_key(&key, passphrase, KdfParams::new()?)?)?;
+    SESSION_KEY.with(|session| session.set(Some(key)));
+    if let Some(storage) = local_storage() {
+        storage
+            .remove_item(KEY_STORAGE_KEY)
+            .map_err(|e| StorageError::LocalStorage(format!("{:?}", e)))?;
+    }
+    Ok(())
+}
+
+/// Rewraps the data key with `new_passphrase` after checking `current`.
+pub fn change_passphrase(current: &str, new_passphrase: &str) -> Result<(), StorageError> {
+    let record = load_vault()?.ok_or(StorageError::VaultDisabled)?;
+    let key = unwrap_key(&record, current)?;
+    save_local(
+        VAULT_KEY,
+        &wrap_key(&key, new_passphrase, KdfParams::new()?)?,
+    )
+}
+
+/// Stores the data key in `localStorage` again and removes the vault.
+pub fn disable_vault(passphrase: &str) -> Result<(), StorageError> {
+    let record = load_vault()?.ok_or(StorageError::VaultDisabled)?;
+    let key = unwrap_key(&record, passphrase)?;
+    let storage = local_storage()
+        .ok_or_else(|| StorageError::LocalStorage("localStorage is unavailable".into()))?;
+    storage
+        .set_item(KEY_STORAGE_KEY, &BASE64.encode(key))
+        .and_then(|_| storage.remove_item(VAULT_KEY))
+        .map_err(|e| StorageError::LocalStorage(format!("{:?}", e)))?;
+    lock();
+    Ok(())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::{parse_vault, unwrap_key, wrap_key, KdfParams, StorageError};
+
+    /// Cheap parameters so tests run quickly.
+    pub(in crate::storage) fn test_kdf() -> KdfParams {
+        KdfParams::with_cost(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn data_key_round_trips_only_with_the_right_passphrase() {
+        let data_key = [7u8; 32];
+        let record = wrap_key(&data_key, "correct horse", test_kdf()).unwrap();
+
+        assert_eq!(unwrap_key(&record, "correct horse").unwrap(), data_key);
+        assert!(matches!(
+            unwrap_key(&record, "wrong horse"),
+            Err(StorageError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn damaged_vault_entries_are_errors_not_missing() {
+        let record = wrap_key(&[3u8; 32], "correct horse", test_kdf()).unwrap();
+        let json = serde_json::to_string(&record).unwrap();
+
+        assert_eq!(parse_vault(None).unwrap(), None);
+        assert_eq!(parse_vault(Some(&json)).unwrap(), Some(record));
+        for damaged in ["{\"salt\": \"AAAA\"", "null", ""] {
+            assert!(matches!(
+                parse_vault(Some(damaged)),
+                Err(StorageError::Encryption(message)) if message.contains("damaged")
+            ));
+        }
+    }
+
+    #[test]
+    fn rewrapping_keeps_the_data_key_with_a_fresh_salt() {
+        let data_key = [42u8; 32];
+        let first = wrap_key(&data_key, "old passphrase", test_kdf()).unwrap();
+        let second = wrap_key(&data_key, "new passphrase", test_kdf()).unwrap();
+
+        assert_ne!(first.kdf, second.kdf);
+        assert_eq!(unwrap_key(&second, "new passphrase").unwrap(), data_key);
+        assert!(unwrap_key(&second, "old passphrase").is_err());
+    }
+}
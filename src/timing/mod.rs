@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+
+use crate::scheduler::frame_duration_ms;
+use crate::tokens::{Token, CLOSING_CHARS};
+
+/// Words shortened by `short_word_reduction`.
+const FUNCTION_WORDS: [&str; 24] = [
+    "a", "an", "and", "as", "at", "be", "but", "by", "for", "if", "in", "is", "it", "nor", "of",
+    "on", "or", "so", "the", "to", "up", "we", "yet", "i",
+];
+
+/// Extra dwell per word, as fractions of the base word duration.
+///
+/// Durations are normalized over the whole document, so these adjust rhythm
+/// without changing the average WPM the user selected.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TimingConfig {
+    pub enabled: bool,
+    pub sentence_end: f64,
+    pub clause_pause: f64,
+    pub paragraph_break: f64,
+    /// Words longer than this many letters get `long_word_per_char` per extra letter.
+    pub long_word_length: usize,
+    pub long_word_per_char: f64,
+    pub number: f64,
+    pub short_word_reduction: f64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sentence_end: 1.0,
+            clause_pause: 0.5,
+            paragraph_break: 1.5,
+            long_word_length: 8,
+            long_word_per_char: 0.1,
+            number: 0.5,
+            short_word_reduction: 0.3,
+        }
+    }
+}
+
+impl TimingConfig {
+    pub fn normalized(self) -> Self {
+        Self {
+            sentence_end: clamp_dwell(self.sentence_end),
+            clause_pause: clamp_dwell(self.clause_pause),
+            paragraph_break: clamp_dwell(self.paragraph_break),
+            long_word_length: self.long_word_length.clamp(1, 40),
+            long_word_per_char: clamp_dwell(self.long_word_per_char),
+            number: clamp_dwell(self.number),
+            short_word_reduction: if self.short_word_reduction.is_finite() {
+                self.short_word_reduction.clamp(0.0, 0.9)
+            } else {
+                0.0
+            },
+            ..self
+        }
+    }
+}
+
+fn clamp_dwell(value: f64) -> f64 {
+    if value.is_finite() {
+        value.clamp(0.0, 5.0)
+    } else {
+        0.0
+    }
+}
+
+/// Relative display time of one word; a plain word weighs 1.0.
+pub fn word_weight(token: &Token, config: &TimingConfig) -> f64 {
+    if !config.enabled {
+        return 1.0;
+    }
+
+    let word = token.text.as_str();
+    let mut weight = 1.0;
+    let mut has_pause = false;
+
+    if token.sentence_end {
+        weight += config.sentence_end;
+        has_pause = true;
+    } else if let Some(',' | ';' | ':' | '.' | '\u{2014}') =
+        word.trim_end_matches(CLOSING_CHARS).chars().last()
+    {
+        // A period that does not end a sentence (`Dr.`) still gets a short pause.
+        weight += config.clause_pause;
+        has_pause = true;
+    }
+
+    if token.paragraph_end {
+        weight += config.paragraph_break;
+        has_pause = true;
+    }
+
+    let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+    if letters > config.long_word_length {
+        weight += (letters - config.long_word_length) as f64 * config.long_word_per_char;
+    }
+
+    if word.chars().any(|c| c.is_ascii_digit()) {
+        weight += config.number;
+    }
+
+    if !has_pause {
+        let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if FUNCTION_WORDS
+            .iter()
+            .any(|function_word| bare.eq_ignore_ascii_case(function_word))
+        {
+            weight *= 1.0 - config.short_word_reduction;
+        }
+    }
+
+    weight
+}
+
+/// Per-chunk durations for one document, scaled so the document's average
+/// word rate matches the selected WPM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingModel {
+    config: TimingConfig,
+    mean_weight: f64,
+}
+
+impl TimingModel {
+    pub fn new(tokens: &[Token], config: TimingConfig) -> Self {
+        let total: f64 = tokens.iter().map(|token| word_weight(token, &config)).sum();
+        let mean_weight = if tokens.is_empty() || total <= 0.0 {
+            1.0
+        } else {
+            total / tokens.len() as f64
+        };
+
+        Self {
+            config,
+            mean_weight,
+        }
+    }
+
+    /// Display time for `chunk`.
+    pub fn chunk_duration_ms(&self, chunk: &[Token], wpm: u32) -> f64 {
+        let weight: f64 = chunk
+            .iter()
+            .map(|token| word_weight(token, &self.config))
+            .sum();
+
+        frame_duration_ms(wpm, 1) * weight / self.mean_weight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{word_weight, TimingConfig, TimingModel};
+    use crate::tokens::{tokenize, Token};
+
+    /// `text` as a single word in the middle of a paragraph.
+    fn word(text: &str) -> Token {
+        tokenize(&format!("{text} next"), &[]).remove(0)
+    }
+
+    #[test]
+    fn punctuation_paragraphs_and_numbers_add_dwell() {
+        let config = TimingConfig::default();
+        let paragraph_end = Token {
+            sentence_end: true,
+            paragraph_end: true,
+            ..word("word")
+        };
+
+        assert_eq!(word_weight(&word("word"), &config), 1.0);
+        assert_eq!(word_weight(&word("end."), &config), 2.0);
+        assert_eq!(word_weight(&word("end?\u{201d}"), &config), 2.0);
+        assert_eq!(word_weight(&word("clause,"), &config), 1.5);
+        assert_eq!(word_weight(&word("Dr."), &config), 1.5);
+        assert_eq!(word_weight(&paragraph_end, &config), 3.5);
+        assert_eq!(word_weight(&word("1984"), &config), 1.5);
+    }
+
+    #[test]
+    fn long_words_grow_and_function_words_shrink() {
+        let config = TimingConfig::default();
+
+        let long = word_weight(&word("institutionalization"), &config);
+        assert!((long - 2.2).abs() < 1e-9);
+        assert!((word_weight(&word("The"), &config) - 0.7).abs() < 1e-9);
+        // Punctuation wins over the short-word reduction.
+        assert_eq!(word_weight(&word("it."), &config), 2.0);
+    }
+
+    #[test]
+    fn disabled_config_gives_uniform_weights() {
+        let config = TimingConfig {
+            enabled: false,
+            ..TimingConfig::default()
+        };
+        let tokens = tokenize("institutionalization.", &[0]);
+
+        assert_eq!(word_weight(&tokens[0], &config), 1.0);
+    }
+
+    #[test]
+    fn average_wpm_is_preserved_across_the_document() {
+        let tokens = tokenize(
+            "Fox Tales\n\nThe quick brown fox, having considered institutionalization, jumped.\n\n\
+             Then it rested for 20 minutes; a fine end!",
+            &[0],
+        );
+        let model = TimingModel::new(&tokens, TimingConfig::default());
+
+        let total: f64 = tokens
+            .iter()
+            .map(|token| model.chunk_duration_ms(std::slice::from_ref(token), 300))
+            .sum();
+
+        let expected = tokens.len() as f64 * 200.0;
+        assert!((total - expected).abs() < 1e-6, "{total} != {expected}");
+    }
+
+    #[test]
+    fn chunk_duration_sums_word_weights() {
+        let single = [word("word")];
+        let plain = TimingModel::new(
+            &single,
+            TimingConfig {
+                enabled: false,
+                ..TimingConfig::default()
+            },
+        );
+        let model = TimingModel::new(&single, TimingConfig::default());
+
+        assert_eq!(
+            plain.chunk_duration_ms(&tokenize("one two three", &[]), 600),
+            300.0
+        );
+        assert!(model.chunk_duration_ms(&tokenize("a long stop.", &[]), 600) > 300.0);
+    }
+
+    #[test]
+    fn normalization_clamps_untrusted_values() {
+        let config = TimingConfig {
+            sentence_end: f64::NAN,
+            clause_pause: -1.0,
+            short_word_reduction: 2.0,
+            long_word_length: 0,
+            ..TimingConfig::default()
+        }
+        .normalized();
+
+        assert_eq!(config.sentence_end, 0.0);
+        assert_eq!(config.clause_pause, 0.0);
+        assert_eq!(config.short_word_reduction, 0.9);
+        assert_eq!(config.long_word_length, 1);
+    }
+}
@@ -0,0 +1,310 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// Characters that can trail punctuation without hiding it, e.g. `end."`.
+pub const CLOSING_CHARS: &[char] = &['"', '\'', ')', ']', '}', '\u{201d}', '\u{2019}', '\u{bb}'];
+
+/// Words ending in a period that usually do not end a sentence.
+const ABBREVIATIONS: [&str; 12] = [
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "e.g", "i.e", "cf",
+];
+
+/// A paragraph or heading as extracted from a file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Block {
+    pub text: String,
+    #[serde(default)]
+    pub heading: bool,
+}
+
+impl Block {
+    pub fn paragraph(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            heading: false,
+        }
+    }
+
+    pub fn heading(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            heading: true,
+        }
+    }
+}
+
+/// One displayed word and the structure around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub text: String,
+    /// Part of a section heading.
+    pub heading: bool,
+    pub sentence_end: bool,
+    /// Last word of a paragraph or heading.
+    pub paragraph_end: bool,
+}
+
+/// Splits plain text into paragraphs at blank lines, collapsing whitespace
+/// inside each paragraph.
+pub fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+
+    for line in text.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join(" "));
+                current.clear();
+            }
+        } else {
+            current.extend(line.split_whitespace());
+        }
+    }
+
+    paragraphs
+}
+
+/// Tokens for stored content, where paragraphs are separated by blank lines and
+/// `headings` lists the indices of paragraphs that are headings.
+pub fn tokenize(content: &str, headings: &[usize]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (index, paragraph) in paragraphs(content).iter().enumerate() {
+        let heading = headings.contains(&index);
+        tokens.extend(paragraph.split_whitespace().map(|word| Token {
+            text: word.to_string(),
+            heading,
+            sentence_end: ends_sentence(word),
+            paragraph_end: false,
+        }));
+
+        // `paragraphs` never yields an empty paragraph.
+        if let Some(last) = tokens.last_mut() {
+            last.sentence_end = true;
+            last.paragraph_end = true;
+        }
+    }
+
+    tokens
+}
+
+fn ends_sentence(word: &str) -> bool {
+    let trimmed = word.trim_end_matches(CLOSING_CHARS);
+    match trimmed.chars().last() {
+        Some('!' | '?' | '\u{2026}') => true,
+        Some('.') => {
+            let stem = trimmed
+                .trim_end_matches('.')
+                .trim_start_matches(|c: char| !c.is_alphanumeric());
+            let is_initial = stem.chars().count() == 1 && stem.chars().all(char::is_uppercase);
+            !is_initial
+                && !ABBREVIATIONS
+                    .iter()
+                    .any(|abbreviation| stem.eq_ignore_ascii_case(abbreviation))
+        }
+        _ => false,
+    }
+}
+
+/// Token range of the sentence containing `index`.
+pub fn sentence_at(tokens: &[Token], index: usize) -> Range<usize> {
+    let index = index.min(tokens.len().saturating_sub(1));
+    let start = tokens[..index]
+        .iter()
+        .rposition(|token| token.sentence_end)
+        .map_or(0, |end| end + 1);
+    let end = tokens[index..]
+        .iter()
+        .position(|token| token.sentence_end)
+        .map_or(tokens.len(), |offset| index + offset + 1);
+    start..end
+}
+
+/// The most recent sentence whose last word is at or before `shown_through`.
+pub fn last_sentence(tokens: &[Token], shown_through: usize) -> Option<Range<usize>> {
+    let last = shown_through.min(tokens.len().checked_sub(1)?);
+    let end = tokens[..=last]
+        .iter()
+        .rposition(|token| token.sentence_end)?;
+    Some(sentence_at(tokens, end))
+}
+
+/// The words in `range`, clamped to the document, joined by single spaces.
+pub fn text_of(tokens: &[Token], range: Range<usize>) -> String {
+    let end = range.end.min(tokens.len());
+    tokens
+        .get(range.start.min(end)..end)
+        .unwrap_or_default()
+        .iter()
+        .map(|token| token.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A heading and the words up to the next heading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub title: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Section {
+    /// Share of the section read when the reader is at `index`, from 0 to 100.
+    pub fn percent_at(&self, index: usize) -> u32 {
+        if index < self.start {
+            return 0;
+        }
+        if index >= self.end || self.end <= self.start {
+            return 100;
+        }
+        ((index - self.start) * 100 / (self.end - self.start)) as u32
+    }
+}
+
+/// Sections in reading order, one per heading.
+pub fn sections(tokens: &[Token]) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut title: Vec<&str> = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        if !token.heading {
+            continue;
+        }
+        if title.is_empty() {
+            if let Some(previous) = sections.last_mut() {
+                previous.end = index;
+            }
+            sections.push(Section {
+                title: String::new(),
+                start: index,
+                end: tokens.len(),
+            });
+        }
+        title.push(&token.text);
+        if token.paragraph_end {
+            if let Some(section) = sections.last_mut() {
+                section.title = title.join(" ");
+            }
+            title.clear();
+        }
+    }
+
+    sections
+}
+
+/// End (exclusive) of the chunk starting at `start`: at most `size` tokens,
+/// stopping after the end of a paragraph or heading.
+pub fn chunk_end(tokens: &[Token], start: usize, size: usize) -> usize {
+    let limit = start.saturating_add(size.max(1)).min(tokens.len());
+    tokens
+        .get(start..limit)
+        .and_then(|chunk| chunk.iter().position(|token| token.paragraph_end))
+        .map_or(limit, |offset| start + offset + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        chunk_end, last_sentence, paragraphs, sections, sentence_at, text_of, tokenize, Section,
+    };
+
+    #[test]
+    fn blank_lines_separate_paragraphs() {
+        assert_eq!(
+            paragraphs("  One\nstill one.\n\n\n  Two  \n \nThree"),
+            vec!["One still one.", "Two", "Three"]
+        );
+        assert!(paragraphs(" \n\n ").is_empty());
+    }
+
+    #[test]
+    fn tokens_mark_sentences_paragraphs_and_headings() {
+        let tokens = tokenize("Chapter One\n\nIt began. Then \"it ended!\" Quietly", &[0]);
+        let marked: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.heading, t.sentence_end, t.paragraph_end))
+            .collect();
+
+        assert_eq!(
+            marked,
+            vec![
+                ("Chapter", true, false, false),
+                ("One", true, true, true),
+                ("It", false, false, false),
+                ("began.", false, true, false),
+                ("Then", false, false, false),
+                ("\"it", false, false, false),
+                ("ended!\"", false, true, false),
+                ("Quietly", false, true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn abbreviations_and_initials_do_not_end_sentences() {
+        let tokens = tokenize("Dr. J. Watson met Mrs. Hudson, e.g. today. Done", &[]);
+        let ends: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.sentence_end)
+            .map(|t| t.text.as_str())
+            .collect();
+
+        assert_eq!(ends, vec!["today.", "Done"]);
+    }
+
+    #[test]
+    fn chunks_stop_at_paragraph_ends() {
+        let tokens = tokenize("one two three\n\nfour five", &[]);
+
+        assert_eq!(chunk_end(&tokens, 0, 2), 2);
+        assert_eq!(chunk_end(&tokens, 0, 5), 3);
+        assert_eq!(chunk_end(&tokens, 3, 5), 5);
+        assert_eq!(chunk_end(&tokens, 5, 5), 5);
+        assert_eq!(chunk_end(&tokens, 1, 0), 2);
+    }
+
+    #[test]
+    fn sections_start_at_headings_and_track_progress() {
+        let tokens = tokenize(
+            "Preface text\n\nPart One\n\na b c d\n\nPart Two\n\ne f",
+            &[1, 3],
+        );
+        let found = sections(&tokens);
+
+        assert_eq!(
+            found,
+            vec![
+                Section {
+                    title: "Part One".into(),
+                    start: 2,
+                    end: 8,
+                },
+                Section {
+                    title: "Part Two".into(),
+                    start: 8,
+                    end: 12,
+                },
+            ]
+        );
+        assert_eq!(found[0].percent_at(0), 0);
+        assert_eq!(found[0].percent_at(5), 50);
+        assert_eq!(found[0].percent_at(9), 100);
+        assert!(sections(&tokenize("no headings", &[])).is_empty());
+    }
+
+    #[test]
+    fn sentences_are_found_around_and_before_a_position() {
+        let tokens = tokenize("One two. Three four five. Six", &[]);
+
+        assert_eq!(sentence_at(&tokens, 3), 2..5);
+        assert_eq!(text_of(&tokens, sentence_at(&tokens, 0)), "One two.");
+        assert_eq!(text_of(&tokens, 5..9), "Six");
+        assert_eq!(last_sentence(&tokens, 4), Some(2..5));
+        assert_eq!(last_sentence(&tokens, 3), Some(0..2));
+        assert_eq!(last_sentence(&tokens, 0), None);
+        assert_eq!(last_sentence(&tokens, 99), Some(5..6));
+        assert_eq!(last_sentence(&[], 0), None);
+    }
+}
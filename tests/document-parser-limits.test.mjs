@@ -3,10 +3,13 @@ import test from "node:test";
 import JSZip from "jszip";
 
 import {
+  addEpubTextCharacters,
   addPdfTextCharacters,
+  assertEpubMimetype,
   assertPdfPageCount,
   DOCUMENT_LIMITS,
   inspectDocxArchive,
+  inspectEpubArchive,
   readZipEntryBounded,
   validateDocumentXml
 } from "../assets/document-parsers/document-limits.js";
@@ -33,6 +36,29 @@ async function makeDocx(documentXml = DOCUMENT_XML, additionalEntries = {}) {
   });
 }
 
+const CONTAINER_XML =
+  '<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>';
+const CHAPTER_XHTML =
+  '<?xml version="1.0"?><!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><body><h1>One</h1><p>Safe text</p></body></html>';
+
+async function makeEpub(
+  additionalEntries = {},
+  mimetypeOptions = { compression: "STORE" }
+) {
+  const zip = new JSZip();
+  zip.file("mimetype", "application/epub+zip", mimetypeOptions);
+  zip.file("META-INF/container.xml", CONTAINER_XML);
+  zip.file("OEBPS/chapter-1.xhtml", CHAPTER_XHTML);
+  for (const [name, content] of Object.entries(additionalEntries)) {
+    zip.file(name, content);
+  }
+  return zip.generateAsync({
+    type: "uint8array",
+    compression: "DEFLATE",
+    compressionOptions: { level: 9 }
+  });
+}
+
 function findCentralDirectoryEntry(bytes, expectedName) {
   const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
   for (let offset = 0; offset <= bytes.byteLength - 46; offset += 1) {
@@ -65,6 +91,17 @@ test("accepts a bounded DOCX and streams its document XML", async () => {
   assert.doesNotThrow(() => validateDocumentXml(DOCUMENT_XML));
 });
 
+test("finds the optional DOCX core properties part", async () => {
+  const coreXml =
+    '<?xml version="1.0"?><cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Report</dc:title></cp:coreProperties>';
+
+  assert.equal(inspectDocxArchive(await makeDocx()).coreXml, null);
+  const inspection = inspectDocxArchive(
+    await makeDocx(DOCUMENT_XML, { "docProps/core.xml": coreXml })
+  );
+  assert.equal(inspection.coreXml.uncompressedSize, coreXml.length);
+});
+
 test("rejects excessive PDF pages and extracted text", () => {
   assert.doesNotThrow(() => assertPdfPageCount(DOCUMENT_LIMITS.maxPdfPages));
   assert.throws(
@@ -174,3 +211,85 @@ test("rejects declarations, excessive elements, and excessive XML depth", () =>
     /nesting depth limit/
   );
 });
+
+test("accepts a bounded EPUB with a stored mimetype", async () => {
+  const archive = await makeEpub();
+  const inspection = inspectEpubArchive(archive);
+
+  assert.ok(inspection.entries.has("META-INF/container.xml"));
+  assert.ok(inspection.entries.has("OEBPS/chapter-1.xhtml"));
+  assert.doesNotThrow(() => assertEpubMimetype("application/epub+zip"));
+  assert.throws(() => assertEpubMimetype("application/zip"), /mimetype/);
+});
+
+test("rejects EPUB archives without a stored mimetype or container", async () => {
+  const compressedMimetype = await makeEpub(
+    {},
+    { compression: "DEFLATE", compressionOptions: { level: 9 } }
+  );
+  assert.throws(
+    () => inspectEpubArchive(compressedMimetype),
+    /invalid mimetype entry/
+  );
+
+  const docx = await makeDocx();
+  assert.throws(
+    () => inspectEpubArchive(docx),
+    /EPUB archive is missing required entry mimetype/
+  );
+});
+
+test("applies DOCX entry count and compression limits to EPUB", async () => {
+  const archive = await makeEpub({
+    "OEBPS/chapter-2.xhtml": CHAPTER_XHTML.replace(
+      "Safe text",
+      "A".repeat(20_000)
+    )
+  });
+
+  assert.throws(
+    () =>
+      inspectEpubArchive(archive, {
+        ...DOCUMENT_LIMITS,
+        maxEntryCount: 3
+      }),
+    /EPUB archive exceeds the 3-entry limit/
+  );
+  assert.throws(
+    () =>
+      inspectEpubArchive(archive, {
+        ...DOCUMENT_LIMITS,
+        maxCompressionRatio: 10
+      }),
+    /EPUB entry OEBPS\/chapter-2.xhtml exceeds the compression ratio limit/
+  );
+});
+
+test("allows a plain XHTML doctype but not an internal subset", () => {
+  const options = { label: "EPUB chapter", allowDoctype: true };
+
+  assert.doesNotThrow(() =>
+    validateDocumentXml(CHAPTER_XHTML, DOCUMENT_LIMITS, options)
+  );
+  assert.throws(
+    () => validateDocumentXml(CHAPTER_XHTML),
+    /declarations and entities/
+  );
+  assert.throws(
+    () =>
+      validateDocumentXml(
+        '<!DOCTYPE html [<!ENTITY bomb "boom">]><html/>',
+        DOCUMENT_LIMITS,
+        options
+      ),
+    /EPUB chapter declarations and entities/
+  );
+});
+
+test("rejects excessive extracted EPUB text", () => {
+  assert.equal(addEpubTextCharacters(10, 20), 30);
+  assert.throws(
+    () => addEpubTextCharacters(DOCUMENT_LIMITS.maxEpubTextCharacters, 1),
+    /EPUB exceeds the extracted text limit/
+  );
+});